@@ -3,7 +3,7 @@ use crate::device::{self, DeviceId};
 use embassy_sync::channel::Channel;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::TrySendError};
 use embassy_time::{with_timeout, Duration};
-use embedded_services::{debug, error, info, intrusive_list, trace, warn, IntrusiveList};
+use embedded_services::{debug, error, info, intrusive_list, trace, warn, TypedIntrusiveList};
 
 use core::cell::RefCell;
 use core::ops::DerefMut;
@@ -77,6 +77,7 @@ pub enum ContextError {
     DeviceNotFound,
     Timeout,
     StateError(StateMachineError),
+    ServiceUnavailable,
 }
 
 /// External battery service context response.
@@ -92,7 +93,7 @@ pub struct BatteryEvent {
 
 /// Battery service context, hardware agnostic state.
 pub struct Context {
-    fuel_gauges: IntrusiveList,
+    fuel_gauges: TypedIntrusiveList<Device>,
     state: RefCell<State>,
     battery_event: Channel<NoopRawMutex, BatteryEvent, 1>,
     battery_response: Channel<NoopRawMutex, BatteryResponse, 1>,
@@ -103,7 +104,7 @@ impl Context {
     /// Create a new context instance.
     pub fn new() -> Self {
         Self {
-            fuel_gauges: IntrusiveList::new(),
+            fuel_gauges: TypedIntrusiveList::new(),
             state: RefCell::new(State::NotPresent),
             battery_event: Channel::new(),
             battery_response: Channel::new(),
@@ -269,16 +270,7 @@ impl Context {
     }
 
     fn get_fuel_gauge(&self, id: DeviceId) -> Option<&'static Device> {
-        for device in &self.fuel_gauges {
-            if let Some(data) = device.data::<Device>() {
-                if data.id() == id {
-                    return Some(data);
-                }
-            } else {
-                error!("Non-device located in devices list");
-            }
-        }
-        None
+        self.fuel_gauges.get(id)
     }
 
     /// Register fuel gauge device with the context instance.
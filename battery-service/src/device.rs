@@ -3,7 +3,8 @@ use core::cell::Cell;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_time::Duration;
-use embedded_services::{Node, NodeContainer};
+use embedded_services::ec_type::units::{DeciKelvin, MilliAmps, MilliVolts, MilliWattHours, MilliWatts};
+use embedded_services::{Keyed, Node, NodeContainer};
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -46,11 +47,11 @@ pub struct StaticBatteryMsgs {
     /// Device Chemistry.
     pub device_chemistry: [u8; 5],
 
-    /// Design Capacity in mWh.
-    pub design_capacity_mwh: u32,
+    /// Design Capacity.
+    pub design_capacity_mwh: MilliWattHours,
 
-    /// Design Voltage in mV.
-    pub design_voltage_mv: u16,
+    /// Design Voltage.
+    pub design_voltage_mv: MilliVolts,
 
     /// Device Chemistry Id.
     pub device_chemistry_id: [u8; 2],
@@ -63,17 +64,17 @@ pub struct StaticBatteryMsgs {
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DynamicBatteryMsgs {
-    /// Battery Max Power in mW.
-    pub max_power_mw: u32,
+    /// Battery Max Power.
+    pub max_power_mw: MilliWatts,
 
-    /// Battery Sustained Power in mW.
-    pub sus_power_mw: u32,
+    /// Battery Sustained Power.
+    pub sus_power_mw: MilliWatts,
 
-    /// Full Charge Capacity in mWh.
-    pub full_charge_capacity_mwh: u32,
+    /// Full Charge Capacity.
+    pub full_charge_capacity_mwh: MilliWattHours,
 
-    /// Remaining Capacity in mWh.
-    pub remaining_capacity_mwh: u32,
+    /// Remaining Capacity.
+    pub remaining_capacity_mwh: MilliWattHours,
 
     /// Rsoc in %.
     pub relative_soc_pct: u16,
@@ -81,8 +82,8 @@ pub struct DynamicBatteryMsgs {
     /// Charge/Discharge Cycle Count.
     pub cycle_count: u16,
 
-    /// Battery Voltage in mV.
-    pub voltage_mv: u16,
+    /// Battery Voltage.
+    pub voltage_mv: MilliVolts,
 
     /// Maximum Error in %.
     pub max_error_pct: u16,
@@ -90,20 +91,34 @@ pub struct DynamicBatteryMsgs {
     /// Battery Status (Standard Smart Battery Defined).
     pub battery_status: u16,
 
-    /// Desired Charging Voltage in mV.
-    pub charging_voltage_mv: u16,
+    /// Desired Charging Voltage.
+    pub charging_voltage_mv: MilliVolts,
 
-    /// Desired Charging Current in mA.
-    pub charging_current_ma: u16,
+    /// Desired Charging Current.
+    pub charging_current_ma: MilliAmps,
 
-    /// Battery Temperature in dK.
-    pub battery_temp_dk: u16,
+    /// Battery Temperature.
+    pub battery_temp_dk: DeciKelvin,
 
-    /// Battery Current in mA.
-    pub current_ma: i16,
+    /// Battery Current, negative when discharging.
+    pub current_ma: MilliAmps,
 
     /// Battery Avg Current.
-    pub average_current_ma: i16,
+    pub average_current_ma: MilliAmps,
+}
+
+impl DynamicBatteryMsgs {
+    /// Smart Battery status bit set when the battery is too hot
+    pub const OVER_TEMP_ALARM: u16 = 1 << 12;
+
+    /// Smart Battery status bit set when the battery is too low to keep discharging
+    pub const TERMINATE_DISCHARGE_ALARM: u16 = 1 << 11;
+
+    /// Returns true if the status reports a thermal trip or critically low battery, which should be sent with
+    /// [`embedded_services::comms::Priority::Critical`]
+    pub fn is_critical(&self) -> bool {
+        self.battery_status & (Self::OVER_TEMP_ALARM | Self::TERMINATE_DISCHARGE_ALARM) != 0
+    }
 }
 
 /// Fuel gauge ID
@@ -202,3 +217,11 @@ impl NodeContainer for Device {
         &self.node
     }
 }
+
+impl Keyed for Device {
+    type Key = DeviceId;
+
+    fn key(&self) -> DeviceId {
+        self.id
+    }
+}
@@ -1,12 +1,13 @@
 #![no_std]
 
-use core::{any::Any, convert::Infallible};
+use core::any::Any;
 
 use context::BatteryEvent;
 use embassy_sync::once_lock::OnceLock;
 use embedded_services::{
     comms::{self, EndpointID},
     error, info,
+    init::{self, Stage},
 };
 
 pub mod context;
@@ -42,39 +43,80 @@ impl Default for Service {
     }
 }
 
-impl comms::MailboxDelegate for Service {
-    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
-        if let Some(event) = message.data.get::<BatteryEvent>() {
-            self.context.send_event_no_wait(*event).map_err(|e| match e {
-                embassy_sync::channel::TrySendError::Full(_) => comms::MailboxDelegateError::BufferFull,
-            })?
-        }
-
-        Ok(())
+impl comms::Handler<BatteryEvent> for Service {
+    fn handle(&self, _message: &comms::Message, event: &BatteryEvent) -> Result<(), comms::MailboxDelegateError> {
+        self.context.send_event_no_wait(*event).map_err(|e| match e {
+            embassy_sync::channel::TrySendError::Full(_) => comms::MailboxDelegateError::BufferFull,
+        })
     }
 }
 
+// Host writes to the battery section, forwarded by the eSPI service as `BatteryMessage`, don't drive the state machine
+// yet. They are refused so the eSPI service reports the dropped write.
+embedded_services::impl_mailbox_delegate!(impl for Service { BatteryEvent });
+
 static SERVICE: OnceLock<Service> = OnceLock::new();
 
+/// Battery service readiness, registered once the endpoint is up and hardware ready once a fuel gauge is registered.
+pub static READINESS: init::Service = init::Service::new("battery-service", &[&init::CORE]);
+
+/// Battery service error.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The battery service failed to come up.
+    Unavailable(init::Error),
+    /// Fuel gauge registration failed.
+    Registration(embedded_services::intrusive_list::Error),
+    /// Sending a comms message failed.
+    Send(comms::SendError),
+}
+
+/// Wait for the battery service to be registered.
+async fn service() -> Result<&'static Service, init::Error> {
+    READINESS.wait(Stage::Registered).await?;
+
+    // Registered is only reported after the service is initialized
+    SERVICE.try_get().ok_or(init::Error::Failed(READINESS.name()))
+}
+
 /// Register fuel gauge device with the battery service.
 ///
 /// Must be done before sending the battery service commands so that hardware device is visible
 /// to the battery service.
-pub async fn register_fuel_gauge(
-    device: &'static device::Device,
-) -> Result<(), embedded_services::intrusive_list::Error> {
-    let service = SERVICE.get().await;
+pub async fn register_fuel_gauge(device: &'static device::Device) -> Result<(), Error> {
+    let service = service().await.map_err(Error::Unavailable)?;
 
-    service.context.register_fuel_gauge(device).await?;
+    service
+        .context
+        .register_fuel_gauge(device)
+        .await
+        .map_err(Error::Registration)?;
+
+    READINESS.ready();
 
     Ok(())
 }
 
 /// Use the battery service endpoint to send data to other subsystems and services.
-pub async fn comms_send(endpoint_id: EndpointID, data: &impl Any) -> Result<(), Infallible> {
-    let service = SERVICE.get().await;
+pub async fn comms_send(endpoint_id: EndpointID, data: &impl Any) -> Result<(), Error> {
+    comms_send_with_priority(endpoint_id, comms::Priority::Normal, data).await
+}
 
-    service.endpoint.send(endpoint_id, data).await
+/// Use the battery service endpoint to send data with the given priority, such as [`comms::Priority::Critical`] when
+/// [`device::DynamicBatteryMsgs::is_critical`].
+pub async fn comms_send_with_priority(
+    endpoint_id: EndpointID,
+    priority: comms::Priority,
+    data: &impl Any,
+) -> Result<(), Error> {
+    let service = service().await.map_err(Error::Unavailable)?;
+
+    service
+        .endpoint
+        .send_with_priority(endpoint_id, priority, data)
+        .await
+        .map_err(Error::Send)
 }
 
 /// Send the battery service state machine an event and await a response.
@@ -82,7 +124,7 @@ pub async fn comms_send(endpoint_id: EndpointID, data: &impl Any) -> Result<(),
 /// This is an alternative method of interacting with the battery service (instead of using the comms service),
 /// and is a useful fn if you want to send an event and await a response sequentially.
 pub async fn execute_event(event: BatteryEvent) -> context::BatteryResponse {
-    let service = SERVICE.get().await;
+    let service = service().await.map_err(|_| context::ContextError::ServiceUnavailable)?;
 
     service.context.execute_event(event).await
 }
@@ -91,7 +133,7 @@ pub async fn execute_event(event: BatteryEvent) -> context::BatteryResponse {
 ///
 /// Use this function after sending the battery service a message via the comms system.
 pub async fn wait_for_battery_response() -> context::BatteryResponse {
-    let service = SERVICE.get().await;
+    let service = service().await.map_err(|_| context::ContextError::ServiceUnavailable)?;
 
     service.context.wait_response().await
 }
@@ -101,14 +143,69 @@ pub async fn wait_for_battery_response() -> context::BatteryResponse {
 pub async fn task() {
     info!("Starting battery-service task");
 
+    if READINESS.wait_dependencies(Stage::HardwareReady).await.is_err() {
+        error!("Battery service dependencies failed");
+        READINESS.fail();
+        return;
+    }
+
     let service = SERVICE.get_or_init(Service::default);
+    READINESS.reached(Stage::StaticInit);
 
     if comms::register_endpoint(service, &service.endpoint).await.is_err() {
         error!("Failed to register battery service endpoint");
+        READINESS.fail();
         return;
     }
+    READINESS.reached(Stage::Registered);
 
     loop {
         service.process().await;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use embedded_services::comms::{External, Internal, MailboxDelegateError, SendError};
+    use embedded_services::ec_type::message::{BatteryMessage, Indexed};
+    use embedded_services::ec_type::units::MilliWattHours;
+
+    use super::*;
+
+    #[test]
+    fn test_host_write() {
+        static SERVICE: OnceLock<Service> = OnceLock::new();
+        let service = SERVICE.get_or_init(Service::new);
+
+        embassy_futures::block_on(async {
+            embedded_services::init().await;
+            comms::register_endpoint(service, &service.endpoint).await.unwrap();
+
+            let host = EndpointID::External(External::Host);
+            let battery = EndpointID::Internal(Internal::Battery);
+
+            // host writes routed by the eSPI service aren't handled yet and are refused
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                comms::send(host, battery, &BatteryMessage::RemainCap(MilliWattHours(100))).await
+            );
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                comms::send(
+                    host,
+                    battery,
+                    &Indexed {
+                        index: 1,
+                        message: BatteryMessage::CycleCount(3),
+                    }
+                )
+                .await
+            );
+
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                comms::send(host, battery, &0u32).await
+            );
+        });
+    }
+}
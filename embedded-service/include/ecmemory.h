@@ -0,0 +1,192 @@
+/* Generated from the embedded-services memory map declarations, do not edit */
+#pragma once
+
+#include <stdint.h>
+
+#pragma pack(push, 1)
+
+typedef struct {
+    uint8_t major;
+    uint8_t minor;
+    uint8_t spin;
+    uint8_t res0;
+} Version;
+
+/* Maps from EC_LAYOUT_VERSION onward start with EcHeader and `count` EcDirectoryEntry, little-endian */
+static const Version EC_LAYOUT_VERSION = {0, 2, 0, 0};
+
+typedef struct {
+    Version ver;
+    uint16_t count;
+    uint16_t res0;
+} EcHeader;
+
+/* `count` instances of `length` bytes each, starting `offset` bytes from the start of the map */
+typedef struct {
+    uint16_t id;
+    uint8_t major;
+    uint8_t minor;
+    uint16_t offset;
+    uint16_t length;
+    uint16_t count;
+    uint16_t res0;
+} EcDirectoryEntry;
+
+#define EC_SECTION_CAPABILITIES 1
+#define EC_SECTION_CAPABILITIES_MAJOR 1
+#define EC_SECTION_CAPABILITIES_MINOR 0
+
+typedef struct {
+    uint32_t events;
+    Version fw_version;
+    uint8_t secure_state;
+    uint8_t boot_status;
+    uint8_t fan_mask;
+    uint8_t battery_mask;
+    uint16_t temp_mask;
+    uint16_t key_mask;
+    uint16_t debug_mask;
+    uint16_t res0;
+} Capabilities;
+
+#define EC_SECTION_TIME_ALARM 2
+#define EC_SECTION_TIME_ALARM_MAJOR 1
+#define EC_SECTION_TIME_ALARM_MINOR 0
+
+typedef struct {
+    uint32_t events;
+    uint32_t capability;
+    uint16_t year;
+    uint8_t month;
+    uint8_t day;
+    uint8_t hour;
+    uint8_t minute;
+    uint8_t second;
+    uint8_t valid;
+    uint8_t daylight;
+    uint8_t res1;
+    uint16_t milli;
+    uint16_t time_zone;
+    uint16_t res2;
+    uint32_t alarm_status;
+    uint32_t ac_time_val; /* Seconds */
+    uint32_t dc_time_val; /* Seconds */
+} TimeAlarm;
+
+#define EC_SECTION_BATTERY 3
+#define EC_SECTION_BATTERY_MAJOR 1
+#define EC_SECTION_BATTERY_MINOR 0
+
+typedef struct {
+    uint32_t events;
+    uint32_t status;
+    uint32_t last_full_charge; /* MilliWattHours */
+    uint32_t cycle_count;
+    uint32_t state;
+    uint32_t present_rate; /* MilliWatts */
+    uint32_t remain_cap; /* MilliWattHours */
+    uint32_t present_volt; /* MilliVolts */
+    uint32_t psr_state;
+    uint32_t psr_max_out; /* MilliWatts */
+    uint32_t psr_max_in; /* MilliWatts */
+    uint32_t peak_level;
+    uint32_t peak_power; /* MilliWatts */
+    uint32_t sus_level;
+    uint32_t sus_power; /* MilliWatts */
+    uint32_t peak_thres;
+    uint32_t sus_thres;
+    uint32_t trip_thres; /* MilliWattHours */
+    uint32_t bmc_data;
+    uint32_t bmd_data;
+    uint32_t bmd_flags;
+    uint32_t bmd_count;
+    uint32_t charge_time; /* Seconds */
+    uint32_t run_time; /* Seconds */
+    uint32_t sample_time;
+} Battery;
+
+#define EC_SECTION_THERMAL 4
+#define EC_SECTION_THERMAL_MAJOR 1
+#define EC_SECTION_THERMAL_MINOR 0
+
+typedef struct {
+    uint32_t events;
+    uint32_t cool_mode;
+    uint32_t dba_limit;
+    uint32_t sonne_limit;
+    int32_t ma_limit; /* MilliAmps */
+    uint32_t fan1_on_temp; /* DeciKelvin */
+    uint32_t fan1_ramp_temp; /* DeciKelvin */
+    uint32_t fan1_max_temp; /* DeciKelvin */
+    uint32_t fan1_crt_temp; /* DeciKelvin */
+    uint32_t fan1_hot_temp; /* DeciKelvin */
+    uint32_t fan1_max_rpm; /* Rpm */
+    uint32_t fan1_cur_rpm; /* Rpm */
+    uint32_t tmp1_val; /* DeciKelvin */
+    uint32_t tmp1_timeout;
+    uint32_t tmp1_low; /* DeciKelvin */
+    uint32_t tmp1_high; /* DeciKelvin */
+} Thermal;
+
+#define EC_SECTION_FAN 5
+#define EC_SECTION_FAN_MAJOR 1
+#define EC_SECTION_FAN_MINOR 0
+
+typedef struct {
+    uint32_t on_temp; /* DeciKelvin */
+    uint32_t ramp_temp; /* DeciKelvin */
+    uint32_t max_temp; /* DeciKelvin */
+    uint32_t crt_temp; /* DeciKelvin */
+    uint32_t hot_temp; /* DeciKelvin */
+    uint32_t max_rpm; /* Rpm */
+    uint32_t cur_rpm; /* Rpm */
+} Fan;
+
+#define EC_SECTION_SENSOR 6
+#define EC_SECTION_SENSOR_MAJOR 1
+#define EC_SECTION_SENSOR_MINOR 0
+
+typedef struct {
+    uint32_t val; /* DeciKelvin */
+    uint32_t timeout;
+    uint32_t low; /* DeciKelvin */
+    uint32_t high; /* DeciKelvin */
+} Sensor;
+
+#define EC_SECTION_EVENT_QUEUE 7
+#define EC_SECTION_EVENT_QUEUE_MAJOR 1
+#define EC_SECTION_EVENT_QUEUE_MINOR 0
+
+typedef struct {
+    uint16_t producer;
+    uint16_t consumer;
+} EventQueue;
+
+#define EC_SECTION_EVENT_ENTRY 8
+#define EC_SECTION_EVENT_ENTRY_MAJOR 1
+#define EC_SECTION_EVENT_ENTRY_MINOR 0
+
+typedef struct {
+    uint16_t service;
+    uint16_t event;
+    uint32_t payload;
+} EventEntry;
+
+/* Fixed layout without a directory */
+static const Version EC_MEMMAP_VERSION = {0, 1, 0, 0};
+
+typedef struct {
+    uint16_t service;
+    uint16_t event;
+} Notifications;
+
+typedef struct {
+    Version ver;
+    Capabilities caps;
+    Notifications notif;
+    TimeAlarm alarm;
+    Battery batt;
+    Thermal therm;
+} ECMemory;
+
+#pragma pack(pop)
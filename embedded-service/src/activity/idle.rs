@@ -0,0 +1,366 @@
+//! Inactivity tracking, idle timeouts and aggregated system activity
+//!
+//! Every published [`State`] is recorded with a timestamp per [`Class`]. [`IdleTimeout`]s expire once none of their
+//! classes has been active for the configured duration, and the aggregated [`SystemActivity`] moves from active to
+//! idle to standby based on the most recent activity of any class. Both are evaluated by [`task`], which notifies
+//! subscribers through [`idle_update`](super::ActivitySubscriber::idle_update) and
+//! [`system_activity_update`](super::ActivitySubscriber::system_activity_update).
+use core::cell::{Cell, RefCell};
+
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::once_lock::OnceLock;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use super::{for_each_subscriber, Class, State};
+use crate::error;
+use crate::intrusive_list::{self, Node, NodeContainer, TypedIntrusiveList};
+
+/// Maximum number of activity classes tracked. Disabled classes make room for new classes once full, activity of
+/// further classes is ignored and logged
+pub const MAX_TRACKED_CLASSES: usize = 8;
+
+/// Last recorded activity of a class
+#[derive(Copy, Clone)]
+struct ClassActivity {
+    class: Class,
+    state: State,
+    last_activity: Instant,
+}
+
+static ACTIVITY: Mutex<CriticalSectionRawMutex, RefCell<heapless::Vec<ClassActivity, MAX_TRACKED_CLASSES>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Signaled on every recorded activity so [`task`] can re-evaluate
+static UPDATED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Record a state update for a class
+pub(super) fn record(class: Class, state: State, now: Instant) {
+    ACTIVITY.lock(|activity| {
+        let mut activity = activity.borrow_mut();
+        match activity.iter_mut().find(|record| record.class == class) {
+            Some(record) => {
+                // a transition to inactive ends the activity, so it also counts as the last activity
+                if state != State::Disabled {
+                    record.last_activity = now;
+                }
+                record.state = state;
+            }
+            None => {
+                if activity.is_full() {
+                    // disabled classes don't take part in idle decisions, so their records are dropped first
+                    match activity.iter().position(|record| record.state == State::Disabled) {
+                        Some(index) => {
+                            activity.swap_remove(index);
+                        }
+                        None if state == State::Disabled => return,
+                        None => {
+                            error!(
+                                "Activity of {:?} ignored, more than {} classes tracked",
+                                class, MAX_TRACKED_CLASSES
+                            );
+                            return;
+                        }
+                    }
+                }
+
+                // room was made above
+                let _ = activity.push(ClassActivity {
+                    class,
+                    state,
+                    last_activity: now,
+                });
+            }
+        }
+    });
+
+    UPDATED.signal(());
+}
+
+/// Get the time of the last activity of a class, `None` if the class never published a state
+pub fn last_activity(class: Class) -> Option<Instant> {
+    ACTIVITY.lock(|activity| {
+        activity
+            .borrow()
+            .iter()
+            .find(|record| record.class == class)
+            .map(|record| record.last_activity)
+    })
+}
+
+/// Activity summary over a set of classes
+struct Summary {
+    /// At least one class is currently active
+    active: bool,
+    /// Most recent activity, boot if none of the classes had any activity
+    last_activity: Instant,
+}
+
+fn summarize(classes: Option<&[Class]>) -> Summary {
+    ACTIVITY.lock(|activity| {
+        activity
+            .borrow()
+            .iter()
+            .filter(|record| record.state != State::Disabled)
+            .filter(|record| classes.is_none_or(|classes| classes.contains(&record.class)))
+            .fold(
+                Summary {
+                    active: false,
+                    last_activity: Instant::MIN,
+                },
+                |summary, record| Summary {
+                    active: summary.active || record.state == State::Active,
+                    last_activity: summary.last_activity.max(record.last_activity),
+                },
+            )
+    })
+}
+
+/// Idle timeout over a set of activity classes, e.g. no keyboard or trackpad activity for 30 s
+pub struct IdleTimeout {
+    node: Node,
+    classes: &'static [Class],
+    timeout: Duration,
+    idle: Cell<bool>,
+}
+
+impl IdleTimeout {
+    /// Create a new idle timeout, register it with [`register_idle_timeout`]
+    pub const fn new(classes: &'static [Class], timeout: Duration) -> Self {
+        Self {
+            node: Node::uninit(),
+            classes,
+            timeout,
+            idle: Cell::new(false),
+        }
+    }
+
+    /// Classes monitored by this timeout
+    pub fn classes(&self) -> &'static [Class] {
+        self.classes
+    }
+
+    /// Time without activity before this timeout expires
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns true if the timeout has expired and no activity occurred since
+    pub fn is_idle(&self) -> bool {
+        self.idle.get()
+    }
+
+    /// Update the idle state at `now`, returns the state if it changed and the next deadline if not yet idle
+    fn evaluate(&self, now: Instant) -> (Option<bool>, Option<Instant>) {
+        let summary = summarize(Some(self.classes));
+        let deadline = summary.last_activity + self.timeout;
+        let idle = !summary.active && now >= deadline;
+
+        let changed = (idle != self.idle.replace(idle)).then_some(idle);
+        let next = (!summary.active && !idle).then_some(deadline);
+        (changed, next)
+    }
+}
+
+impl NodeContainer for IdleTimeout {
+    fn get_node(&self) -> &Node {
+        &self.node
+    }
+}
+
+static TIMEOUTS: OnceLock<TypedIntrusiveList<IdleTimeout>> = OnceLock::new();
+
+/// Register an idle timeout, subscribers are notified when it expires or is cleared
+pub async fn register_idle_timeout(timeout: &'static IdleTimeout) -> intrusive_list::Result<()> {
+    TIMEOUTS.get().await.push(timeout)?;
+    UPDATED.signal(());
+    Ok(())
+}
+
+/// Aggregated activity of the whole system
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SystemActivity {
+    /// A class is active or was active recently
+    #[default]
+    Active,
+    /// No activity for [`SystemConfig::idle_timeout`], e.g. dim the backlight
+    Idle,
+    /// No activity for [`SystemConfig::standby_timeout`], e.g. enter modern standby
+    Standby,
+}
+
+/// System activity thresholds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemConfig {
+    /// Time without activity before the system is idle
+    pub idle_timeout: Duration,
+    /// Time without activity before the system goes to standby, must be longer than `idle_timeout`
+    pub standby_timeout: Duration,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(30),
+            standby_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+struct System {
+    config: Option<SystemConfig>,
+    activity: SystemActivity,
+}
+
+static SYSTEM: Mutex<CriticalSectionRawMutex, RefCell<System>> = Mutex::new(RefCell::new(System {
+    config: None,
+    activity: SystemActivity::Active,
+}));
+
+/// Set the system activity thresholds, the system stays active until this is called
+pub fn configure_system(config: SystemConfig) {
+    SYSTEM.lock(|system| system.borrow_mut().config = Some(config));
+    UPDATED.signal(());
+}
+
+/// Get the current aggregated system activity
+pub fn system_activity() -> SystemActivity {
+    SYSTEM.lock(|system| system.borrow().activity)
+}
+
+/// Update the system activity at `now`, returns the activity if it changed and the next deadline
+fn evaluate_system(now: Instant) -> (Option<SystemActivity>, Option<Instant>) {
+    let summary = summarize(None);
+
+    SYSTEM.lock(|system| {
+        let mut system = system.borrow_mut();
+        let Some(config) = system.config else {
+            return (None, None);
+        };
+
+        let idle_deadline = summary.last_activity + config.idle_timeout;
+        let standby_deadline = summary.last_activity + config.standby_timeout;
+        let (activity, next) = if summary.active {
+            (SystemActivity::Active, None)
+        } else if now < idle_deadline {
+            (SystemActivity::Active, Some(idle_deadline))
+        } else if now < standby_deadline {
+            (SystemActivity::Idle, Some(standby_deadline))
+        } else {
+            (SystemActivity::Standby, None)
+        };
+
+        let changed = (activity != system.activity).then_some(activity);
+        system.activity = activity;
+        (changed, next)
+    })
+}
+
+/// Evaluate all timeouts at `now` and notify subscribers of changes, returns the next deadline
+fn evaluate(now: Instant) -> Option<Instant> {
+    let mut next: Option<Instant> = None;
+    let mut update_next = |deadline: Option<Instant>| {
+        next = match (next, deadline) {
+            (Some(next), Some(deadline)) => Some(next.min(deadline)),
+            (next, deadline) => next.or(deadline),
+        };
+    };
+
+    if let Some(timeouts) = TIMEOUTS.try_get() {
+        for timeout in timeouts {
+            let (changed, deadline) = timeout.evaluate(now);
+            if let Some(idle) = changed {
+                for_each_subscriber(|subscriber| subscriber.idle_update(timeout, idle));
+            }
+            update_next(deadline);
+        }
+    }
+
+    let (changed, deadline) = evaluate_system(now);
+    if let Some(activity) = changed {
+        for_each_subscriber(|subscriber| subscriber.system_activity_update(activity));
+    }
+    update_next(deadline);
+
+    next
+}
+
+/// Run idle timeouts and system activity tracking
+pub async fn task() -> ! {
+    loop {
+        match evaluate(Instant::now()) {
+            Some(deadline) => {
+                select(Timer::at(deadline), UPDATED.wait()).await;
+            }
+            None => UPDATED.wait().await,
+        }
+    }
+}
+
+pub(super) fn init() {
+    TIMEOUTS.get_or_init(TypedIntrusiveList::new);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_idle_timeouts() {
+        init();
+
+        static KEYBOARD_IDLE: OnceLock<IdleTimeout> = OnceLock::new();
+        let keyboard_idle = KEYBOARD_IDLE
+            .get_or_init(|| IdleTimeout::new(&[Class::Keyboard, Class::Trackpad], Duration::from_secs(30)));
+        embassy_futures::block_on(register_idle_timeout(keyboard_idle)).unwrap();
+        configure_system(SystemConfig {
+            idle_timeout: Duration::from_secs(60),
+            standby_timeout: Duration::from_secs(120),
+        });
+
+        let at = |secs: u64| Instant::from_secs(1000 + secs);
+
+        // active classes never time out
+        record(Class::Keyboard, State::Active, at(0));
+        assert_eq!(evaluate(at(100)), None);
+        assert!(!keyboard_idle.is_idle());
+        assert_eq!(system_activity(), SystemActivity::Active);
+
+        // timeouts count from the end of the activity
+        record(Class::Keyboard, State::Inactive, at(10));
+        assert_eq!(last_activity(Class::Keyboard), Some(at(10)));
+        assert_eq!(evaluate(at(20)), Some(at(40)));
+        assert_eq!(evaluate(at(40)), Some(at(70)));
+        assert!(keyboard_idle.is_idle());
+        assert_eq!(system_activity(), SystemActivity::Active);
+
+        // other classes keep the system active, but don't affect the keyboard timeout
+        record(Class::Oem(1), State::Inactive, at(50));
+        assert_eq!(evaluate(at(70)), Some(at(110)));
+        assert!(keyboard_idle.is_idle());
+        assert_eq!(evaluate(at(110)), Some(at(170)));
+        assert_eq!(system_activity(), SystemActivity::Idle);
+        assert_eq!(evaluate(at(170)), None);
+        assert_eq!(system_activity(), SystemActivity::Standby);
+
+        // new activity clears the timeout, disabled classes are ignored
+        record(Class::Trackpad, State::Inactive, at(200));
+        record(Class::Oem(1), State::Disabled, at(200));
+        assert_eq!(evaluate(at(200)), Some(at(230)));
+        assert!(!keyboard_idle.is_idle());
+        assert_eq!(system_activity(), SystemActivity::Active);
+
+        // once all classes are tracked, disabled classes make room for new ones
+        for key in 2..7 {
+            record(Class::Oem(key), State::Disabled, at(210));
+        }
+        record(Class::Oem(7), State::Active, at(220));
+        assert_eq!(last_activity(Class::Oem(7)), Some(at(220)));
+        assert_eq!(evaluate(at(500)), None);
+        assert_eq!(system_activity(), SystemActivity::Active);
+    }
+}
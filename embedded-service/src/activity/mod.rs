@@ -4,8 +4,14 @@ use embassy_sync::once_lock::OnceLock;
 
 use crate::intrusive_list::*;
 
+mod idle;
+pub use idle::{
+    configure_system, last_activity, register_idle_timeout, system_activity, task, IdleTimeout, SystemActivity,
+    SystemConfig, MAX_TRACKED_CLASSES,
+};
+
 /// potential activity service states
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum State {
     /// the service is currently active
     Active,
@@ -21,7 +27,8 @@ pub enum State {
 pub type OemIdentifier = u32;
 
 /// specifies which Activity Class is updating state
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Class {
     /// the keyboard, if present, is currently active (keys pressed), inactive (keys released), or disabled (key scanning disabled)
     Keyboard,
@@ -48,6 +55,12 @@ pub struct Notification {
 pub trait ActivitySubscriber {
     /// async function invoked when Activity service update occurs
     fn activity_update(&self, notif: &Notification);
+
+    /// invoked when a registered idle timeout expires (`idle` is true) or is cleared by new activity
+    fn idle_update(&self, _timeout: &'static IdleTimeout, _idle: bool) {}
+
+    /// invoked when the aggregated system activity changes
+    fn system_activity_update(&self, _activity: SystemActivity) {}
 }
 
 /// actual subscriber node instance for embedding within static or singleton type T
@@ -76,6 +89,11 @@ impl Subscriber {
             subscriber.activity_update(notif);
         }
     }
+
+    /// returns the subscriber instance, if initialized
+    fn instance(&self) -> Option<&'static dyn ActivitySubscriber> {
+        self.instance.get()
+    }
 }
 
 impl NodeContainer for Subscriber {
@@ -109,6 +127,7 @@ impl Publisher {
     /// publish state update
     pub async fn publish(&self, state: State) {
         let subs = SUBSCRIBERS.get().await;
+        idle::record(self.class, state, embassy_time::Instant::now());
 
         // build publisher-side "queue" of outbound messages
         let notif = Notification {
@@ -134,6 +153,22 @@ impl Publisher {
 
 static SUBSCRIBERS: OnceLock<IntrusiveList> = OnceLock::new();
 
+/// visit every initialized subscriber, used for notifications raised outside of a publisher
+fn for_each_subscriber(mut f: impl FnMut(&'static dyn ActivitySubscriber)) {
+    if let Some(subs) = SUBSCRIBERS.try_get() {
+        for subscriber in subs.iter_only::<Subscriber>() {
+            if let Some(instance) = subscriber.instance() {
+                f(instance);
+            }
+        }
+    }
+}
+
+/// Activity service readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("activity", &[]);
+
 pub(crate) fn init() {
     SUBSCRIBERS.get_or_init(IntrusiveList::new);
+    idle::init();
+    READINESS.ready();
 }
@@ -7,14 +7,26 @@
 //! ownership guarantees. `SharedRef` represents an immutable reference into the buffer. This type can be cloned
 //! and can be created from an `OwnedRef`. `Access` and `AccessMut` are guard types that provide access to the buffer through
 //! references tied to the lifetime of the guard struct. These types enforce Rust's aliasing and mutability rules dynamically,
-//! similar to RefCell.
+//! similar to RefCell. Conflicting borrows panic, the `try_borrow*` variants return a `BorrowError` instead and the
+//! `borrow*_async` variants wait until the conflicting borrows are dropped.
 //!
 //! This allows for producer code to own the buffer through a `OwnedRef`, and then allow access to consumers
 //! through any number of `SharedRef`.
+//!
+//! `BufferPool` provides a fixed number of equally sized blocks, each handed out as an `OwnedRef`. A block returns to
+//! the pool once its `OwnedRef` and every `SharedRef` into it have been dropped, so producers with short-lived data
+//! can share one pool instead of each reserving a worst-case static buffer.
 use core::borrow::{Borrow, BorrowMut};
-use core::cell::Cell;
+use core::cell::{Cell, RefCell};
+use core::future::poll_fn;
 use core::marker::PhantomData;
 use core::ops::Range;
+use core::task::Poll;
+
+use embassy_sync::waitqueue::MultiWakerRegistration;
+
+/// Maximum number of tasks waiting on a single buffer, further waiters cause all waiters to be woken and re-register
+const MAX_WAITERS: usize = 4;
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 enum Status {
@@ -23,10 +35,24 @@ enum Status {
     Immutable(u32),
 }
 
+/// Buffer borrow error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BorrowError {
+    /// The buffer is already borrowed mutably
+    BorrowedMutably,
+    /// The buffer is already borrowed immutably
+    BorrowedImmutably,
+}
+
 /// Underlying buffer storage struct
 pub struct Buffer<'a, T> {
     buffer: *mut [T],
     status: Cell<Status>,
+    /// Number of live `OwnedRef` and `SharedRef` handles
+    handles: Cell<u32>,
+    /// Tasks waiting for a conflicting borrow to be dropped
+    waiters: RefCell<MultiWakerRegistration<MAX_WAITERS>>,
     _lifetime: PhantomData<&'a ()>,
 }
 
@@ -38,6 +64,8 @@ impl<'a, T> Buffer<'a, T> {
         Buffer {
             buffer: raw_buffer,
             status: Cell::new(Status::None),
+            handles: Cell::new(0),
+            waiters: RefCell::new(MultiWakerRegistration::new()),
             _lifetime: PhantomData,
         }
     }
@@ -46,6 +74,7 @@ impl<'a, T> Buffer<'a, T> {
     /// # Safety
     /// Can be used to create mulitple mut references to the buffer
     pub unsafe fn as_owned(&'a self) -> OwnedRef<'a, T> {
+        self.acquire();
         OwnedRef(self)
     }
 
@@ -61,15 +90,52 @@ impl<'a, T> Buffer<'a, T> {
         unsafe { self.buffer.as_mut().unwrap().is_empty() }
     }
 
-    fn borrow(&self, mutable: bool) {
+    fn try_borrow(&self, mutable: bool) -> Result<(), BorrowError> {
         let status = match (self.status.get(), mutable) {
             (Status::None, false) => Status::Immutable(1),
             (Status::None, true) => Status::Mutable,
-            (Status::Mutable, _) => panic!("Buffer already borrowed mutably"),
+            (Status::Mutable, _) => return Err(BorrowError::BorrowedMutably),
             (Status::Immutable(count), false) => Status::Immutable(count + 1),
-            (Status::Immutable(_), true) => panic!("Buffer already borrowed immutably"),
+            (Status::Immutable(_), true) => return Err(BorrowError::BorrowedImmutably),
         };
         self.status.set(status);
+        Ok(())
+    }
+
+    fn borrow(&self, mutable: bool) {
+        match self.try_borrow(mutable) {
+            Ok(()) => (),
+            Err(BorrowError::BorrowedMutably) => panic!("Buffer already borrowed mutably"),
+            Err(BorrowError::BorrowedImmutably) => panic!("Buffer already borrowed immutably"),
+        }
+    }
+
+    /// Wait until the buffer can be borrowed, then borrow it
+    async fn borrow_async(&self, mutable: bool) {
+        poll_fn(|cx| match self.try_borrow(mutable) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => {
+                self.waiters.borrow_mut().register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    fn acquire(&self) {
+        self.handles.set(self.handles.get() + 1);
+    }
+
+    fn release(&self) {
+        match self.handles.get() {
+            0 => panic!("Buffer handle count underflow"),
+            count => self.handles.set(count - 1),
+        }
+    }
+
+    /// Returns true if no handles or borrows to the buffer exist
+    fn is_unused(&self) -> bool {
+        self.handles.get() == 0 && self.status.get() == Status::None
     }
 
     fn drop_borrow(&self) {
@@ -81,6 +147,10 @@ impl<'a, T> Buffer<'a, T> {
             Status::Immutable(count) => Status::Immutable(count - 1),
         };
         self.status.set(status);
+
+        if status == Status::None {
+            self.waiters.borrow_mut().wake();
+        }
     }
 }
 
@@ -105,6 +175,26 @@ impl<'a, T> OwnedRef<'a, T> {
         AccessMut::new(self.0)
     }
 
+    /// Borrows the buffer immutably, returns an error if the buffer is already borrowed mutably
+    pub fn try_borrow(&self) -> Result<Access<'a, T>, BorrowError> {
+        Access::try_new(self.0, 0..self.0.len())
+    }
+
+    /// Borrows the buffer mutably, returns an error if the buffer is already borrowed
+    pub fn try_borrow_mut(&self) -> Result<AccessMut<'a, T>, BorrowError> {
+        AccessMut::try_new(self.0)
+    }
+
+    /// Borrows the buffer immutably, waiting for any mutable borrow to be dropped
+    pub async fn borrow_async(&self) -> Access<'a, T> {
+        Access::new_async(self.0, 0..self.0.len()).await
+    }
+
+    /// Borrows the buffer mutably, waiting for all other borrows to be dropped
+    pub async fn borrow_mut_async(&self) -> AccessMut<'a, T> {
+        AccessMut::new_async(self.0).await
+    }
+
     /// Returns the length of the buffer
     pub fn len(&self) -> usize {
         self.0.len()
@@ -116,6 +206,12 @@ impl<'a, T> OwnedRef<'a, T> {
     }
 }
 
+impl<T> Drop for OwnedRef<'_, T> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
 /// Guard struct for mutable buffer access
 pub struct AccessMut<'a, T>(&'a Buffer<'a, T>);
 
@@ -124,6 +220,16 @@ impl<'a, T> AccessMut<'a, T> {
         buffer.borrow(true);
         Self(buffer)
     }
+
+    fn try_new(buffer: &'a Buffer<'a, T>) -> Result<Self, BorrowError> {
+        buffer.try_borrow(true)?;
+        Ok(Self(buffer))
+    }
+
+    async fn new_async(buffer: &'a Buffer<'a, T>) -> Self {
+        buffer.borrow_async(true).await;
+        Self(buffer)
+    }
 }
 
 // SAFETY: Access to the buffer is dynamically checked
@@ -147,7 +253,6 @@ impl<T> Drop for AccessMut<'_, T> {
 }
 
 /// A immutable reference to a buffer
-#[derive(Clone)]
 pub struct SharedRef<'a, T> {
     buffer: &'a Buffer<'a, T>,
     slice: Range<usize>,
@@ -156,6 +261,7 @@ pub struct SharedRef<'a, T> {
 impl<'a, T> SharedRef<'a, T> {
     /// Creates a new immutable buffer refference
     pub fn new(buffer: &'a Buffer<'a, T>, slice: Range<usize>) -> Self {
+        buffer.acquire();
         Self { buffer, slice }
     }
 
@@ -165,6 +271,16 @@ impl<'a, T> SharedRef<'a, T> {
         Access::new(self.buffer, self.slice.clone())
     }
 
+    /// Borrows the buffer immutably, returns an error if the buffer is already borrowed mutably
+    pub fn try_borrow(&self) -> Result<Access<'a, T>, BorrowError> {
+        Access::try_new(self.buffer, self.slice.clone())
+    }
+
+    /// Borrows the buffer immutably, waiting for any mutable borrow to be dropped
+    pub async fn borrow_async(&self) -> Access<'a, T> {
+        Access::new_async(self.buffer, self.slice.clone()).await
+    }
+
     /// Produces a new slice into the buffer
     pub fn slice(&self, range: Range<usize>) -> SharedRef<'a, T> {
         if range.start >= self.slice.len() || range.end > self.slice.len() {
@@ -187,6 +303,18 @@ impl<'a, T> SharedRef<'a, T> {
     }
 }
 
+impl<T> Clone for SharedRef<'_, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.buffer, self.slice.clone())
+    }
+}
+
+impl<T> Drop for SharedRef<'_, T> {
+    fn drop(&mut self) {
+        self.buffer.release();
+    }
+}
+
 /// Guard struct for immutable buffer access
 pub struct Access<'a, T> {
     buffer: &'a Buffer<'a, T>,
@@ -198,6 +326,16 @@ impl<'a, T> Access<'a, T> {
         buffer.borrow(false);
         Self { buffer, slice }
     }
+
+    fn try_new(buffer: &'a Buffer<'a, T>, slice: Range<usize>) -> Result<Self, BorrowError> {
+        buffer.try_borrow(false)?;
+        Ok(Self { buffer, slice })
+    }
+
+    async fn new_async(buffer: &'a Buffer<'a, T>, slice: Range<usize>) -> Self {
+        buffer.borrow_async(false).await;
+        Self { buffer, slice }
+    }
 }
 
 // SAFETY: Access to the buffer is dynamically checked
@@ -214,6 +352,51 @@ impl<T> Drop for Access<'_, T> {
     }
 }
 
+/// Fixed-size pool of `N` buffers of `BLOCK` elements each
+pub struct BufferPool<'a, T, const BLOCK: usize, const N: usize> {
+    blocks: [Buffer<'a, T>; N],
+}
+
+impl<'a, T, const BLOCK: usize, const N: usize> BufferPool<'a, T, BLOCK, N> {
+    /// Create a new pool from backing storage
+    /// # Safety
+    /// No other code should have access to the storage
+    pub unsafe fn new(storage: &'a mut [[T; BLOCK]; N]) -> Self {
+        let storage = storage.as_mut_ptr();
+        Self {
+            // SAFETY: each block is a distinct element of the storage array
+            blocks: core::array::from_fn(|i| unsafe { Buffer::new(&mut *storage.add(i)) }),
+        }
+    }
+
+    /// Take an unused block from the pool, returns `None` if every block is in use
+    ///
+    /// The block returns to the pool when the returned `OwnedRef` and all `SharedRef` created from it are dropped.
+    /// Block contents are not cleared between uses
+    pub fn alloc(&'a self) -> Option<OwnedRef<'a, T>> {
+        self.blocks
+            .iter()
+            .find(|block| block.is_unused())
+            // SAFETY: the block has no other handles, so this is the only reference to it
+            .map(|block| unsafe { block.as_owned() })
+    }
+
+    /// Returns the number of unused blocks
+    pub fn available(&self) -> usize {
+        self.blocks.iter().filter(|block| block.is_unused()).count()
+    }
+
+    /// Returns the length of each block
+    pub const fn block_len(&self) -> usize {
+        BLOCK
+    }
+
+    /// Returns the number of blocks in the pool
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
 /// Macro to simplify the defining a static buffer
 #[macro_export]
 macro_rules! define_static_buffer {
@@ -255,6 +438,38 @@ macro_rules! define_static_buffer {
     };
 }
 
+/// Macro to simplify defining a static buffer pool of `$count` blocks, each containing `$contents`
+#[macro_export]
+macro_rules! define_static_buffer_pool {
+    ($name:ident, $type:ty, $contents:expr, $count:expr) => {
+        mod $name {
+            #![allow(dead_code)]
+            use super::*;
+
+            const BLOCK: usize = $contents.len();
+            const COUNT: usize = $count;
+            static POOL: ::embassy_sync::once_lock::OnceLock<$crate::buffer::BufferPool<'static, $type, BLOCK, COUNT>> =
+                ::embassy_sync::once_lock::OnceLock::new();
+            static mut POOL_STORAGE: [[$type; BLOCK]; COUNT] = [$contents; COUNT];
+
+            // SAFETY: The storage is not externally visible and the constructor closure is only called once
+            pub fn get() -> &'static $crate::buffer::BufferPool<'static, $type, BLOCK, COUNT> {
+                POOL.get_or_init(|| unsafe {
+                    $crate::buffer::BufferPool::new(&mut *::core::ptr::addr_of_mut!(POOL_STORAGE))
+                })
+            }
+
+            pub fn alloc() -> ::core::option::Option<$crate::buffer::OwnedRef<'static, $type>> {
+                get().alloc()
+            }
+
+            pub const fn block_len() -> usize {
+                BLOCK
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod test {
     extern crate std;
@@ -357,4 +572,123 @@ mod test {
 
         let _slice = buffer.reference().slice(0..9);
     }
+
+    // Verify blocks return to the pool once every handle is dropped
+    #[test]
+    fn test_pool_alloc() {
+        define_static_buffer_pool!(pool, u8, [0; 4], 2);
+        assert_eq!(pool::get().available(), 2);
+        assert_eq!(pool::block_len(), 4);
+
+        let a = pool::alloc().unwrap();
+        let b = pool::alloc().unwrap();
+        assert_eq!(a.len(), 4);
+        assert!(pool::alloc().is_none());
+
+        {
+            let mut access = a.borrow_mut();
+            let buf: &mut [u8] = access.borrow_mut();
+            buf.copy_from_slice(&[1, 2, 3, 4]);
+        }
+
+        // shared references keep the block alive after the owner is dropped
+        let shared = a.reference().slice(1..3);
+        let cloned = shared.clone();
+        drop(a);
+        assert_eq!(pool::get().available(), 0);
+        drop(shared);
+        assert_eq!(pool::get().available(), 0);
+        assert_eq!(cloned.borrow().borrow(), [2, 3]);
+        drop(cloned);
+        assert_eq!(pool::get().available(), 1);
+
+        // the freed block is handed out again
+        let c = pool::alloc().unwrap();
+        assert_eq!(c.borrow().borrow(), [1, 2, 3, 4]);
+        assert!(pool::alloc().is_none());
+
+        drop(b);
+        drop(c);
+        assert_eq!(pool::get().available(), 2);
+    }
+
+    // Verify a block is not handed out while an access guard into it is alive
+    #[test]
+    fn test_pool_outstanding_access() {
+        define_static_buffer_pool!(pool, u8, [0; 4], 1);
+
+        let a = pool::alloc().unwrap();
+        let access = a.reference().borrow();
+        drop(a);
+        assert!(pool::alloc().is_none());
+
+        drop(access);
+        assert!(pool::alloc().is_some());
+    }
+
+    // Verify fallible borrows report conflicts instead of panicking
+    #[test]
+    fn test_try_borrow() {
+        define_static_buffer!(buffer, u8, [0; 16]);
+        let buffer = buffer::get_mut().unwrap();
+        let shared = buffer.reference();
+
+        let a = shared.try_borrow().unwrap();
+        assert!(buffer.try_borrow().is_ok());
+        assert_eq!(buffer.try_borrow_mut().err(), Some(BorrowError::BorrowedImmutably));
+        drop(a);
+
+        let mut_a = buffer.try_borrow_mut().unwrap();
+        assert_eq!(shared.try_borrow().err(), Some(BorrowError::BorrowedMutably));
+        assert_eq!(buffer.try_borrow_mut().err(), Some(BorrowError::BorrowedMutably));
+        drop(mut_a);
+
+        assert!(buffer.try_borrow_mut().is_ok());
+    }
+
+    struct FlagWaker(std::sync::atomic::AtomicBool);
+
+    impl std::task::Wake for FlagWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    // Verify async borrows wait for conflicting borrows to be dropped
+    #[test]
+    fn test_borrow_async() {
+        use core::future::Future;
+        use core::pin::pin;
+        use core::task::Context;
+        use std::sync::atomic::Ordering;
+        use std::sync::Arc;
+
+        use embassy_futures::poll_once;
+
+        define_static_buffer!(buffer, u8, [0; 16]);
+        let buffer = buffer::get_mut().unwrap();
+        let shared = buffer.reference();
+
+        // a writer waits for all readers
+        let a = shared.borrow();
+        let b = shared.borrow();
+        let mut writer = pin!(buffer.borrow_mut_async());
+        assert!(poll_once(writer.as_mut()).is_pending());
+        drop(a);
+        assert!(poll_once(writer.as_mut()).is_pending());
+        drop(b);
+        let Poll::Ready(mut_a) = poll_once(writer.as_mut()) else {
+            panic!("Writer not ready after readers dropped");
+        };
+
+        // readers wait for the writer, and are woken when it is dropped
+        let flag = Arc::new(FlagWaker(false.into()));
+        let waker = flag.clone().into();
+        let mut reader = pin!(shared.borrow_async());
+        assert!(reader.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+        drop(mut_a);
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(poll_once(reader.as_mut()).is_ready());
+    }
 }
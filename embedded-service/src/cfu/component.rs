@@ -112,6 +112,14 @@ impl intrusive_list::NodeContainer for CfuDevice {
     }
 }
 
+impl intrusive_list::Keyed for CfuDevice {
+    type Key = ComponentId;
+
+    fn key(&self) -> ComponentId {
+        self.component_id
+    }
+}
+
 /// Trait for any container that holds a device
 pub trait CfuDeviceContainer {
     /// Get the underlying device struct
@@ -10,7 +10,7 @@ use embassy_sync::once_lock::OnceLock;
 use embedded_cfu_protocol::protocol_definitions::{CfuProtocolError, ComponentId};
 
 use crate::cfu::component::{CfuDevice, CfuDeviceContainer, InternalResponseData, RequestData, DEVICE_CHANNEL_SIZE};
-use crate::{error, intrusive_list};
+use crate::intrusive_list;
 
 /// Error type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,7 +39,7 @@ pub struct Request {
 /// Cfu context
 struct ClientContext {
     /// Registered devices
-    devices: intrusive_list::IntrusiveList,
+    devices: intrusive_list::TypedIntrusiveList<CfuDevice>,
     /// Request to components
     request: Channel<NoopRawMutex, Request, { DEVICE_CHANNEL_SIZE }>,
     /// Response from components
@@ -49,7 +49,7 @@ struct ClientContext {
 impl ClientContext {
     fn new() -> Self {
         Self {
-            devices: intrusive_list::IntrusiveList::new(),
+            devices: intrusive_list::TypedIntrusiveList::new(),
             request: Channel::new(),
             response: Channel::new(),
         }
@@ -58,34 +58,33 @@ impl ClientContext {
 
 static CONTEXT: OnceLock<ClientContext> = OnceLock::new();
 
+/// Cfu Client service readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("cfu", &[]);
+
 /// Init Cfu Client service
 pub fn init() {
     CONTEXT.get_or_init(ClientContext::new);
+    READINESS.ready();
 }
 
-/// Register a device with the Cfu Client service
+/// Register a device with the Cfu Client service, devices are kept ordered by component ID
 pub async fn register_device(device: &'static impl CfuDeviceContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_cfu_component_device();
     if get_device(device.component_id()).await.is_some() {
         return Err(intrusive_list::Error::NodeAlreadyInList);
     }
 
-    CONTEXT.get().await.devices.push(device)
+    CONTEXT.get().await.devices.insert_sorted(device)
+}
+
+/// Deregister a device from the Cfu Client service
+pub async fn deregister_device(device: &'static impl CfuDeviceContainer) -> Result<(), intrusive_list::Error> {
+    CONTEXT.get().await.devices.remove(device.get_cfu_component_device())
 }
 
 /// Find a device by its ID
 async fn get_device(id: ComponentId) -> Option<&'static CfuDevice> {
-    for device in &CONTEXT.get().await.devices {
-        if let Some(data) = device.data::<CfuDevice>() {
-            if data.component_id() == id {
-                return Some(data);
-            }
-        } else {
-            error!("Non-device located in devices list");
-        }
-    }
-
-    None
+    CONTEXT.get().await.devices.get(id)
 }
 
 /// Convenience function to send a request to the Cfu service
@@ -145,7 +144,7 @@ impl ContextToken {
     }
 
     /// Provides access to the device list
-    pub async fn devices(&self) -> &intrusive_list::IntrusiveList {
+    pub async fn devices(&self) -> &intrusive_list::TypedIntrusiveList<CfuDevice> {
         &CONTEXT.get().await.devices
     }
 }
@@ -0,0 +1,145 @@
+//! Access control for messages from external endpoints
+//!
+//! Once a routing policy is installed with [`set_routing_policy`], a message from an external endpoint is only
+//! delivered to an internal endpoint if a [`Rule`] allows it. Messages between internal endpoints and messages to
+//! external endpoints are not affected. Until a policy is installed every message is delivered.
+use core::any::{Any, TypeId};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_sync::once_lock::OnceLock;
+
+use super::{EndpointID, External, Internal, Message};
+
+/// Message types a [`Rule`] applies to
+#[derive(Copy, Clone, Debug)]
+pub enum MessageFilter {
+    /// Any message type
+    Any,
+    /// A single message type, see [`Rule::allow`]
+    Type(fn() -> TypeId),
+}
+
+/// Allowed (from, to, message type) triple
+#[derive(Copy, Clone, Debug)]
+pub struct Rule {
+    /// External source
+    pub from: External,
+    /// Internal destination
+    pub to: Internal,
+    /// Allowed message types
+    pub message: MessageFilter,
+}
+
+impl Rule {
+    /// Allow messages of type `T` from `from` to `to`
+    pub const fn allow<T: Any>(from: External, to: Internal) -> Self {
+        Self {
+            from,
+            to,
+            message: MessageFilter::Type(TypeId::of::<T>),
+        }
+    }
+
+    /// Allow messages of any type from `from` to `to`
+    pub const fn allow_any(from: External, to: Internal) -> Self {
+        Self {
+            from,
+            to,
+            message: MessageFilter::Any,
+        }
+    }
+
+    fn matches(&self, from: External, to: Internal, type_id: TypeId) -> bool {
+        self.from == from
+            && self.to == to
+            && match self.message {
+                MessageFilter::Any => true,
+                MessageFilter::Type(type_id_of) => type_id_of() == type_id,
+            }
+    }
+}
+
+/// Routing policy error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PolicyError {
+    /// A routing policy is already installed
+    AlreadySet,
+}
+
+static ROUTING_POLICY: OnceLock<&'static [Rule]> = OnceLock::new();
+static VIOLATIONS: AtomicU32 = AtomicU32::new(0);
+
+/// Install the routing policy for external endpoints, the policy cannot be changed afterwards
+pub fn set_routing_policy(rules: &'static [Rule]) -> Result<(), PolicyError> {
+    ROUTING_POLICY.init(rules).map_err(|_| PolicyError::AlreadySet)
+}
+
+/// Number of messages rejected by the routing policy
+pub fn policy_violations() -> u32 {
+    VIOLATIONS.load(Ordering::Relaxed)
+}
+
+/// Returns true if `rules` allow delivery of `message`
+fn allowed(rules: &[Rule], message: &Message) -> bool {
+    match (message.from, message.to) {
+        (EndpointID::External(from), EndpointID::Internal(to)) => {
+            rules.iter().any(|rule| rule.matches(from, to, message.data.type_id()))
+        }
+        _ => true,
+    }
+}
+
+/// Check `message` against the installed routing policy, violations are counted
+pub(super) fn check(message: &Message) -> bool {
+    match ROUTING_POLICY.try_get() {
+        Some(rules) if !allowed(rules, message) => {
+            VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::comms::{Data, Priority};
+
+    #[test]
+    fn test_allowed() {
+        const RULES: &[Rule] = &[
+            Rule::allow::<u32>(External::Host, Internal::Battery),
+            Rule::allow_any(External::Debug, Internal::Thermal),
+        ];
+
+        let check = |from: EndpointID, to: EndpointID, data: &dyn Any| {
+            allowed(
+                RULES,
+                &Message {
+                    from,
+                    to,
+                    priority: Priority::Normal,
+                    data: Data {
+                        contents: data,
+                        response: None,
+                    },
+                },
+            )
+        };
+
+        // only the allowed type reaches the allowed destination
+        assert!(check(External::Host.into(), Internal::Battery.into(), &0u32));
+        assert!(!check(External::Host.into(), Internal::Battery.into(), &0u8));
+        assert!(!check(External::Host.into(), Internal::Security.into(), &0u32));
+
+        // any type is allowed by a wildcard rule, but only for its source
+        assert!(check(External::Debug.into(), Internal::Thermal.into(), &0u8));
+        assert!(!check(External::Host.into(), Internal::Thermal.into(), &0u8));
+        assert!(!check(External::Oem(0).into(), Internal::Nonvol.into(), &0u8));
+
+        // internal sources and external destinations are not restricted
+        assert!(check(Internal::Power.into(), Internal::Security.into(), &0u8));
+        assert!(check(Internal::Battery.into(), External::Host.into(), &0u8));
+    }
+}
@@ -0,0 +1,318 @@
+//! Serialized comms bridge to an external target over a byte transport
+//!
+//! Messages routed to the bridge endpoint are serialized with postcard and written as frames:
+//!
+//! | SOF (0xEC) | length: u16 LE | tag: u16 LE | from, to, priority: postcard | payload: postcard | CRC: u16 LE |
+//!
+//! `length` covers the tag, header and payload. The CRC is CRC-16/CCITT-FALSE over the length, tag, header and
+//! payload. Inbound frames use the same layout and are routed to the internal endpoint given in `to`. Outbound
+//! frames are written in priority order.
+use core::any::{Any, TypeId};
+use core::future::Future;
+use core::marker::PhantomData;
+use core::sync::atomic::AtomicU32;
+
+use embassy_futures::join::join;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::priority_channel::{Max, PriorityChannel};
+use embedded_io_async::{Read, Write};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::mailbox::Queued;
+use super::{
+    send_with_priority, Data, Endpoint, EndpointID, External, MailboxDelegate, MailboxDelegateError, Message, Priority,
+    SendError,
+};
+use crate::error;
+
+/// Start of frame marker
+pub const SOF: u8 = 0xEC;
+
+/// Maximum size of a frame, including framing overhead
+pub const MAX_FRAME_SIZE: usize = 256;
+
+/// Framing overhead: start of frame, length and CRC
+const FRAME_OVERHEAD: usize = 5;
+
+/// Encoded frame
+pub type Frame = heapless::Vec<u8, MAX_FRAME_SIZE>;
+
+/// Bridge error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BridgeError {
+    /// The transport failed or was closed
+    Io,
+    /// The frame does not fit in [`MAX_FRAME_SIZE`]
+    FrameTooLarge,
+    /// The frame is too short to hold a tag and endpoints
+    Malformed,
+    /// The frame CRC does not match its contents
+    Crc,
+    /// The message type is not part of the bridge message set
+    UnknownType,
+    /// The frame tag is not part of the bridge message set
+    UnknownTag(u16),
+    /// The message could not be serialized
+    Encode,
+    /// The frame contents could not be deserialized
+    Decode,
+    /// An inbound frame claimed to come from an endpoint other than the bridge
+    InvalidSource,
+    /// An inbound frame was addressed to an external endpoint
+    InvalidDestination,
+    /// The deserialized message could not be delivered
+    Send(SendError),
+}
+
+/// A message type that can cross the bridge
+pub trait BridgeMessage: Any + Serialize + DeserializeOwned {
+    /// Tag identifying this type on the wire, must be unique within a [`MessageSet`]
+    const TAG: u16;
+}
+
+/// The set of message types a bridge serializes, implemented for tuples of [`BridgeMessage`]
+pub trait MessageSet {
+    /// Returns true if the type is part of the set
+    fn contains(type_id: TypeId) -> bool;
+
+    /// Serialize `data` into `buf` if its type is part of the set, returns the tag and the serialized length
+    fn encode(data: Data<'_>, buf: &mut [u8]) -> Result<(u16, usize), BridgeError>;
+
+    /// Deserialize the payload for `tag` and send it to `to`
+    fn route(
+        tag: u16,
+        payload: &[u8],
+        from: EndpointID,
+        to: EndpointID,
+        priority: Priority,
+    ) -> impl Future<Output = Result<(), BridgeError>>;
+}
+
+macro_rules! impl_message_set {
+    ($($t:ident),+) => {
+        impl<$($t: BridgeMessage),+> MessageSet for ($($t,)+) {
+            fn contains(type_id: TypeId) -> bool {
+                $(type_id == TypeId::of::<$t>())||+
+            }
+
+            fn encode(data: Data<'_>, buf: &mut [u8]) -> Result<(u16, usize), BridgeError> {
+                $(
+                    if let Some(data) = data.get::<$t>() {
+                        let len = postcard::to_slice(data, buf).map_err(|_| BridgeError::Encode)?.len();
+                        return Ok(($t::TAG, len));
+                    }
+                )+
+                Err(BridgeError::UnknownType)
+            }
+
+            async fn route(
+                tag: u16,
+                payload: &[u8],
+                from: EndpointID,
+                to: EndpointID,
+                priority: Priority,
+            ) -> Result<(), BridgeError> {
+                $(
+                    if tag == $t::TAG {
+                        let data: $t = postcard::from_bytes(payload).map_err(|_| BridgeError::Decode)?;
+                        return send_with_priority(from, to, priority, &data).await.map_err(BridgeError::Send);
+                    }
+                )+
+                Err(BridgeError::UnknownTag(tag))
+            }
+        }
+    };
+}
+
+impl_message_set!(A);
+impl_message_set!(A, B);
+impl_message_set!(A, B, C);
+impl_message_set!(A, B, C, D);
+impl_message_set!(A, B, C, D, E);
+impl_message_set!(A, B, C, D, E, F);
+impl_message_set!(A, B, C, D, E, F, G);
+impl_message_set!(A, B, C, D, E, F, G, H);
+
+/// CRC-16/CCITT-FALSE
+fn crc16(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+const CRC_INIT: u16 = 0xFFFF;
+
+/// Encode a message into a frame, host tools use this to drive EC endpoints
+pub fn encode_frame<S: MessageSet>(message: &Message) -> Result<Frame, BridgeError> {
+    let mut frame = Frame::new();
+    frame
+        .resize_default(MAX_FRAME_SIZE)
+        .map_err(|_| BridgeError::FrameTooLarge)?;
+
+    let body = &mut frame[5..MAX_FRAME_SIZE - 2];
+    let header_len = postcard::to_slice(&(message.from, message.to, message.priority), &mut body[..])
+        .map_err(|_| BridgeError::FrameTooLarge)?
+        .len();
+    let (tag, payload_len) = S::encode(message.data, &mut body[header_len..])?;
+
+    let len = 2 + header_len + payload_len;
+    frame[0] = SOF;
+    frame[1..3].copy_from_slice(&(len as u16).to_le_bytes());
+    frame[3..5].copy_from_slice(&tag.to_le_bytes());
+    let crc = crc16(CRC_INIT, &frame[1..3 + len]);
+    frame[3 + len..5 + len].copy_from_slice(&crc.to_le_bytes());
+    frame.truncate(len + FRAME_OVERHEAD);
+
+    Ok(frame)
+}
+
+/// Contents of a decoded frame
+pub struct DecodedFrame<'a> {
+    /// Message type tag
+    pub tag: u16,
+    /// Source endpoint
+    pub from: EndpointID,
+    /// Destination endpoint
+    pub to: EndpointID,
+    /// Message priority
+    pub priority: Priority,
+    /// Serialized message payload
+    pub payload: &'a [u8],
+}
+
+/// Read the next frame from `rx` into `buf`, bytes before the start of frame marker are skipped
+pub async fn read_frame<'a, R: Read>(
+    rx: &mut R,
+    buf: &'a mut [u8; MAX_FRAME_SIZE],
+) -> Result<DecodedFrame<'a>, BridgeError> {
+    loop {
+        rx.read_exact(&mut buf[..1]).await.map_err(|_| BridgeError::Io)?;
+        if buf[0] == SOF {
+            break;
+        }
+    }
+
+    rx.read_exact(&mut buf[1..3]).await.map_err(|_| BridgeError::Io)?;
+    let len = u16::from_le_bytes([buf[1], buf[2]]) as usize;
+    if len < 2 {
+        return Err(BridgeError::Malformed);
+    }
+    if len + FRAME_OVERHEAD > MAX_FRAME_SIZE {
+        return Err(BridgeError::FrameTooLarge);
+    }
+
+    rx.read_exact(&mut buf[3..len + FRAME_OVERHEAD])
+        .await
+        .map_err(|_| BridgeError::Io)?;
+    let crc = u16::from_le_bytes([buf[3 + len], buf[4 + len]]);
+    if crc != crc16(CRC_INIT, &buf[1..3 + len]) {
+        return Err(BridgeError::Crc);
+    }
+
+    let tag = u16::from_le_bytes([buf[3], buf[4]]);
+    let ((from, to, priority), payload) =
+        postcard::take_from_bytes::<(EndpointID, EndpointID, Priority)>(&buf[5..3 + len])
+            .map_err(|_| BridgeError::Malformed)?;
+
+    Ok(DecodedFrame {
+        tag,
+        from,
+        to,
+        priority,
+        payload,
+    })
+}
+
+/// Bridge between comms endpoints and an external target.
+///
+/// Register `tp` with [`super::register_endpoint`] under the external endpoint ID the bridge serves, messages of the
+/// types in `S` sent to that ID are queued (up to `N`) and written out by [`Bridge::run`]
+pub struct Bridge<S: MessageSet, const N: usize> {
+    /// Endpoint for the external target
+    pub tp: Endpoint,
+    outbound: PriorityChannel<NoopRawMutex, Queued<Frame>, Max, N>,
+    sequence: AtomicU32,
+    _messages: PhantomData<fn() -> S>,
+}
+
+impl<S: MessageSet, const N: usize> Bridge<S, N> {
+    /// Create a new bridge for the given external endpoint
+    pub fn new(id: External) -> Self {
+        Self {
+            tp: Endpoint::uninit(EndpointID::External(id)),
+            outbound: PriorityChannel::new(),
+            sequence: AtomicU32::new(0),
+            _messages: PhantomData,
+        }
+    }
+
+    /// Read one frame from `rx` and route it to its internal destination
+    pub async fn receive_frame<R: Read>(&self, rx: &mut R) -> Result<(), BridgeError> {
+        let mut buf = [0u8; MAX_FRAME_SIZE];
+        let frame = read_frame(rx, &mut buf).await?;
+
+        if frame.from != self.tp.get_id() {
+            return Err(BridgeError::InvalidSource);
+        }
+        if !matches!(frame.to, EndpointID::Internal(_)) {
+            return Err(BridgeError::InvalidDestination);
+        }
+
+        S::route(frame.tag, frame.payload, frame.from, frame.to, frame.priority).await
+    }
+
+    /// Wait for the next outbound frame and write it to `tx`
+    pub async fn transmit_frame<W: Write>(&self, tx: &mut W) -> Result<(), BridgeError> {
+        let frame = self.outbound.receive().await.item;
+        tx.write_all(&frame).await.map_err(|_| BridgeError::Io)?;
+        tx.flush().await.map_err(|_| BridgeError::Io)
+    }
+
+    /// Run the bridge, `rx` and `tx` are the receive and transmit halves of the transport
+    pub async fn run<R: Read, W: Write>(&self, mut rx: R, mut tx: W) -> ! {
+        join(
+            async {
+                loop {
+                    if let Err(e) = self.receive_frame(&mut rx).await {
+                        error!("Bridge receive error: {:?}", e);
+                    }
+                }
+            },
+            async {
+                loop {
+                    if let Err(e) = self.transmit_frame(&mut tx).await {
+                        error!("Bridge transmit error: {:?}", e);
+                    }
+                }
+            },
+        )
+        .await;
+
+        unreachable!()
+    }
+}
+
+impl<S: MessageSet, const N: usize> MailboxDelegate for Bridge<S, N> {
+    fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+        let frame = encode_frame::<S>(message).map_err(|e| match e {
+            BridgeError::UnknownType => MailboxDelegateError::MessageNotFound,
+            _ => MailboxDelegateError::InvalidData,
+        })?;
+
+        self.outbound
+            .try_send(Queued::new(message.priority, &self.sequence, frame))
+            .map_err(|_| MailboxDelegateError::BufferFull)
+    }
+
+    fn accepts(&self, type_id: TypeId) -> bool {
+        S::contains(type_id)
+    }
+}
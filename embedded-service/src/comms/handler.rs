@@ -0,0 +1,46 @@
+//! Typed message handlers, replacing downcast chains in [`super::MailboxDelegate::receive`]
+use core::any::Any;
+
+use super::{MailboxDelegateError, Message};
+
+/// Handler for one message type accepted by an endpoint delegate.
+///
+/// Implement this once per accepted type, then generate the [`super::MailboxDelegate`] implementation with
+/// [`crate::impl_mailbox_delegate`]
+pub trait Handler<T: Any> {
+    /// Handle a message, `data` is the message contents already downcast to `T`
+    fn handle(&self, message: &Message, data: &T) -> Result<(), MailboxDelegateError>;
+}
+
+/// Implement [`MailboxDelegate`](crate::comms::MailboxDelegate) by dispatching to [`Handler`] implementations.
+///
+/// The generated delegate only accepts the listed message types, sending any other type to the endpoint fails with
+/// [`MailboxDelegateError::MessageNotFound`] before the delegate is called.
+///
+/// ```ignore
+/// impl_mailbox_delegate!(impl for Service { BatteryMessage, ThermalMessage });
+/// impl_mailbox_delegate!(impl[B: Bus] for Host<B> { hid::Message });
+/// ```
+#[macro_export]
+macro_rules! impl_mailbox_delegate {
+    (impl $([$($generics:tt)*])? for $delegate:ty { $($message:ty),+ $(,)? }) => {
+        impl $(<$($generics)*>)? $crate::comms::MailboxDelegate for $delegate {
+            fn receive(
+                &self,
+                message: &$crate::comms::Message,
+            ) -> Result<(), $crate::comms::MailboxDelegateError> {
+                $(
+                    if let Some(data) = message.data.get::<$message>() {
+                        return <Self as $crate::comms::Handler<$message>>::handle(self, message, data);
+                    }
+                )+
+
+                Err($crate::comms::MailboxDelegateError::MessageNotFound)
+            }
+
+            fn accepts(&self, type_id: ::core::any::TypeId) -> bool {
+                $(type_id == ::core::any::TypeId::of::<$message>())||+
+            }
+        }
+    };
+}
@@ -0,0 +1,213 @@
+//! Queued message delivery for endpoints that receive asynchronously
+use core::any::{Any, TypeId};
+use core::cell::RefCell;
+use core::cmp::Ordering;
+use core::future::poll_fn;
+use core::sync::atomic::{self, AtomicU32};
+use core::task::{Context, Poll};
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::WakerRegistration;
+
+use super::{EndpointID, MailboxDelegate, MailboxDelegateError, Message, Priority};
+use crate::warn;
+
+/// Message taken from a [`Mailbox`], owning a copy of the sent data
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OwnedMessage<T> {
+    /// where this message came from
+    pub from: EndpointID,
+
+    /// where this message is going
+    pub to: EndpointID,
+
+    /// message priority
+    pub priority: Priority,
+
+    /// message content
+    pub data: T,
+}
+
+/// Queue entry ordered by priority, then by arrival so messages of equal priority are received in order
+pub(super) struct Queued<T> {
+    priority: Priority,
+    sequence: u32,
+    pub(super) item: T,
+}
+
+impl<T> Queued<T> {
+    pub(super) fn new(priority: Priority, sequence: &AtomicU32, item: T) -> Self {
+        Self {
+            priority,
+            sequence: sequence.fetch_add(1, atomic::Ordering::Relaxed),
+            item,
+        }
+    }
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // earlier messages are greater, compared with wrapping arithmetic so the sequence may overflow
+        let age = (other.sequence.wrapping_sub(self.sequence) as i32).cmp(&0);
+        self.priority.cmp(&other.priority).then(age)
+    }
+}
+
+/// Error receiving from an endpoint mailbox
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReceiveError {
+    /// Endpoint was not registered with a mailbox
+    NoMailbox,
+
+    /// Endpoint mailbox holds a different message type
+    InvalidType,
+}
+
+/// Bounded queue of owned messages, used in place of a [`MailboxDelegate`] by endpoints registered with
+/// [`super::register_queued_endpoint`].
+///
+/// Messages of type `T` are cloned into the queue on the sender's task and received in priority order, messages of
+/// equal priority are received in the order they were sent. Other message types are rejected with
+/// [`MailboxDelegateError::MessageNotFound`], and messages sent to a full queue are dropped and rejected with
+/// [`MailboxDelegateError::BufferFull`] so the sender can apply backpressure. A [`Priority::Critical`] message sent to
+/// a full queue instead replaces the most recent message of the lowest queued priority, it is only rejected if every
+/// queued message is critical.
+pub struct Mailbox<M: RawMutex, T, const N: usize> {
+    queue: Mutex<M, RefCell<Entries<OwnedMessage<T>, N>>>,
+    sequence: AtomicU32,
+}
+
+struct Entries<T, const N: usize> {
+    /// Queued messages, unordered
+    entries: heapless::Vec<Queued<T>, N>,
+    /// Task waiting to receive
+    waker: WakerRegistration,
+}
+
+impl<M: RawMutex, T, const N: usize> Mailbox<M, T, N> {
+    /// Create a new, empty mailbox
+    pub const fn new() -> Self {
+        Self {
+            queue: Mutex::new(RefCell::new(Entries {
+                entries: heapless::Vec::new(),
+                waker: WakerRegistration::new(),
+            })),
+            sequence: AtomicU32::new(0),
+        }
+    }
+
+    /// Number of messages waiting to be received
+    pub fn len(&self) -> usize {
+        self.queue.lock(|queue| queue.borrow().entries.len())
+    }
+
+    /// Returns true if no messages are waiting to be received
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<M: RawMutex, T, const N: usize> Default for Mailbox<M, T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: RawMutex, T: Any + Clone, const N: usize> MailboxDelegate for Mailbox<M, T, N> {
+    fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+        let data = message.data.get::<T>().ok_or(MailboxDelegateError::MessageNotFound)?;
+
+        let message = OwnedMessage {
+            from: message.from,
+            to: message.to,
+            priority: message.priority,
+            data: data.clone(),
+        };
+
+        let queued = Queued::new(message.priority, &self.sequence, message);
+        self.queue.lock(|queue| {
+            let mut queue = queue.borrow_mut();
+            if queue.entries.is_full() {
+                let lowest = queue
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| a.cmp(b))
+                    .filter(|(_, lowest)| lowest.priority < queued.priority && queued.priority == Priority::Critical)
+                    .map(|(index, _)| index)
+                    .ok_or(MailboxDelegateError::BufferFull)?;
+
+                warn!("Mailbox full, dropping a message to make room for a critical message");
+                queue.entries.swap_remove(lowest);
+            }
+
+            // room was made above
+            let _ = queue.entries.push(queued);
+            queue.waker.wake();
+            Ok(())
+        })
+    }
+
+    fn accepts(&self, type_id: TypeId) -> bool {
+        type_id == TypeId::of::<T>()
+    }
+}
+
+/// Type-erased access to a [`Mailbox`] from its endpoint
+pub(super) trait Queue {
+    /// Poll for the next message, `out` must be an `Option<OwnedMessage<T>>` matching the mailbox message type
+    fn poll_receive(&self, cx: &mut Context<'_>, out: &mut dyn Any) -> Poll<Result<(), ReceiveError>>;
+}
+
+impl<M: RawMutex, T: Any, const N: usize> Queue for Mailbox<M, T, N> {
+    fn poll_receive(&self, cx: &mut Context<'_>, out: &mut dyn Any) -> Poll<Result<(), ReceiveError>> {
+        let Some(out) = out.downcast_mut::<Option<OwnedMessage<T>>>() else {
+            return Poll::Ready(Err(ReceiveError::InvalidType));
+        };
+
+        self.queue.lock(|queue| {
+            let mut queue = queue.borrow_mut();
+            let highest = queue
+                .entries
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.cmp(b))
+                .map(|(index, _)| index);
+
+            match highest {
+                Some(index) => {
+                    *out = Some(queue.entries.swap_remove(index).item);
+                    Poll::Ready(Ok(()))
+                }
+                None => {
+                    queue.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+/// Wait for the next message in a type-erased mailbox
+pub(super) async fn receive<T: Any>(queue: &dyn Queue) -> Result<OwnedMessage<T>, ReceiveError> {
+    let mut message = None;
+    poll_fn(|cx| queue.poll_receive(cx, &mut message)).await?;
+    message.ok_or(ReceiveError::InvalidType)
+}
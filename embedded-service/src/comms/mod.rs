@@ -0,0 +1,1267 @@
+//! Comms Service Definitions
+
+use core::any::{Any, TypeId};
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embassy_sync::mutex::Mutex;
+use embassy_sync::once_lock::OnceLock;
+use serde::{Deserialize, Serialize};
+
+use crate::intrusive_list::{self, Node, NodeContainer};
+use crate::IntrusiveList;
+
+mod access;
+pub use access::{policy_violations, set_routing_policy, MessageFilter, PolicyError, Rule};
+pub mod bridge;
+mod handler;
+pub use handler::Handler;
+mod mailbox;
+pub use mailbox::{Mailbox, OwnedMessage, ReceiveError};
+mod rpc;
+pub use rpc::{Request, RequestError, RequestId, Response, DEFAULT_REQUEST_TIMEOUT};
+#[cfg(feature = "comms-tap")]
+pub mod tap;
+mod topic;
+pub use topic::{publish, publish_named, publish_with_priority, subscribe, unsubscribe, Subscription, Topic};
+
+/// key type for OEM Endpoint declarations
+pub type OemKey = isize;
+
+/// Internal endpoints, by generalized name
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Internal {
+    /// platform information service provider
+    PlatformInfo,
+
+    /// keyboard manager
+    Keyboard,
+
+    /// HID service provider
+    Hid,
+
+    /// Host manager and boot control
+    HostBoot,
+
+    /// Power manager for the system
+    Power,
+
+    /// USB-C service provider
+    Usbc,
+
+    /// Thermal service provider
+    Thermal,
+
+    /// Trackpad service provider
+    Trackpad,
+
+    /// Battery service provider
+    Battery,
+
+    /// NVM service provider
+    Nonvol,
+
+    /// Debug service provider
+    Debug,
+
+    /// Security service provider
+    Security,
+
+    /// Time alarm service provider
+    TimeAlarm,
+
+    /// OEM defined receiver
+    Oem(OemKey),
+}
+
+/// External identifier for routing
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum External {
+    /// route a message to the host (typ. SoC with HLOS)
+    Host,
+
+    /// route a message to debug probe or utility
+    Debug,
+
+    /// route a message to an OEM defined target
+    Oem(OemKey),
+}
+
+/// Endpoint identifier for routing
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EndpointID {
+    /// route to/from an internal source
+    Internal(Internal),
+
+    /// route to/from an external source
+    External(External),
+}
+
+impl From<Internal> for EndpointID {
+    fn from(value: Internal) -> Self {
+        EndpointID::Internal(value)
+    }
+}
+
+impl From<External> for EndpointID {
+    fn from(value: External) -> Self {
+        EndpointID::External(value)
+    }
+}
+
+/// Data reference -- generalized such that any stack variable can be transmitted "in place" as needed
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Data<'a> {
+    contents: &'a dyn Any,
+    /// Request answered by the contents, set for responses sent with [`Endpoint::respond`]
+    response: Option<RequestId>,
+}
+
+impl<'a> Data<'a> {
+    /// Construct a Data portion of a Message from some data input
+    pub fn new(from: &'a impl Any) -> Self {
+        Self {
+            contents: from,
+            response: None,
+        }
+    }
+
+    /// Attempt to retrieve data as type T -- None if incorrect type
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.contents.downcast_ref()
+    }
+
+    /// Fetch type ID for message contents to allow reception of multiple top level elements
+    /// Ex:
+    /// match message.data.type_id() {
+    ///     TypeId::of::<MessageClassA>() -> (),
+    ///     TypeId::of::<MessageClassB>() -> (), etc.
+    /// }
+    pub fn type_id(&self) -> TypeId {
+        self.contents.type_id()
+    }
+
+    /// Shorthand if only a few Message types are supported by an Endpoint:
+    /// if data.is_a::<MessageClassA>() {}
+    /// else if data.is_a::<MessageClassB>() {}
+    /// etc.
+    pub fn is_a<T: Any>(&self) -> bool {
+        self.type_id() == TypeId::of::<T>()
+    }
+}
+
+/// Message priority, queued endpoints and bridges service higher priority messages first
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Priority {
+    /// background traffic such as telemetry
+    Low,
+
+    /// default priority
+    #[default]
+    Normal,
+
+    /// latency sensitive traffic such as input events
+    High,
+
+    /// events that must not wait behind other traffic, such as thermal trips or critical battery
+    Critical,
+}
+
+/// Message to receive
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Message<'a> {
+    /// where this message came from
+    pub from: EndpointID,
+
+    /// where this message is going
+    pub to: EndpointID,
+
+    /// message priority
+    pub priority: Priority,
+
+    /// message content
+    pub data: Data<'a>,
+}
+
+/// Trait to receive messages
+pub trait MailboxDelegate {
+    /// Receive a Message (typically, push contents to queue or queue some action)
+    fn receive(&self, _message: &Message) -> Result<(), MailboxDelegateError> {
+        Ok(())
+    }
+
+    /// Returns true if messages of the given type are accepted. Messages of other types are rejected with
+    /// [`MailboxDelegateError::MessageNotFound`] without calling [`MailboxDelegate::receive`]
+    fn accepts(&self, _type_id: TypeId) -> bool {
+        true
+    }
+}
+
+/// Message transmission Error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MailboxDelegateError {
+    /// Buffer is full
+    BufferFull,
+
+    /// Message not found
+    MessageNotFound,
+
+    /// Invalid source
+    InvalidSource,
+
+    /// Invalid destination
+    InvalidDestination,
+
+    /// Invalid ID
+    InvalidId,
+
+    /// Invalid data
+    InvalidData,
+
+    /// Other error. Usually related to the underlying device or
+    /// transport.
+    Other,
+}
+
+/// Message send Error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SendError {
+    /// No endpoint is registered to receive messages for the destination
+    NoReceiver,
+
+    /// A receiver rejected the message, and the message was dropped for that receiver.
+    /// [`MailboxDelegateError::BufferFull`] indicates backpressure from a queued endpoint.
+    Rejected(MailboxDelegateError),
+}
+
+/// Primary node registration for receiving messages from the comms service
+pub struct Endpoint {
+    node: Node,
+    id: EndpointID,
+    delegator: Cell<Option<&'static dyn MailboxDelegate>>,
+    queue: Cell<Option<&'static dyn mailbox::Queue>>,
+    pending: Cell<Option<*const dyn rpc::Pending>>,
+    last_request: Cell<Option<RequestId>>,
+    request_lock: Mutex<NoopRawMutex, ()>,
+}
+
+impl NodeContainer for Endpoint {
+    fn get_node(&self) -> &Node {
+        &self.node
+    }
+}
+
+impl Endpoint {
+    /// Get endpoint ID
+    pub fn get_id(&self) -> EndpointID {
+        self.id
+    }
+
+    /// use this when static initialization occurs, internal fields will be validated in register_subscriber() later
+    pub const fn uninit(id: EndpointID) -> Self {
+        Self {
+            node: Node::uninit(),
+            id,
+            delegator: Cell::new(None),
+            queue: Cell::new(None),
+            pending: Cell::new(None),
+            last_request: Cell::new(None),
+            request_lock: Mutex::new(()),
+        }
+    }
+
+    /// Send a generic message to an endpoint
+    pub async fn send(&self, to: EndpointID, data: &impl Any) -> Result<(), SendError> {
+        send(self.id, to, data).await
+    }
+
+    /// Send a generic message to an endpoint with the given priority
+    pub async fn send_with_priority(
+        &self,
+        to: EndpointID,
+        priority: Priority,
+        data: &impl Any,
+    ) -> Result<(), SendError> {
+        send_with_priority(self.id, to, priority, data).await
+    }
+
+    /// Wait for the next message queued for this endpoint. Only available for endpoints registered with
+    /// [`register_queued_endpoint`], `T` must match the mailbox message type
+    pub async fn receive<T: Any>(&self) -> Result<OwnedMessage<T>, ReceiveError> {
+        let queue = self.queue.get().ok_or(ReceiveError::NoMailbox)?;
+        mailbox::receive(queue).await
+    }
+
+    fn init(&self, rx: &'static dyn MailboxDelegate) {
+        self.delegator.set(Some(rx));
+    }
+
+    fn deinit(&self) {
+        self.delegator.set(None);
+        self.queue.set(None);
+    }
+
+    /// deliver a message to this endpoint
+    fn process(&self, message: &Message) -> Outcome {
+        let Some(delegator) = self.delegator.get() else {
+            return Outcome::Unregistered;
+        };
+
+        if self.complete_request(message) {
+            return Outcome::Handled(Ok(()));
+        }
+
+        if !delegator.accepts(message.data.type_id()) {
+            return Outcome::Skipped;
+        }
+
+        Outcome::Handled(delegator.receive(message))
+    }
+}
+
+/// Outcome of delivering a message to a single endpoint
+#[derive(Copy, Clone)]
+enum Outcome {
+    /// The endpoint has no delegate
+    Unregistered,
+    /// The endpoint doesn't accept the message type
+    Skipped,
+    /// The endpoint received the message
+    Handled(Result<(), MailboxDelegateError>),
+}
+
+/// Endpoint registration error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegistrationError {
+    /// Endpoint is already registered
+    AlreadyRegistered,
+    /// Endpoint is not registered
+    NotRegistered,
+    /// Another endpoint is already registered with the same OEM key
+    OemKeyInUse(OemKey),
+    /// No free slots left in the OEM endpoint registry
+    OemRegistryFull,
+}
+
+impl From<intrusive_list::Error> for RegistrationError {
+    fn from(value: intrusive_list::Error) -> Self {
+        match value {
+            intrusive_list::Error::NodeAlreadyInList => RegistrationError::AlreadyRegistered,
+            intrusive_list::Error::NodeNotInList => RegistrationError::NotRegistered,
+        }
+    }
+}
+
+/// initialize receiver node for message handling
+pub async fn register_endpoint(
+    this: &'static impl MailboxDelegate,
+    node: &'static Endpoint,
+) -> Result<(), RegistrationError> {
+    node.init(this);
+    match get_registry(node.id) {
+        Registry::List(list) => Ok(list.get().await.push(node)?),
+        Registry::Oem(oem, key) => oem.get().await.insert(key, node),
+    }
+}
+
+/// initialize receiver node with a mailbox, messages are queued and taken with [`Endpoint::receive`]
+pub async fn register_queued_endpoint<M: RawMutex + 'static, T: Any + Clone, const N: usize>(
+    mailbox: &'static Mailbox<M, T, N>,
+    node: &'static Endpoint,
+) -> Result<(), RegistrationError> {
+    node.queue.set(Some(mailbox));
+    register_endpoint(mailbox, node).await
+}
+
+/// remove a receiver node from message handling. The endpoint may be registered again afterwards
+pub async fn deregister_endpoint(node: &'static Endpoint) -> Result<(), RegistrationError> {
+    match get_registry(node.id) {
+        Registry::List(list) => list.get().await.remove(node)?,
+        Registry::Oem(oem, key) => oem.get().await.remove(key, node)?,
+    }
+    node.deinit();
+    Ok(())
+}
+
+/// Maximum number of OEM endpoints that can be registered, per internal/external registry
+pub const OEM_ENDPOINT_COUNT: usize = 16;
+
+/// OEM endpoint registry, kept sorted by key so that lookups are a binary search
+struct OemRegistry {
+    endpoints: RefCell<heapless::Vec<(OemKey, &'static Endpoint), OEM_ENDPOINT_COUNT>>,
+}
+
+impl OemRegistry {
+    fn new() -> Self {
+        Self {
+            endpoints: RefCell::new(heapless::Vec::new()),
+        }
+    }
+
+    fn insert(&self, key: OemKey, endpoint: &'static Endpoint) -> Result<(), RegistrationError> {
+        critical_section::with(|_cs| {
+            let mut endpoints = self.endpoints.borrow_mut();
+            match endpoints.binary_search_by_key(&key, |(key, _)| *key) {
+                Ok(index) if core::ptr::eq(endpoints[index].1, endpoint) => Err(RegistrationError::AlreadyRegistered),
+                Ok(_) => Err(RegistrationError::OemKeyInUse(key)),
+                Err(index) => endpoints
+                    .insert(index, (key, endpoint))
+                    .map_err(|_| RegistrationError::OemRegistryFull),
+            }
+        })
+    }
+
+    fn remove(&self, key: OemKey, endpoint: &'static Endpoint) -> Result<(), RegistrationError> {
+        critical_section::with(|_cs| {
+            let mut endpoints = self.endpoints.borrow_mut();
+            match endpoints.binary_search_by_key(&key, |(key, _)| *key) {
+                Ok(index) if core::ptr::eq(endpoints[index].1, endpoint) => {
+                    endpoints.remove(index);
+                    Ok(())
+                }
+                _ => Err(RegistrationError::NotRegistered),
+            }
+        })
+    }
+
+    fn get(&self, key: OemKey) -> Option<&'static Endpoint> {
+        critical_section::with(|_cs| {
+            let endpoints = self.endpoints.borrow();
+            endpoints
+                .binary_search_by_key(&key, |(key, _)| *key)
+                .ok()
+                .map(|index| endpoints[index].1)
+        })
+    }
+}
+
+/// Receiver storage for a given endpoint ID
+enum Registry {
+    /// all receivers registered under a well-known endpoint
+    List(&'static OnceLock<IntrusiveList>),
+    /// OEM receivers, looked up by key
+    Oem(&'static OnceLock<OemRegistry>, OemKey),
+}
+
+static INTERNAL_OEM: OnceLock<OemRegistry> = OnceLock::new();
+static EXTERNAL_OEM: OnceLock<OemRegistry> = OnceLock::new();
+
+fn get_registry(target: EndpointID) -> Registry {
+    match target {
+        EndpointID::Internal(Internal::Oem(key)) => Registry::Oem(&INTERNAL_OEM, key),
+        EndpointID::External(External::Oem(key)) => Registry::Oem(&EXTERNAL_OEM, key),
+        _ => Registry::List(get_list(target)),
+    }
+}
+
+fn get_list(target: EndpointID) -> &'static OnceLock<IntrusiveList> {
+    match target {
+        EndpointID::External(ext_endpoint) => match ext_endpoint {
+            External::Host => {
+                static EXTERNAL_HOST: OnceLock<IntrusiveList> = OnceLock::new();
+                &EXTERNAL_HOST
+            }
+            External::Debug => {
+                static EXTERNAL_DEBUG: OnceLock<IntrusiveList> = OnceLock::new();
+                &EXTERNAL_DEBUG
+            }
+            External::Oem(_key) => unreachable!("OEM endpoints are routed through the OEM registry"),
+        },
+        EndpointID::Internal(int_endpoint) => {
+            use Internal::*;
+
+            static INTERNAL_LIST_PLATFORM_INFO: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_KEYBOARD: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_HID: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_HOST_BOOT: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_POWER: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_USBC: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_THERMAL: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_TRACKPAD: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_BATTERY: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_NONVOL: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_DEBUG: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_SECURITY: OnceLock<IntrusiveList> = OnceLock::new();
+            static INTERNAL_LIST_TIME_ALARM: OnceLock<IntrusiveList> = OnceLock::new();
+
+            match int_endpoint {
+                PlatformInfo => &INTERNAL_LIST_PLATFORM_INFO,
+                Keyboard => &INTERNAL_LIST_KEYBOARD,
+                Hid => &INTERNAL_LIST_HID,
+                HostBoot => &INTERNAL_LIST_HOST_BOOT,
+                Power => &INTERNAL_LIST_POWER,
+                Usbc => &INTERNAL_LIST_USBC,
+                Thermal => &INTERNAL_LIST_THERMAL,
+                Trackpad => &INTERNAL_LIST_TRACKPAD,
+                Battery => &INTERNAL_LIST_BATTERY,
+                Nonvol => &INTERNAL_LIST_NONVOL,
+                Debug => &INTERNAL_LIST_DEBUG,
+                Security => &INTERNAL_LIST_SECURITY,
+                TimeAlarm => &INTERNAL_LIST_TIME_ALARM,
+                Oem(_key) => unreachable!("OEM endpoints are routed through the OEM registry"),
+            }
+        }
+    }
+}
+
+/// Send a generic message to an endpoint.
+///
+/// The message is delivered to every receiver registered for the destination that accepts its type. Sending succeeds
+/// if any receiver accepted the message, otherwise the first rejection is reported. If no receiver accepts the type
+/// the message is rejected with [`MailboxDelegateError::MessageNotFound`].
+/// Messages from external endpoints are subject to the routing policy, see [`set_routing_policy`].
+pub async fn send(from: EndpointID, to: EndpointID, data: &impl Any) -> Result<(), SendError> {
+    send_with_priority(from, to, Priority::Normal, data).await
+}
+
+/// Send a generic message to an endpoint with the given priority, see [`send`]
+pub async fn send_with_priority(
+    from: EndpointID,
+    to: EndpointID,
+    priority: Priority,
+    data: &impl Any,
+) -> Result<(), SendError> {
+    route(Message {
+        from,
+        to,
+        priority,
+        data: Data::new(data),
+    })
+    .await
+}
+
+/// Result of delivering a message to any number of receivers
+#[derive(Default)]
+struct Delivery {
+    /// A receiver accepted the message
+    accepted: bool,
+    /// First rejection by a receiver that accepts the message type
+    rejected: Option<MailboxDelegateError>,
+    /// A receiver doesn't accept the message type
+    skipped: bool,
+}
+
+impl Delivery {
+    fn deliver(&mut self, endpoint: &Endpoint, message: &Message) {
+        match endpoint.process(message) {
+            Outcome::Unregistered => (),
+            Outcome::Skipped => self.skipped = true,
+            Outcome::Handled(result) => self.record(message, result),
+        }
+    }
+
+    /// Refuse a message that violates the routing policy, checked once per destination by the caller
+    fn refuse(&mut self, message: &Message) {
+        self.record(message, Err(MailboxDelegateError::InvalidSource));
+    }
+
+    fn record(&mut self, _message: &Message, result: Result<(), MailboxDelegateError>) {
+        #[cfg(feature = "comms-tap")]
+        tap::record(_message, result.map_err(SendError::Rejected));
+
+        match result {
+            Ok(()) => self.accepted = true,
+            Err(e) => {
+                self.rejected.get_or_insert(e);
+            }
+        }
+    }
+
+    /// True if a receiver accepted or rejected the message rather than skipping it
+    #[cfg(feature = "comms-tap")]
+    fn handled(&self) -> bool {
+        self.accepted || self.rejected.is_some()
+    }
+
+    fn result(&self) -> Result<(), SendError> {
+        match (self.accepted, self.rejected, self.skipped) {
+            (true, _, _) => Ok(()),
+            (false, Some(e), _) => Err(SendError::Rejected(e)),
+            (false, None, true) => Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+            (false, None, false) => Err(SendError::NoReceiver),
+        }
+    }
+}
+
+/// route a message to any valid receiver nodes
+async fn route(message: Message<'_>) -> Result<(), SendError> {
+    let mut delivery = Delivery::default();
+
+    if !access::check(&message) {
+        delivery.refuse(&message);
+        return delivery.result();
+    }
+
+    match get_registry(message.to) {
+        Registry::List(list) => {
+            for rxq in list.get().await {
+                if let Some(endpoint) = rxq.data::<Endpoint>() {
+                    if message.to == endpoint.id {
+                        delivery.deliver(endpoint, &message);
+                    }
+                }
+            }
+        }
+        Registry::Oem(oem, key) => {
+            if let Some(endpoint) = oem.get().await.get(key) {
+                delivery.deliver(endpoint, &message);
+            }
+        }
+    }
+
+    let result = delivery.result();
+
+    // receivers that handled the message already recorded it
+    #[cfg(feature = "comms-tap")]
+    if !delivery.handled() {
+        tap::record(&message, result);
+    }
+
+    result
+}
+
+/// Comms readiness, ready once the endpoint lists are initialized
+pub static READINESS: crate::init::Service = crate::init::Service::new("comms", &[]);
+
+pub(crate) fn init() {
+    // initialize internal subscriber lists
+    get_list(Internal::PlatformInfo.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Keyboard.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Hid.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::HostBoot.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Power.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Usbc.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Thermal.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Trackpad.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Battery.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Nonvol.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Debug.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::Security.into()).get_or_init(IntrusiveList::new);
+    get_list(Internal::TimeAlarm.into()).get_or_init(IntrusiveList::new);
+    INTERNAL_OEM.get_or_init(OemRegistry::new);
+
+    // initialize external subscriber lists
+    get_list(External::Debug.into()).get_or_init(IntrusiveList::new);
+    get_list(External::Host.into()).get_or_init(IntrusiveList::new);
+    EXTERNAL_OEM.get_or_init(OemRegistry::new);
+
+    // initialize topic subscriptions
+    topic::init();
+
+    READINESS.ready();
+}
+
+#[cfg(test)]
+mod test {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct Receiver {
+        tp: Endpoint,
+        received: AtomicUsize,
+    }
+
+    impl Receiver {
+        fn new(id: EndpointID) -> Self {
+            Self {
+                tp: Endpoint::uninit(id),
+                received: AtomicUsize::new(0),
+            }
+        }
+
+        fn received(&self) -> usize {
+            self.received.load(Ordering::SeqCst)
+        }
+    }
+
+    impl MailboxDelegate for Receiver {
+        fn receive(&self, _message: &Message) -> Result<(), MailboxDelegateError> {
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_oem_registry() {
+        static FIRST: OnceLock<Receiver> = OnceLock::new();
+        static SECOND: OnceLock<Receiver> = OnceLock::new();
+        static DUPLICATE: OnceLock<Receiver> = OnceLock::new();
+        static EXTERNAL: OnceLock<Receiver> = OnceLock::new();
+        let first = FIRST.get_or_init(|| Receiver::new(Internal::Oem(100).into()));
+        let second = SECOND.get_or_init(|| Receiver::new(Internal::Oem(101).into()));
+        let duplicate = DUPLICATE.get_or_init(|| Receiver::new(Internal::Oem(100).into()));
+        let external = EXTERNAL.get_or_init(|| Receiver::new(External::Oem(100).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            assert_eq!(Ok(()), register_endpoint(first, &first.tp).await);
+            assert_eq!(Ok(()), register_endpoint(second, &second.tp).await);
+            assert_eq!(Ok(()), register_endpoint(external, &external.tp).await);
+
+            // keys are unique per registry
+            assert_eq!(
+                Err(RegistrationError::AlreadyRegistered),
+                register_endpoint(first, &first.tp).await
+            );
+            assert_eq!(
+                Err(RegistrationError::OemKeyInUse(100)),
+                register_endpoint(duplicate, &duplicate.tp).await
+            );
+            assert_eq!(
+                Err(RegistrationError::NotRegistered),
+                deregister_endpoint(&duplicate.tp).await
+            );
+
+            // messages only reach the endpoint with the matching key
+            send(External::Host.into(), Internal::Oem(100).into(), &0u32)
+                .await
+                .unwrap();
+            assert_eq!(1, first.received());
+            assert_eq!(0, second.received());
+            assert_eq!(0, external.received());
+
+            assert_eq!(
+                Err(SendError::NoReceiver),
+                send(External::Host.into(), Internal::Oem(102).into(), &0u32).await
+            );
+            assert_eq!(1, first.received());
+            assert_eq!(0, second.received());
+
+            // the key is released on deregistration
+            assert_eq!(Ok(()), deregister_endpoint(&first.tp).await);
+            assert_eq!(Ok(()), register_endpoint(duplicate, &duplicate.tp).await);
+            send(External::Host.into(), Internal::Oem(100).into(), &0u32)
+                .await
+                .unwrap();
+            assert_eq!(1, first.received());
+            assert_eq!(1, duplicate.received());
+        });
+    }
+
+    #[test]
+    fn test_queued_endpoint() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, u32, 2>> = OnceLock::new();
+        static QUEUED: OnceLock<Endpoint> = OnceLock::new();
+        static PLAIN: OnceLock<Receiver> = OnceLock::new();
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let queued = QUEUED.get_or_init(|| Endpoint::uninit(Internal::Oem(200).into()));
+        let plain = PLAIN.get_or_init(|| Receiver::new(Internal::Oem(201).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::External(External::Host);
+            let to = EndpointID::Internal(Internal::Oem(200));
+
+            assert_eq!(Err(SendError::NoReceiver), send(from, to, &0u32).await);
+
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, queued).await);
+            assert_eq!(Ok(()), register_endpoint(plain, &plain.tp).await);
+
+            // mailbox only accepts its own message type, and reports backpressure once full
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                send(from, to, &0u8).await
+            );
+            assert_eq!(Ok(()), send(from, to, &1u32).await);
+            assert_eq!(Ok(()), send(from, to, &2u32).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::BufferFull)),
+                send(from, to, &3u32).await
+            );
+            assert_eq!(2, mailbox.len());
+
+            // messages are received in order, with ownership of the data
+            assert_eq!(
+                Err(ReceiveError::InvalidType),
+                queued.receive::<u8>().await.map(|m| m.data)
+            );
+            let message = queued.receive::<u32>().await.unwrap();
+            assert_eq!((from, to, 1), (message.from, message.to, message.data));
+            assert_eq!(2, queued.receive::<u32>().await.unwrap().data);
+            assert!(mailbox.is_empty());
+
+            // endpoints with a plain delegate have no mailbox
+            assert_eq!(
+                Err(ReceiveError::NoMailbox),
+                plain.tp.receive::<u32>().await.map(|m| m.data)
+            );
+
+            assert_eq!(Ok(()), deregister_endpoint(queued).await);
+            assert_eq!(
+                Err(ReceiveError::NoMailbox),
+                queued.receive::<u32>().await.map(|m| m.data)
+            );
+            assert_eq!(Err(SendError::NoReceiver), send(from, to, &0u32).await);
+        });
+    }
+
+    #[test]
+    fn test_request_response() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+        use embassy_time::Duration;
+
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, Request<u32>, 1>> = OnceLock::new();
+        static RESPONDER: OnceLock<Endpoint> = OnceLock::new();
+        static REQUESTER: OnceLock<Receiver> = OnceLock::new();
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let responder = RESPONDER.get_or_init(|| Endpoint::uninit(Internal::Oem(300).into()));
+        let requester = REQUESTER.get_or_init(|| Receiver::new(Internal::Oem(301).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let to = EndpointID::Internal(Internal::Oem(300));
+
+            assert_eq!(
+                Err(RequestError::Send(SendError::NoReceiver)),
+                requester.tp.request::<u32, u32>(to, 0).await
+            );
+
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, responder).await);
+            assert_eq!(Ok(()), register_endpoint(requester, &requester.tp).await);
+
+            // responder doubles the request
+            let respond = async {
+                let request = responder.receive::<Request<u32>>().await.unwrap();
+                responder
+                    .respond(request.from, request.data.id(), request.data.data * 2)
+                    .await
+            };
+
+            let (response, responded) =
+                embassy_futures::join::join(requester.tp.request::<u32, u32>(to, 21), respond).await;
+            assert_eq!(Ok(42), response);
+            assert_eq!(Ok(()), responded);
+
+            // responses are consumed by the requester rather than its delegate
+            assert_eq!(0, requester.received());
+
+            // unanswered requests time out, late responses are dropped without an error for the responder
+            assert_eq!(
+                Err(RequestError::Timeout),
+                requester
+                    .tp
+                    .request_with_timeout::<u32, u32>(to, 1, Duration::from_millis(10))
+                    .await
+            );
+            let request = responder.receive::<Request<u32>>().await.unwrap();
+            assert_eq!(Ok(()), responder.respond(request.from, request.data.id(), 2u32).await);
+            assert_eq!(0, requester.received());
+
+            // and don't complete the next request
+            let respond = async {
+                let stale = responder.receive::<Request<u32>>().await.unwrap();
+                responder.respond(stale.from, request.data.id(), 0u32).await?;
+                responder.respond(stale.from, stale.data.id(), 6u32).await
+            };
+            let (response, responded) =
+                embassy_futures::join::join(requester.tp.request::<u32, u32>(to, 3), respond).await;
+            assert_eq!(Ok(6), response);
+            assert_eq!(Ok(()), responded);
+
+            // late responses are dropped after a request with another response type, which isn't consumed afterwards
+            let respond = async {
+                let next = responder.receive::<Request<u32>>().await.unwrap();
+                responder.respond(next.from, request.data.id(), 0u32).await?;
+                responder.respond(next.from, next.data.id(), 4u8).await
+            };
+            let (response, responded) =
+                embassy_futures::join::join(requester.tp.request::<u32, u8>(to, 2), respond).await;
+            assert_eq!(Ok(4), response);
+            assert_eq!(Ok(()), responded);
+            assert_eq!(Ok(()), responder.respond(request.from, request.data.id(), 0u32).await);
+            assert_eq!(0, requester.received());
+
+            // other messages still reach the delegate
+            assert_eq!(Ok(()), send(to, requester.tp.get_id(), &0u8).await);
+            assert_eq!(1, requester.received());
+        });
+    }
+
+    #[test]
+    fn test_publish_subscribe() {
+        static FIRST: OnceLock<Receiver> = OnceLock::new();
+        static SECOND: OnceLock<Receiver> = OnceLock::new();
+        static FIRST_TYPED: OnceLock<Subscription> = OnceLock::new();
+        static SECOND_TYPED: OnceLock<Subscription> = OnceLock::new();
+        static SECOND_NAMED: OnceLock<Subscription> = OnceLock::new();
+        let first = FIRST.get_or_init(|| Receiver::new(Internal::Oem(400).into()));
+        let second = SECOND.get_or_init(|| Receiver::new(Internal::Oem(401).into()));
+        let first_typed = FIRST_TYPED.get_or_init(|| Subscription::new(Topic::of::<u16>()));
+        let second_typed = SECOND_TYPED.get_or_init(|| Subscription::new(Topic::of::<u16>()));
+        let second_named = SECOND_NAMED.get_or_init(|| Subscription::new(Topic::Named("test_publish_subscribe")));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::External(External::Host);
+
+            assert_eq!(Ok(()), register_endpoint(first, &first.tp).await);
+            assert_eq!(Ok(()), register_endpoint(second, &second.tp).await);
+            assert_eq!(Ok(()), subscribe(&first.tp, first_typed).await);
+            assert_eq!(Ok(()), subscribe(&second.tp, second_typed).await);
+            assert_eq!(Ok(()), subscribe(&second.tp, second_named).await);
+            assert_eq!(
+                Err(RegistrationError::AlreadyRegistered),
+                subscribe(&first.tp, second_typed).await
+            );
+
+            // typed topics reach every subscriber of the type
+            assert_eq!(Ok(()), publish(from, &0u16).await);
+            assert_eq!((1, 1), (first.received(), second.received()));
+
+            // named topics only reach subscribers of the name
+            assert_eq!(Ok(()), publish_named(from, "test_publish_subscribe", &0u16).await);
+            assert_eq!((1, 2), (first.received(), second.received()));
+
+            assert_eq!(Err(SendError::NoReceiver), publish(from, &0i16).await);
+
+            assert_eq!(Ok(()), unsubscribe(first_typed).await);
+            assert_eq!(Err(RegistrationError::NotRegistered), unsubscribe(first_typed).await);
+            assert_eq!(Ok(()), first.tp.publish(&0u16).await);
+            assert_eq!((1, 3), (first.received(), second.received()));
+        });
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: u8,
+        value: i32,
+    }
+
+    impl bridge::BridgeMessage for Reading {
+        const TAG: u16 = 1;
+    }
+
+    impl bridge::BridgeMessage for u32 {
+        const TAG: u16 = 2;
+    }
+
+    #[test]
+    fn test_bridge() {
+        use bridge::{encode_frame, read_frame, Bridge, BridgeError, MAX_FRAME_SIZE};
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+        use embassy_sync::pipe::Pipe;
+
+        type TestBridge = Bridge<(Reading, u32), 1>;
+
+        static BRIDGE: OnceLock<TestBridge> = OnceLock::new();
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, Reading, 1>> = OnceLock::new();
+        static SENSOR: OnceLock<Endpoint> = OnceLock::new();
+        let bridge = BRIDGE.get_or_init(|| Bridge::new(External::Debug));
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let sensor = SENSOR.get_or_init(|| Endpoint::uninit(Internal::Oem(500).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let host = EndpointID::External(External::Debug);
+            let reading = Reading { sensor: 3, value: -42 };
+            let to_ec: Pipe<NoopRawMutex, MAX_FRAME_SIZE> = Pipe::new();
+            let to_host: Pipe<NoopRawMutex, MAX_FRAME_SIZE> = Pipe::new();
+
+            assert_eq!(Ok(()), register_endpoint(bridge, &bridge.tp).await);
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, sensor).await);
+
+            // inbound frames are routed to internal endpoints
+            let frame = encode_frame::<(Reading, u32)>(&Message {
+                from: host,
+                to: sensor.get_id(),
+                priority: Priority::Normal,
+                data: Data::new(&reading),
+            })
+            .unwrap();
+            to_ec.write_all(&frame).await;
+            assert_eq!(Ok(()), bridge.receive_frame(&mut &to_ec).await);
+            let message = sensor.receive::<Reading>().await.unwrap();
+            assert_eq!((host, reading.clone()), (message.from, message.data));
+
+            // corrupted frames are dropped
+            let mut corrupted = frame.clone();
+            corrupted[6] ^= 0xFF;
+            to_ec.write_all(&corrupted).await;
+            assert_eq!(Err(BridgeError::Crc), bridge.receive_frame(&mut &to_ec).await);
+
+            // only the bridged target may be named as the source
+            let spoofed = encode_frame::<(Reading, u32)>(&Message {
+                from: External::Host.into(),
+                to: sensor.get_id(),
+                priority: Priority::Normal,
+                data: Data::new(&reading),
+            })
+            .unwrap();
+            to_ec.write_all(&spoofed).await;
+            assert_eq!(Err(BridgeError::InvalidSource), bridge.receive_frame(&mut &to_ec).await);
+
+            // outbound messages are serialized and queued for the transport
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                sensor.send(host, &0u8).await
+            );
+            assert_eq!(Ok(()), sensor.send(host, &7u32).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::BufferFull)),
+                sensor.send(host, &8u32).await
+            );
+            assert_eq!(Ok(()), bridge.transmit_frame(&mut &to_host).await);
+
+            let mut buf = [0u8; MAX_FRAME_SIZE];
+            let frame = read_frame(&mut &to_host, &mut buf).await.unwrap();
+            assert_eq!((2, sensor.get_id(), host), (frame.tag, frame.from, frame.to));
+            assert_eq!(Ok(7u32), postcard::from_bytes(frame.payload));
+        });
+    }
+
+    #[cfg(feature = "comms-tap")]
+    #[test]
+    fn test_tap() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+        static PLAIN: OnceLock<Receiver> = OnceLock::new();
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, u32, 1>> = OnceLock::new();
+        static QUEUED: OnceLock<Endpoint> = OnceLock::new();
+        let plain = PLAIN.get_or_init(|| Receiver::new(Internal::Oem(600).into()));
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let queued = QUEUED.get_or_init(|| Endpoint::uninit(Internal::Oem(601).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::Internal(Internal::Oem(602));
+            let missing = EndpointID::Internal(Internal::Oem(603));
+
+            assert_eq!(Ok(()), register_endpoint(plain, &plain.tp).await);
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, queued).await);
+            assert_eq!(Ok(()), tap::register_debug_endpoint().await);
+
+            assert_eq!(Ok(()), send(from, plain.tp.get_id(), &0u8).await);
+            assert_eq!(Ok(()), send(from, queued.get_id(), &1u32).await);
+            assert!(send(from, queued.get_id(), &2u32).await.is_err());
+            assert!(send(from, queued.get_id(), &3u8).await.is_err());
+            assert_eq!(Err(SendError::NoReceiver), send(from, missing, &4u32).await);
+
+            let mut last = None;
+            tap::for_each_trace(|entry| {
+                if entry.from == from {
+                    last = Some(*entry);
+                }
+            });
+            let last = last.unwrap();
+            assert_eq!(missing, last.to);
+            assert_eq!(tap::type_hash::<u32>(), last.type_hash);
+            assert_eq!(Err(SendError::NoReceiver), last.result);
+
+            let sender = tap::stats(from).unwrap();
+            assert_eq!((4, 1), (sender.sent, sender.undelivered));
+            assert_eq!(1, tap::stats(plain.tp.get_id()).unwrap().received);
+
+            let queued_stats = tap::stats(queued.get_id()).unwrap();
+            assert_eq!(1, queued_stats.received);
+            assert_eq!(1, queued_stats.rejected(MailboxDelegateError::BufferFull));
+            assert_eq!(1, queued_stats.rejected(MailboxDelegateError::MessageNotFound));
+            assert_eq!(2, queued_stats.total_rejected());
+            assert_eq!(None, tap::stats(missing));
+
+            assert_eq!(Ok(()), send(from, Internal::Debug.into(), &tap::Dump).await);
+
+            // the tap doesn't claim other messages for the debug endpoint
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                send(from, Internal::Debug.into(), &5u32).await
+            );
+        });
+    }
+
+    struct Typed {
+        tp: Endpoint,
+        received: AtomicUsize,
+    }
+
+    impl Handler<u8> for Typed {
+        fn handle(&self, _message: &Message, _data: &u8) -> Result<(), MailboxDelegateError> {
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    impl Handler<u16> for Typed {
+        fn handle(&self, _message: &Message, data: &u16) -> Result<(), MailboxDelegateError> {
+            if *data == 0 {
+                return Err(MailboxDelegateError::InvalidData);
+            }
+
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    crate::impl_mailbox_delegate!(impl for Typed { u8, u16 });
+
+    #[test]
+    fn test_typed_handler() {
+        static TYPED: OnceLock<Typed> = OnceLock::new();
+        let typed = TYPED.get_or_init(|| Typed {
+            tp: Endpoint::uninit(Internal::Oem(700).into()),
+            received: AtomicUsize::new(0),
+        });
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::External(External::Host);
+            let to = typed.tp.get_id();
+
+            assert_eq!(Ok(()), register_endpoint(typed, &typed.tp).await);
+
+            assert_eq!(Ok(()), send(from, to, &0u8).await);
+            assert_eq!(Ok(()), send(from, to, &1u16).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::InvalidData)),
+                send(from, to, &0u16).await
+            );
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                send(from, to, &0u32).await
+            );
+            assert_eq!(2, typed.received.load(Ordering::SeqCst));
+
+            assert!(typed.accepts(TypeId::of::<u8>()));
+            assert!(!typed.accepts(TypeId::of::<u32>()));
+        });
+    }
+
+    #[test]
+    fn test_mixed_receivers() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+        static TYPED: OnceLock<Typed> = OnceLock::new();
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, u32, 1>> = OnceLock::new();
+        static QUEUED: OnceLock<Endpoint> = OnceLock::new();
+        let typed = TYPED.get_or_init(|| Typed {
+            tp: Endpoint::uninit(Internal::Security.into()),
+            received: AtomicUsize::new(0),
+        });
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let queued = QUEUED.get_or_init(|| Endpoint::uninit(Internal::Security.into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::Internal(Internal::Power);
+            let to = EndpointID::Internal(Internal::Security);
+
+            assert_eq!(Ok(()), register_endpoint(typed, &typed.tp).await);
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, queued).await);
+
+            // each type reaches the receiver that accepts it, the other receiver is skipped
+            assert_eq!(Ok(()), send(from, to, &1u8).await);
+            assert_eq!(Ok(()), send(from, to, &2u32).await);
+            assert_eq!(1, typed.received.load(Ordering::SeqCst));
+            assert_eq!(2, queued.receive::<u32>().await.unwrap().data);
+
+            // rejections are only reported from receivers that accept the type
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::InvalidData)),
+                send(from, to, &0u16).await
+            );
+            assert_eq!(Ok(()), send(from, to, &3u32).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::BufferFull)),
+                send(from, to, &4u32).await
+            );
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::MessageNotFound)),
+                send(from, to, &5u64).await
+            );
+        });
+    }
+
+    #[test]
+    fn test_priority() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, u32, 4>> = OnceLock::new();
+        static QUEUED: OnceLock<Endpoint> = OnceLock::new();
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let queued = QUEUED.get_or_init(|| Endpoint::uninit(Internal::Oem(800).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::Internal(Internal::Thermal);
+            let to = queued.get_id();
+
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, queued).await);
+
+            assert_eq!(Ok(()), send_with_priority(from, to, Priority::Low, &1u32).await);
+            assert_eq!(Ok(()), send(from, to, &2u32).await);
+            assert_eq!(Ok(()), send_with_priority(from, to, Priority::Critical, &3u32).await);
+            assert_eq!(Ok(()), send(from, to, &4u32).await);
+
+            // higher priority first, then in the order sent
+            for (priority, data) in [
+                (Priority::Critical, 3),
+                (Priority::Normal, 2),
+                (Priority::Normal, 4),
+                (Priority::Low, 1),
+            ] {
+                let message = queued.receive::<u32>().await.unwrap();
+                assert_eq!((priority, data), (message.priority, message.data));
+            }
+        });
+    }
+
+    #[test]
+    fn test_critical_eviction() {
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+        static MAILBOX: OnceLock<Mailbox<NoopRawMutex, u32, 2>> = OnceLock::new();
+        static QUEUED: OnceLock<Endpoint> = OnceLock::new();
+        let mailbox = MAILBOX.get_or_init(Mailbox::new);
+        let queued = QUEUED.get_or_init(|| Endpoint::uninit(Internal::Oem(900).into()));
+
+        init();
+
+        embassy_futures::block_on(async {
+            let from = EndpointID::Internal(Internal::Thermal);
+            let to = queued.get_id();
+
+            assert_eq!(Ok(()), register_queued_endpoint(mailbox, queued).await);
+
+            assert_eq!(Ok(()), send_with_priority(from, to, Priority::Low, &1u32).await);
+            assert_eq!(Ok(()), send(from, to, &2u32).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::BufferFull)),
+                send(from, to, &3u32).await
+            );
+
+            // critical messages replace the lowest priority message, then the next lowest
+            assert_eq!(Ok(()), send_with_priority(from, to, Priority::Critical, &4u32).await);
+            assert_eq!(Ok(()), send_with_priority(from, to, Priority::Critical, &5u32).await);
+            assert_eq!(
+                Err(SendError::Rejected(MailboxDelegateError::BufferFull)),
+                send_with_priority(from, to, Priority::Critical, &6u32).await
+            );
+
+            for data in [4, 5] {
+                let message = queued.receive::<u32>().await.unwrap();
+                assert_eq!((Priority::Critical, data), (message.priority, message.data));
+            }
+            assert!(mailbox.is_empty());
+        });
+    }
+}
@@ -0,0 +1,177 @@
+//! Request/response pattern on top of comms endpoints
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration};
+
+use super::{route, send, Data, Endpoint, EndpointID, Message, Priority, SendError};
+
+/// Default request timeout
+/// set to high value since this is intended to prevent an unresponsive endpoint from blocking the requester
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// A unique identifier for a particular request, used to match the response. Later requests have greater IDs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestId(usize);
+
+impl RequestId {
+    /// Get the next request ID, unique across all endpoints
+    fn next() -> Self {
+        static NEXT_REQUEST_ID: AtomicUsize = AtomicUsize::new(0);
+        RequestId(NEXT_REQUEST_ID.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+/// Request sent by [`Endpoint::request`], responders answer with [`Endpoint::respond`]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Request<T> {
+    id: RequestId,
+    /// Request data
+    pub data: T,
+}
+
+impl<T> Request<T> {
+    /// Get the request ID to respond with
+    pub fn id(&self) -> RequestId {
+        self.id
+    }
+}
+
+/// Response sent by [`Endpoint::respond`]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response<T> {
+    id: RequestId,
+    /// Response data
+    pub data: T,
+}
+
+/// Request error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RequestError {
+    /// The request could not be delivered
+    Send(SendError),
+
+    /// No response was received in time
+    Timeout,
+}
+
+/// Response slot for the request currently outstanding on an endpoint
+struct Slot<T> {
+    id: RequestId,
+    response: Signal<NoopRawMutex, T>,
+}
+
+/// Type-erased access to an outstanding request from its endpoint
+pub(super) trait Pending {
+    /// Complete the request if `message` is its response, returns true if the message was consumed
+    fn complete(&self, message: &Message) -> bool;
+}
+
+impl<T: Any + Clone> Pending for Slot<T> {
+    fn complete(&self, message: &Message) -> bool {
+        match message.data.get::<Response<T>>() {
+            Some(response) if response.id == self.id => {
+                self.response.signal(response.data.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Clears the outstanding request of an endpoint when the request completes or is cancelled
+struct PendingGuard<'a>(&'a Endpoint);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.pending.set(None);
+    }
+}
+
+impl Endpoint {
+    /// Send a request and wait for the typed response, see [`Endpoint::request_with_timeout`]
+    pub async fn request<Req: Any, Resp: Any + Clone>(
+        &self,
+        to: EndpointID,
+        request: Req,
+    ) -> Result<Resp, RequestError> {
+        self.request_with_timeout(to, request, DEFAULT_REQUEST_TIMEOUT).await
+    }
+
+    /// Send a request and wait for the typed response.
+    ///
+    /// This endpoint must be registered so the response can be routed back to it. Requests from the same endpoint are
+    /// executed one at a time and responses are matched by request ID. A late response to any earlier request from
+    /// this endpoint, such as one that timed out, is dropped without reaching the endpoint delegate.
+    pub async fn request_with_timeout<Req: Any, Resp: Any + Clone>(
+        &self,
+        to: EndpointID,
+        request: Req,
+        timeout: Duration,
+    ) -> Result<Resp, RequestError> {
+        let _lock = self.request_lock.lock().await;
+
+        let slot = Slot::<Resp> {
+            id: RequestId::next(),
+            response: Signal::new(),
+        };
+
+        // The slot outlives the guard, which unregisters it before the slot is dropped.
+        // This future is pinned while awaiting, so the slot cannot move while it is registered.
+        self.pending.set(Some(&slot as &dyn Pending as *const dyn Pending));
+        self.last_request.set(Some(slot.id));
+        let _guard = PendingGuard(self);
+
+        send(
+            self.id,
+            to,
+            &Request {
+                id: slot.id,
+                data: request,
+            },
+        )
+        .await
+        .map_err(RequestError::Send)?;
+
+        with_timeout(timeout, slot.response.wait())
+            .await
+            .map_err(|_| RequestError::Timeout)
+    }
+
+    /// Respond to a request received from `to`
+    pub async fn respond<Resp: Any>(&self, to: EndpointID, id: RequestId, response: Resp) -> Result<(), SendError> {
+        let response = Response { id, data: response };
+        route(Message {
+            from: self.id,
+            to,
+            priority: Priority::Normal,
+            data: Data {
+                contents: &response,
+                response: Some(id),
+            },
+        })
+        .await
+    }
+
+    /// Deliver `message` to the outstanding request if it is the matching response, returns true if the message was
+    /// consumed. Responses to earlier requests from this endpoint are consumed and dropped.
+    pub(super) fn complete_request(&self, message: &Message) -> bool {
+        if let Some(pending) = self.pending.get() {
+            // SAFETY: the pointer is only set while the requesting future holds the slot, see `request_with_timeout`
+            if unsafe { &*pending }.complete(message) {
+                return true;
+            }
+        }
+
+        match (message.data.response, self.last_request.get()) {
+            (Some(id), Some(last)) => id <= last,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Comms tap, traces routed messages and keeps per-endpoint delivery counters
+//!
+//! Every delivery attempt is recorded in a fixed-size ring buffer, along with messages that had no receiver.
+//! Message types are identified by a hash of their [`TypeId`], use [`type_hash`] to match them.
+use core::any::{Any, TypeId};
+use core::cell::RefCell;
+use core::hash::{Hash, Hasher};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::once_lock::OnceLock;
+use embassy_time::Instant;
+use heapless::HistoryBuffer;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Endpoint, EndpointID, Internal, MailboxDelegate, MailboxDelegateError, Message, RegistrationError, SendError,
+};
+use crate::info;
+
+/// Number of messages kept in the trace
+pub const TRACE_DEPTH: usize = 32;
+
+/// Maximum number of endpoints with counters
+pub const TAP_ENDPOINT_COUNT: usize = 32;
+
+/// Number of [`MailboxDelegateError`] variants
+const REJECT_KINDS: usize = 7;
+
+/// Traced delivery of a message
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TraceEntry {
+    /// When the message was routed
+    pub timestamp: Instant,
+    /// Where the message came from
+    pub from: EndpointID,
+    /// Where the message was going
+    pub to: EndpointID,
+    /// Hash of the message type, see [`type_hash`]
+    pub type_hash: u32,
+    /// Delivery result
+    pub result: Result<(), SendError>,
+}
+
+/// Delivery counters for a single endpoint
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EndpointStats {
+    /// Messages sent by this endpoint, counted once per receiver
+    pub sent: u32,
+    /// Messages sent by this endpoint that had no receiver
+    pub undelivered: u32,
+    /// Messages accepted by this endpoint
+    pub received: u32,
+    rejected: [u32; REJECT_KINDS],
+}
+
+impl EndpointStats {
+    /// Messages rejected by this endpoint with the given error
+    pub fn rejected(&self, error: MailboxDelegateError) -> u32 {
+        self.rejected[reject_index(error)]
+    }
+
+    /// Messages rejected by this endpoint for any reason
+    pub fn total_rejected(&self) -> u32 {
+        self.rejected.iter().sum()
+    }
+}
+
+fn reject_index(error: MailboxDelegateError) -> usize {
+    match error {
+        MailboxDelegateError::BufferFull => 0,
+        MailboxDelegateError::MessageNotFound => 1,
+        MailboxDelegateError::InvalidSource => 2,
+        MailboxDelegateError::InvalidDestination => 3,
+        MailboxDelegateError::InvalidId => 4,
+        MailboxDelegateError::InvalidData => 5,
+        MailboxDelegateError::Other => 6,
+    }
+}
+
+/// FNV-1a, stable across builds of the same binary so hashes can be matched on a host
+struct Fnv(u32);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u32).wrapping_mul(0x0100_0193);
+        }
+    }
+}
+
+fn hash_type_id(type_id: TypeId) -> u32 {
+    let mut hasher = Fnv(0x811c_9dc5);
+    type_id.hash(&mut hasher);
+    hasher.0
+}
+
+/// Hash of a message type, as recorded in [`TraceEntry::type_hash`]
+pub fn type_hash<T: Any>() -> u32 {
+    hash_type_id(TypeId::of::<T>())
+}
+
+static TRACE: Mutex<CriticalSectionRawMutex, RefCell<HistoryBuffer<TraceEntry, TRACE_DEPTH>>> =
+    Mutex::new(RefCell::new(HistoryBuffer::new()));
+
+static STATS: Mutex<CriticalSectionRawMutex, RefCell<heapless::Vec<(EndpointID, EndpointStats), TAP_ENDPOINT_COUNT>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Update the counters for `id`, endpoints past [`TAP_ENDPOINT_COUNT`] are not counted
+fn update_stats(
+    stats: &mut heapless::Vec<(EndpointID, EndpointStats), TAP_ENDPOINT_COUNT>,
+    id: EndpointID,
+    f: impl FnOnce(&mut EndpointStats),
+) {
+    let index = match stats.iter().position(|(stats_id, _)| *stats_id == id) {
+        Some(index) => index,
+        None => {
+            if stats.push((id, EndpointStats::default())).is_err() {
+                return;
+            }
+            stats.len() - 1
+        }
+    };
+
+    f(&mut stats[index].1);
+}
+
+/// Record a delivery attempt
+pub(super) fn record(message: &Message, result: Result<(), SendError>) {
+    TRACE.lock(|trace| {
+        trace.borrow_mut().write(TraceEntry {
+            timestamp: Instant::now(),
+            from: message.from,
+            to: message.to,
+            type_hash: hash_type_id(message.data.type_id()),
+            result,
+        })
+    });
+
+    STATS.lock(|stats| {
+        let mut stats = stats.borrow_mut();
+        match result {
+            Ok(()) => {
+                update_stats(&mut stats, message.from, |stats| stats.sent += 1);
+                update_stats(&mut stats, message.to, |stats| stats.received += 1);
+            }
+            Err(SendError::Rejected(e)) => {
+                update_stats(&mut stats, message.from, |stats| stats.sent += 1);
+                update_stats(&mut stats, message.to, |stats| stats.rejected[reject_index(e)] += 1);
+            }
+            Err(SendError::NoReceiver) => {
+                update_stats(&mut stats, message.from, |stats| stats.undelivered += 1);
+            }
+        }
+    });
+}
+
+/// Get the counters for an endpoint
+pub fn stats(id: EndpointID) -> Option<EndpointStats> {
+    STATS.lock(|stats| {
+        stats
+            .borrow()
+            .iter()
+            .find(|(stats_id, _)| *stats_id == id)
+            .map(|(_, stats)| *stats)
+    })
+}
+
+/// Visit traced messages, oldest first
+pub fn for_each_trace(mut f: impl FnMut(&TraceEntry)) {
+    TRACE.lock(|trace| trace.borrow().oldest_ordered().for_each(&mut f));
+}
+
+/// Clear the trace and all counters
+pub fn clear() {
+    TRACE.lock(|trace| trace.borrow_mut().clear());
+    STATS.lock(|stats| stats.borrow_mut().clear());
+}
+
+/// Log the trace and all counters
+pub fn dump() {
+    info!("Comms trace:");
+    for_each_trace(|entry| {
+        info!(
+            "{} us: {:?} -> {:?} type {:x}: {:?}",
+            entry.timestamp.as_micros(),
+            entry.from,
+            entry.to,
+            entry.type_hash,
+            entry.result
+        );
+    });
+
+    info!("Comms counters:");
+    STATS.lock(|stats| {
+        for (id, stats) in stats.borrow().iter() {
+            info!(
+                "{:?}: sent {}, undelivered {}, received {}, rejected {}",
+                id,
+                stats.sent,
+                stats.undelivered,
+                stats.received,
+                stats.total_rejected()
+            );
+        }
+    });
+}
+
+/// Message requesting a [`dump`] from the tap endpoint, see [`register_debug_endpoint`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Dump;
+
+struct Tap {
+    tp: Endpoint,
+}
+
+impl MailboxDelegate for Tap {
+    fn receive(&self, message: &Message) -> Result<(), MailboxDelegateError> {
+        if !message.data.is_a::<Dump>() {
+            return Err(MailboxDelegateError::MessageNotFound);
+        }
+
+        dump();
+        Ok(())
+    }
+
+    // other debug endpoints may share this ID, leave their messages to them
+    fn accepts(&self, type_id: TypeId) -> bool {
+        type_id == TypeId::of::<Dump>()
+    }
+}
+
+/// Register an endpoint on [`Internal::Debug`] that dumps the tap when it receives [`Dump`]
+pub async fn register_debug_endpoint() -> Result<(), RegistrationError> {
+    static TAP: OnceLock<Tap> = OnceLock::new();
+    let tap = TAP.get_or_init(|| Tap {
+        tp: Endpoint::uninit(EndpointID::Internal(Internal::Debug)),
+    });
+
+    super::register_endpoint(tap, &tap.tp).await
+}
@@ -0,0 +1,135 @@
+//! Topic based publish/subscribe on top of comms endpoints
+use core::any::{Any, TypeId};
+use core::cell::Cell;
+
+use embassy_sync::once_lock::OnceLock;
+
+use super::{access, Data, Delivery, Endpoint, EndpointID, Message, Priority, RegistrationError, SendError};
+use crate::{IntrusiveList, Node, NodeContainer};
+
+/// Topic that subscribers register interest in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topic {
+    /// Every published message of a given type
+    Type(TypeId),
+
+    /// Messages published under a name, for events that share a message type
+    Named(&'static str),
+}
+
+impl Topic {
+    /// Topic for every published message of type `T`
+    pub fn of<T: Any>() -> Self {
+        Topic::Type(TypeId::of::<T>())
+    }
+}
+
+/// Subscription of an endpoint to a topic. An endpoint may hold any number of subscriptions
+pub struct Subscription {
+    node: Node,
+    topic: Topic,
+    endpoint: Cell<Option<&'static Endpoint>>,
+}
+
+impl NodeContainer for Subscription {
+    fn get_node(&self) -> &Node {
+        &self.node
+    }
+}
+
+impl Subscription {
+    /// use this when static initialization occurs, the subscribing endpoint is attached in [`subscribe`]
+    pub const fn new(topic: Topic) -> Self {
+        Self {
+            node: Node::uninit(),
+            topic,
+            endpoint: Cell::new(None),
+        }
+    }
+
+    /// Get the subscribed topic
+    pub fn topic(&self) -> Topic {
+        self.topic
+    }
+}
+
+static SUBSCRIPTIONS: OnceLock<IntrusiveList> = OnceLock::new();
+
+pub(super) fn init() {
+    SUBSCRIPTIONS.get_or_init(IntrusiveList::new);
+}
+
+/// Deliver messages published to the subscription's topic to `endpoint`. The endpoint must be registered to receive
+pub async fn subscribe(
+    endpoint: &'static Endpoint,
+    subscription: &'static Subscription,
+) -> Result<(), RegistrationError> {
+    SUBSCRIPTIONS.get().await.push(subscription)?;
+    subscription.endpoint.set(Some(endpoint));
+    Ok(())
+}
+
+/// Stop delivering messages for a subscription. The subscription may be used again afterwards
+pub async fn unsubscribe(subscription: &'static Subscription) -> Result<(), RegistrationError> {
+    SUBSCRIPTIONS.get().await.remove(subscription)?;
+    subscription.endpoint.set(None);
+    Ok(())
+}
+
+/// Publish a message to every endpoint subscribed to its type
+pub async fn publish<T: Any>(from: EndpointID, data: &T) -> Result<(), SendError> {
+    publish_topic(from, Topic::of::<T>(), Priority::Normal, data).await
+}
+
+/// Publish a message with the given priority to every endpoint subscribed to its type
+pub async fn publish_with_priority<T: Any>(from: EndpointID, priority: Priority, data: &T) -> Result<(), SendError> {
+    publish_topic(from, Topic::of::<T>(), priority, data).await
+}
+
+/// Publish a message to every endpoint subscribed to a named topic
+pub async fn publish_named(from: EndpointID, name: &'static str, data: &impl Any) -> Result<(), SendError> {
+    publish_topic(from, Topic::Named(name), Priority::Normal, data).await
+}
+
+async fn publish_topic(from: EndpointID, topic: Topic, priority: Priority, data: &impl Any) -> Result<(), SendError> {
+    let mut delivery = Delivery::default();
+
+    for subscription in SUBSCRIPTIONS.get().await.iter_only::<Subscription>() {
+        if subscription.topic != topic {
+            continue;
+        }
+
+        if let Some(endpoint) = subscription.endpoint.get() {
+            let message = Message {
+                from,
+                to: endpoint.id,
+                priority,
+                data: Data::new(data),
+            };
+            if access::check(&message) {
+                delivery.deliver(endpoint, &message);
+            } else {
+                delivery.refuse(&message);
+            }
+        }
+    }
+
+    delivery.result()
+}
+
+impl Endpoint {
+    /// Publish a message from this endpoint to every endpoint subscribed to its type
+    pub async fn publish<T: Any>(&self, data: &T) -> Result<(), SendError> {
+        publish(self.id, data).await
+    }
+
+    /// Publish a message from this endpoint with the given priority to every endpoint subscribed to its type
+    pub async fn publish_with_priority<T: Any>(&self, priority: Priority, data: &T) -> Result<(), SendError> {
+        publish_with_priority(self.id, priority, data).await
+    }
+
+    /// Publish a message from this endpoint to every endpoint subscribed to a named topic
+    pub async fn publish_named(&self, name: &'static str, data: &impl Any) -> Result<(), SendError> {
+        publish_named(self.id, name, data).await
+    }
+}
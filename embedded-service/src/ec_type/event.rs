@@ -0,0 +1,102 @@
+//! Host event queue
+//!
+//! Services post [`HostEvent`]s with [`post`]. The host transport, such as the eSPI service, takes them with
+//! [`receive`], adds them to the event queue in the memory map and rings a [`Doorbell`] so the host reads them.
+//! Posting waits while the queue is full, so events aren't lost when the host is slow to drain it.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::{Channel, TrySendError};
+
+use super::structure::EventEntry;
+
+/// Number of events buffered before they reach the memory map
+pub const QUEUE_SIZE: usize = 16;
+
+static EVENTS: Channel<CriticalSectionRawMutex, HostEvent, QUEUE_SIZE> = Channel::new();
+
+/// Event for the host
+///
+/// Service and event codes are platform defined and shared with the host driver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HostEvent {
+    /// Posting service
+    pub service: u16,
+    /// Event code
+    pub event: u16,
+    /// Event specific data
+    pub payload: u32,
+}
+
+impl From<HostEvent> for EventEntry {
+    fn from(event: HostEvent) -> Self {
+        Self {
+            service: event.service,
+            event: event.event,
+            payload: event.payload,
+        }
+    }
+}
+
+impl From<EventEntry> for HostEvent {
+    fn from(entry: EventEntry) -> Self {
+        Self {
+            service: entry.service,
+            event: entry.event,
+            payload: entry.payload,
+        }
+    }
+}
+
+/// Post an event for the host, waits while the queue is full
+pub async fn post(event: HostEvent) {
+    EVENTS.send(event).await;
+}
+
+/// Post an event for the host without waiting, returns the event if the queue is full
+pub fn try_post(event: HostEvent) -> Result<(), HostEvent> {
+    EVENTS.try_send(event).map_err(|TrySendError::Full(event)| event)
+}
+
+/// Wait for the next posted event, used by the host transport
+pub async fn receive() -> HostEvent {
+    EVENTS.receive().await
+}
+
+/// Notifies the host that events are pending, such as an SCI or an eSPI virtual wire
+#[allow(async_fn_in_trait)]
+pub trait Doorbell {
+    /// Error type
+    type Error;
+
+    /// Notify the host
+    async fn ring(&mut self) -> Result<(), Self::Error>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_post() {
+        let event = |event| HostEvent {
+            service: 1,
+            event,
+            payload: 0,
+        };
+
+        for i in 0..QUEUE_SIZE as u16 {
+            assert_eq!(try_post(event(i)), Ok(()));
+        }
+        assert_eq!(try_post(event(0xff)), Err(event(0xff)));
+
+        embassy_futures::block_on(async {
+            for i in 0..QUEUE_SIZE as u16 {
+                assert_eq!(receive().await, event(i));
+            }
+
+            post(event(0xff)).await;
+            assert_eq!(receive().await, event(0xff));
+        });
+    }
+}
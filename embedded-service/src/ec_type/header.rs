@@ -0,0 +1,215 @@
+//! C header describing the memory map for the host
+//!
+//! The header is generated from the [`ec_section!`] and [`ec_struct!`] declarations, so the host and the EC share one
+//! description of the layout. The checked-in `include/ecmemory.h` is compared against the generated header by the
+//! tests. Regenerate it from `examples/std` with:
+//!
+//! ```text
+//! cargo run --bin ec_memory_header > ../../embedded-service/include/ecmemory.h
+//! ```
+
+use core::fmt::{self, Write};
+
+use super::layout::LAYOUT_VERSION;
+use super::section::{Field, Section, SectionId};
+use super::structure::{self, ECMemory, Notifications, Version, EC_MEMMAP_VERSION};
+
+const fn field(name: &'static str, offset: usize, size: usize, ty: &'static str) -> Field {
+    Field {
+        name,
+        offset,
+        size,
+        ty,
+        unit: None,
+        message: false,
+    }
+}
+
+/// Fields of [`super::layout::Header`] as encoded in the map
+const HEADER_FIELDS: &[Field] = &[
+    field("ver", 0, 4, "Version"),
+    field("count", 4, 2, "u16"),
+    field("res0", 6, 2, "u16"),
+];
+
+/// Fields of [`super::layout::DirectoryEntry`] as encoded in the map
+const DIRECTORY_ENTRY_FIELDS: &[Field] = &[
+    field("id", 0, 2, "u16"),
+    field("major", 2, 1, "u8"),
+    field("minor", 3, 1, "u8"),
+    field("offset", 4, 2, "u16"),
+    field("length", 6, 2, "u16"),
+    field("count", 8, 2, "u16"),
+    field("res0", 10, 2, "u16"),
+];
+
+/// Formats a type name as an upper case C identifier, `EventQueue` becomes `EVENT_QUEUE`
+struct ScreamingSnake<'a>(&'a str);
+
+impl fmt::Display for ScreamingSnake<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, c) in self.0.char_indices() {
+            if i > 0 && c.is_ascii_uppercase() {
+                f.write_char('_')?;
+            }
+            f.write_char(c.to_ascii_uppercase())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn c_type(ty: &str) -> &str {
+    match ty {
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        _ => ty,
+    }
+}
+
+fn write_struct(out: &mut impl Write, name: &str, fields: &[Field]) -> fmt::Result {
+    writeln!(out, "typedef struct {{")?;
+    for field in fields {
+        write!(out, "    {} {};", c_type(field.ty), field.name)?;
+        if let Some(unit) = field.unit {
+            write!(out, " /* {unit} */")?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "}} {name};")?;
+    writeln!(out)
+}
+
+fn write_version(out: &mut impl Write, name: &str, version: Version) -> fmt::Result {
+    writeln!(
+        out,
+        "static const Version {name} = {{{}, {}, {}, {}}};",
+        version.major, version.minor, version.spin, version.res0
+    )?;
+    writeln!(out)
+}
+
+fn write_section<S: Section>(out: &mut impl Write) -> fmt::Result {
+    let name = ScreamingSnake(match S::ID {
+        SectionId::Capabilities => "Capabilities",
+        SectionId::TimeAlarm => "TimeAlarm",
+        SectionId::Battery => "Battery",
+        SectionId::Thermal => "Thermal",
+        SectionId::Fan => "Fan",
+        SectionId::Sensor => "Sensor",
+        SectionId::EventQueue => "EventQueue",
+        SectionId::EventEntry => "EventEntry",
+    });
+
+    writeln!(out, "#define EC_SECTION_{name} {}", S::ID as u16)?;
+    writeln!(out, "#define EC_SECTION_{name}_MAJOR {}", S::VERSION.major)?;
+    writeln!(out, "#define EC_SECTION_{name}_MINOR {}", S::VERSION.minor)?;
+    writeln!(out)?;
+    write_struct(out, name.0, S::FIELDS)
+}
+
+/// Write the C header for the memory map
+pub fn write_c_header(out: &mut impl Write) -> fmt::Result {
+    writeln!(
+        out,
+        "/* Generated from the embedded-services memory map declarations, do not edit */"
+    )?;
+    writeln!(out, "#pragma once")?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "#pragma pack(push, 1)")?;
+    writeln!(out)?;
+    write_struct(out, "Version", Version::FIELDS)?;
+
+    writeln!(
+        out,
+        "/* Maps from EC_LAYOUT_VERSION onward start with EcHeader and `count` EcDirectoryEntry, little-endian */"
+    )?;
+    write_version(out, "EC_LAYOUT_VERSION", LAYOUT_VERSION)?;
+    write_struct(out, "EcHeader", HEADER_FIELDS)?;
+    writeln!(
+        out,
+        "/* `count` instances of `length` bytes each, starting `offset` bytes from the start of the map */"
+    )?;
+    write_struct(out, "EcDirectoryEntry", DIRECTORY_ENTRY_FIELDS)?;
+
+    for id in SectionId::ALL {
+        match id {
+            SectionId::Capabilities => write_section::<structure::Capabilities>(out)?,
+            SectionId::TimeAlarm => write_section::<structure::TimeAlarm>(out)?,
+            SectionId::Battery => write_section::<structure::Battery>(out)?,
+            SectionId::Thermal => write_section::<structure::Thermal>(out)?,
+            SectionId::Fan => write_section::<structure::Fan>(out)?,
+            SectionId::Sensor => write_section::<structure::Sensor>(out)?,
+            SectionId::EventQueue => write_section::<structure::EventQueue>(out)?,
+            SectionId::EventEntry => write_section::<structure::EventEntry>(out)?,
+        }
+    }
+
+    writeln!(out, "/* Fixed layout without a directory */")?;
+    write_version(out, "EC_MEMMAP_VERSION", EC_MEMMAP_VERSION)?;
+    write_struct(out, "Notifications", Notifications::FIELDS)?;
+    write_struct(out, "ECMemory", ECMemory::FIELDS)?;
+
+    writeln!(out, "#pragma pack(pop)")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ec_type::layout::{DirectoryEntry, Header};
+
+    /// Compares the written header against the expected text without allocating
+    struct Compare<'a> {
+        expected: &'a str,
+        line: usize,
+    }
+
+    impl Write for Compare<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            match self.expected.strip_prefix(s) {
+                Some(rest) => {
+                    self.line += s.matches('\n').count();
+                    self.expected = rest;
+                    Ok(())
+                }
+                None => Err(fmt::Error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_c_header() {
+        let mut compare = Compare {
+            expected: include_str!("../../include/ecmemory.h"),
+            line: 1,
+        };
+
+        let result = write_c_header(&mut compare);
+        assert!(
+            result.is_ok() && compare.expected.is_empty(),
+            "include/ecmemory.h is out of date from line {}, regenerate it with the ec_memory_header example",
+            compare.line
+        );
+    }
+
+    #[test]
+    fn test_encoded_fields() {
+        for (fields, len) in [
+            (HEADER_FIELDS, Header::LEN),
+            (DIRECTORY_ENTRY_FIELDS, DirectoryEntry::LEN),
+        ] {
+            let mut offset = 0;
+            for field in fields {
+                assert_eq!(field.offset, offset, "{}", field.name);
+                offset += field.size;
+            }
+            assert_eq!(offset, len);
+        }
+    }
+}
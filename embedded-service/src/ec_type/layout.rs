@@ -0,0 +1,674 @@
+//! Memory map layout with a section directory
+//!
+//! The map starts with a [`Header`] followed by one [`DirectoryEntry`] per enabled section, then the sections
+//! themselves. Each platform picks the sections it supports with [`Layout::new`] and the host discovers them through
+//! [`Directory`]. See [`super::section`] for the rules on changing sections compatibly.
+//!
+//! A section may hold several instances, such as one per battery or fan, stored back to back. Instances are
+//! addressed by index, which matches the bit position in the corresponding [`structure::Capabilities`] mask.
+//!
+//! The header and directory are little-endian.
+
+use core::ops::Range;
+
+use super::event::HostEvent;
+use super::message::{EventQueueMessage, Indexed};
+use super::section::{Section, SectionId, SectionVersion};
+use super::structure::{self, Version};
+use super::Error;
+
+/// Memory map version, maps from 0.2 onward start with a directory
+pub const LAYOUT_VERSION: Version = Version {
+    major: 0,
+    minor: 2,
+    spin: 0,
+    res0: 0,
+};
+
+/// Maximum number of sections in a layout
+pub const MAX_SECTIONS: usize = 8;
+
+/// Sections start on this alignment
+const SECTION_ALIGN: usize = 4;
+
+/// Memory map header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    /// Memory map version
+    pub ver: Version,
+    /// Number of directory entries following the header
+    pub count: u16,
+}
+
+impl Header {
+    /// Encoded length in bytes
+    pub const LEN: usize = 8;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&[self.ver.major, self.ver.minor, self.ver.spin, self.ver.res0]);
+        buf[4..6].copy_from_slice(&self.count.to_le_bytes());
+        buf[6..8].fill(0);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        Self {
+            ver: Version {
+                major: buf[0],
+                minor: buf[1],
+                spin: buf[2],
+                res0: buf[3],
+            },
+            count: u16::from_le_bytes([buf[4], buf[5]]),
+        }
+    }
+}
+
+/// Directory entry describing one section
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DirectoryEntry {
+    /// Raw section ID, may not be a known [`SectionId`] for maps written by newer firmware
+    pub id: u16,
+    /// Section version
+    pub version: SectionVersion,
+    /// Offset from the start of the map
+    pub offset: u16,
+    /// Length of one instance in bytes
+    pub length: u16,
+    /// Number of instances
+    pub count: u16,
+}
+
+impl DirectoryEntry {
+    /// Encoded length in bytes
+    pub const LEN: usize = 12;
+
+    /// Section ID
+    pub fn section_id(&self) -> Result<SectionId, Error> {
+        SectionId::try_from(self.id)
+    }
+
+    /// Range of all instances in the map
+    pub fn range(&self) -> Range<usize> {
+        self.offset as usize..self.offset as usize + self.length as usize * self.count as usize
+    }
+
+    /// Range of one instance in the map
+    pub fn instance(&self, index: usize) -> Option<Range<usize>> {
+        if index >= self.count as usize {
+            return None;
+        }
+
+        let start = self.offset as usize + index * self.length as usize;
+        Some(start..start + self.length as usize)
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&self.id.to_le_bytes());
+        buf[2] = self.version.major;
+        buf[3] = self.version.minor;
+        buf[4..6].copy_from_slice(&self.offset.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.length.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.count.to_le_bytes());
+        buf[10..12].fill(0);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        Self {
+            id: u16::from_le_bytes([buf[0], buf[1]]),
+            version: SectionVersion {
+                major: buf[2],
+                minor: buf[3],
+            },
+            offset: u16::from_le_bytes([buf[4], buf[5]]),
+            length: u16::from_le_bytes([buf[6], buf[7]]),
+            count: u16::from_le_bytes([buf[8], buf[9]]),
+        }
+    }
+}
+
+/// Version and length of a section
+fn section_info(id: SectionId) -> (SectionVersion, usize) {
+    fn info<S: Section>() -> (SectionVersion, usize) {
+        (S::VERSION, size_of::<S>())
+    }
+
+    match id {
+        SectionId::Capabilities => info::<structure::Capabilities>(),
+        SectionId::TimeAlarm => info::<structure::TimeAlarm>(),
+        SectionId::Battery => info::<structure::Battery>(),
+        SectionId::Thermal => info::<structure::Thermal>(),
+        SectionId::Fan => info::<structure::Fan>(),
+        SectionId::Sensor => info::<structure::Sensor>(),
+        SectionId::EventQueue => info::<structure::EventQueue>(),
+        SectionId::EventEntry => info::<structure::EventEntry>(),
+    }
+}
+
+/// Number of instances needed to cover every bit set in a capabilities mask
+pub fn instances(mask: u16) -> usize {
+    (u16::BITS - mask.leading_zeros()) as usize
+}
+
+const fn align(offset: usize) -> usize {
+    offset.next_multiple_of(SECTION_ALIGN)
+}
+
+/// Sections enabled on a platform and their location in the map
+#[derive(Clone, Debug)]
+pub struct Layout {
+    entries: heapless::Vec<DirectoryEntry, MAX_SECTIONS>,
+    size: usize,
+}
+
+impl Layout {
+    /// Lay out one instance of each of the given sections in order after the directory
+    pub fn new(sections: &[SectionId]) -> Result<Self, Error> {
+        let mut instances = heapless::Vec::<_, MAX_SECTIONS>::new();
+        for id in sections {
+            instances.push((*id, 1)).map_err(|_| Error::TooManySections)?;
+        }
+
+        Self::with_instances(&instances)
+    }
+
+    /// Lay out the given sections and their number of instances in order after the directory
+    ///
+    /// Sections with no instances are left out, and don't count as duplicates of an enabled section.
+    pub fn with_instances(sections: &[(SectionId, usize)]) -> Result<Self, Error> {
+        let mut entries = heapless::Vec::<DirectoryEntry, MAX_SECTIONS>::new();
+        let enabled = sections.iter().filter(|(_, count)| *count > 0).count();
+        let mut offset = align(Header::LEN + enabled * DirectoryEntry::LEN);
+        for (id, count) in sections {
+            if *count == 0 {
+                continue;
+            }
+
+            if entries.iter().any(|entry| entry.id == *id as u16) {
+                return Err(Error::DuplicateSection);
+            }
+
+            let (version, length) = section_info(*id);
+            let entry = DirectoryEntry {
+                id: *id as u16,
+                version,
+                offset: offset.try_into().map_err(|_| Error::LayoutTooLarge)?,
+                length: length.try_into().map_err(|_| Error::LayoutTooLarge)?,
+                count: (*count).try_into().map_err(|_| Error::LayoutTooLarge)?,
+            };
+            entries.push(entry).map_err(|_| Error::TooManySections)?;
+            offset = align(offset + length * count);
+        }
+
+        if offset > u16::MAX as usize {
+            return Err(Error::LayoutTooLarge);
+        }
+
+        // The indices and entries only work together, and one entry is always left empty
+        let layout = Self { entries, size: offset };
+        let slots = layout.count(SectionId::EventEntry);
+        if (layout.count(SectionId::EventQueue) > 0) != (slots > 0) || slots == 1 {
+            return Err(Error::InvalidEventQueue);
+        }
+
+        Ok(layout)
+    }
+
+    /// Directory entries in map order
+    pub fn entries(&self) -> &[DirectoryEntry] {
+        &self.entries
+    }
+
+    /// Total size of the map in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Directory entry of a section, if enabled
+    pub fn entry(&self, id: SectionId) -> Option<&DirectoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id as u16)
+    }
+
+    /// Number of instances of a section, 0 if disabled
+    pub fn count(&self, id: SectionId) -> usize {
+        self.entry(id).map_or(0, |entry| entry.count as usize)
+    }
+
+    /// Find the section containing a map offset, returns the section, the instance index and the offset within it
+    pub fn locate(&self, offset: usize) -> Option<(SectionId, usize, usize)> {
+        let entry = self.entries.iter().find(|entry| entry.range().contains(&offset))?;
+        let offset = offset - entry.offset as usize;
+        let length = entry.length as usize;
+        Some((entry.section_id().ok()?, offset / length, offset % length))
+    }
+}
+
+/// Memory map shared with the host, written by the EC
+pub struct MemoryMap<'a> {
+    layout: Layout,
+    buf: &'a mut [u8],
+}
+
+impl<'a> MemoryMap<'a> {
+    /// Write the header and directory to `buf` and clear all sections
+    pub fn new(layout: Layout, buf: &'a mut [u8]) -> Result<Self, Error> {
+        if buf.len() < layout.size() {
+            return Err(Error::BufferTooSmall);
+        }
+
+        buf.fill(0);
+        Header {
+            ver: LAYOUT_VERSION,
+            count: layout.entries().len() as u16,
+        }
+        .encode(&mut buf[..Header::LEN]);
+
+        for (i, entry) in layout.entries().iter().enumerate() {
+            entry.encode(&mut buf[Header::LEN + i * DirectoryEntry::LEN..]);
+        }
+
+        Ok(Self { layout, buf })
+    }
+
+    /// Layout of the map
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Raw map contents
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.layout.size()]
+    }
+
+    fn range<S: Section>(&self, index: usize) -> Result<Range<usize>, Error> {
+        self.layout
+            .entry(S::ID)
+            .ok_or(Error::SectionDisabled)?
+            .instance(index)
+            .ok_or(Error::InvalidIndex)
+    }
+
+    /// Read the first instance of a section
+    pub fn section<S: Section>(&self) -> Result<S, Error> {
+        self.instance(0)
+    }
+
+    /// Read one instance of a section
+    pub fn instance<S: Section>(&self, index: usize) -> Result<S, Error> {
+        Ok(S::read(&self.buf[self.range::<S>(index)?]))
+    }
+
+    /// Update a field of the first instance of a section based on a message
+    pub fn update<S: Section>(&mut self, msg: &S::Message) -> Result<(), Error> {
+        self.update_instance::<S>(0, msg)
+    }
+
+    /// Update a field of one instance of a section based on a message
+    pub fn update_instance<S: Section>(&mut self, index: usize, msg: &S::Message) -> Result<(), Error> {
+        let range = self.range::<S>(index)?;
+        let mut section = S::read(&self.buf[range.clone()]);
+        section.update(msg)?;
+        section.write(&mut self.buf[range])
+    }
+
+    /// Convert from map offset and length to a message of section `S` and the instance it belongs to
+    /// Modifies offset and length
+    pub fn decode<S: Section>(&self, offset: &mut usize, length: &mut usize) -> Result<Indexed<S::Message>, Error> {
+        let (id, index, local_offset) = self.layout.locate(*offset).ok_or(Error::InvalidLocation)?;
+        if id != S::ID {
+            return Err(Error::InvalidLocation);
+        }
+
+        let (message, size) = self.instance::<S>(index)?.decode(local_offset)?;
+
+        *length = length.checked_sub(size).ok_or(Error::InvalidLocation)?;
+        *offset += size;
+        Ok(Indexed {
+            index: index as u8,
+            message,
+        })
+    }
+
+    /// Indices and number of slots of the event queue
+    fn event_queue(&self) -> Result<(usize, usize, usize), Error> {
+        let queue = self.section::<structure::EventQueue>()?;
+        // The layout guarantees at least two slots when the indices are enabled, the host may write anything
+        let slots = self.layout.count(SectionId::EventEntry);
+        Ok((queue.producer as usize % slots, queue.consumer as usize % slots, slots))
+    }
+
+    /// Number of events the host hasn't read yet
+    pub fn pending_events(&self) -> Result<usize, Error> {
+        let (producer, consumer, slots) = self.event_queue()?;
+        Ok((producer + slots - consumer) % slots)
+    }
+
+    /// Add an event to the host event queue, the host is notified separately
+    pub fn push_event(&mut self, event: HostEvent) -> Result<(), Error> {
+        let (producer, consumer, slots) = self.event_queue()?;
+        let next = (producer + 1) % slots;
+        if next == consumer {
+            return Err(Error::QueueFull);
+        }
+
+        let range = self.range::<structure::EventEntry>(producer)?;
+        structure::EventEntry::from(event).write(&mut self.buf[range])?;
+        self.update::<structure::EventQueue>(&EventQueueMessage::Producer(next as u16))
+    }
+}
+
+/// Directory of a memory map, used by the host to discover sections
+pub struct Directory<'a> {
+    header: Header,
+    map: &'a [u8],
+}
+
+impl<'a> Directory<'a> {
+    /// Parse the header and directory at the start of a map
+    pub fn parse(map: &'a [u8]) -> Result<Self, Error> {
+        if map.len() < Header::LEN {
+            return Err(Error::InvalidDirectory);
+        }
+
+        let header = Header::decode(map);
+        if header.ver.major != LAYOUT_VERSION.major || header.ver.minor < LAYOUT_VERSION.minor {
+            return Err(Error::InvalidDirectory);
+        }
+
+        if map.len() < Header::LEN + header.count as usize * DirectoryEntry::LEN {
+            return Err(Error::InvalidDirectory);
+        }
+
+        Ok(Self { header, map })
+    }
+
+    /// Map header
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    /// All directory entries, including sections unknown to this build
+    pub fn entries(&self) -> impl Iterator<Item = DirectoryEntry> + 'a {
+        let map = self.map;
+        (0..self.header.count as usize)
+            .map(move |i| DirectoryEntry::decode(&map[Header::LEN + i * DirectoryEntry::LEN..]))
+    }
+
+    /// Find a section with a version compatible with `known`
+    pub fn find(&self, id: SectionId, known: SectionVersion) -> Option<DirectoryEntry> {
+        self.entries()
+            .find(|entry| entry.id == id as u16 && entry.version.is_compatible(known))
+    }
+
+    /// Read the first instance of a section, fields the EC doesn't provide are left at their default
+    pub fn read<S: Section>(&self) -> Result<S, Error> {
+        self.read_instance(0)
+    }
+
+    /// Events the host hasn't read yet, oldest first
+    ///
+    /// After reading them the host writes the current producer index to the consumer index.
+    pub fn events(&self) -> Result<impl Iterator<Item = HostEvent> + '_, Error> {
+        let queue = self.read::<structure::EventQueue>()?;
+        let slots = self
+            .find(SectionId::EventEntry, structure::EventEntry::VERSION)
+            .ok_or(Error::SectionDisabled)?
+            .count as usize;
+        if slots < 2 {
+            return Err(Error::InvalidEventQueue);
+        }
+
+        let (producer, consumer) = (queue.producer as usize % slots, queue.consumer as usize % slots);
+        let pending = (producer + slots - consumer) % slots;
+        Ok((0..pending).filter_map(move |i| {
+            self.read_instance::<structure::EventEntry>((consumer + i) % slots)
+                .ok()
+                .map(HostEvent::from)
+        }))
+    }
+
+    /// Read one instance of a section, fields the EC doesn't provide are left at their default
+    pub fn read_instance<S: Section>(&self, index: usize) -> Result<S, Error> {
+        let entry = self.find(S::ID, S::VERSION).ok_or(Error::SectionDisabled)?;
+        let range = entry.instance(index).ok_or(Error::InvalidIndex)?;
+        let bytes = self.map.get(range).ok_or(Error::InvalidDirectory)?;
+        Ok(S::read(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ec_type::message::{BatteryMessage, FanMessage, ThermalMessage};
+    use crate::ec_type::structure::{Battery, Capabilities, Fan, Sensor, Thermal};
+    use crate::ec_type::units::{DeciKelvin, MilliWattHours, Rpm};
+
+    #[test]
+    fn test_layout() {
+        let layout = Layout::new(&[SectionId::Battery, SectionId::Thermal]).unwrap();
+        let battery = *layout.entry(SectionId::Battery).unwrap();
+        let thermal = *layout.entry(SectionId::Thermal).unwrap();
+
+        assert_eq!(battery.offset as usize, Header::LEN + 2 * DirectoryEntry::LEN);
+        assert_eq!(battery.length as usize, size_of::<Battery>());
+        assert_eq!(thermal.offset as usize, align(battery.range().end));
+        assert_eq!(layout.size(), align(thermal.range().end));
+        assert!(layout.entry(SectionId::TimeAlarm).is_none());
+
+        assert_eq!(layout.locate(0), None);
+        assert_eq!(
+            layout.locate(battery.offset as usize + 4),
+            Some((SectionId::Battery, 0, 4))
+        );
+        assert_eq!(layout.locate(thermal.offset as usize), Some((SectionId::Thermal, 0, 0)));
+        assert_eq!(layout.locate(layout.size()), None);
+
+        assert_eq!(
+            Layout::new(&[SectionId::Battery, SectionId::Battery]).unwrap_err(),
+            Error::DuplicateSection
+        );
+        assert_eq!(
+            Layout::new(&[SectionId::Battery; MAX_SECTIONS + 1]).unwrap_err(),
+            Error::TooManySections
+        );
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let layout = Layout::new(&[SectionId::Capabilities, SectionId::Battery]).unwrap();
+        let mut buf = [0xffu8; 256];
+        assert_eq!(
+            MemoryMap::new(layout.clone(), &mut buf[..layout.size() - 1]).err(),
+            Some(Error::BufferTooSmall)
+        );
+
+        let mut map = MemoryMap::new(layout, &mut buf).unwrap();
+        map.update::<Battery>(&BatteryMessage::CycleCount(42)).unwrap();
+        assert_eq!(
+            map.update::<Thermal>(&ThermalMessage::Tmp1Val(DeciKelvin(1))),
+            Err(Error::SectionDisabled)
+        );
+        assert_eq!({ map.section::<Battery>().unwrap().cycle_count }, 42);
+
+        // host write to the cycle count is decoded back into a message
+        let base = map.layout().entry(SectionId::Battery).unwrap().offset as usize;
+        let mut offset = base + core::mem::offset_of!(Battery, cycle_count);
+        let mut length = 6;
+        assert_eq!(
+            map.decode::<Battery>(&mut offset, &mut length),
+            Ok(Indexed {
+                index: 0,
+                message: BatteryMessage::CycleCount(42)
+            })
+        );
+        assert_eq!(offset, base + core::mem::offset_of!(Battery, state));
+        assert_eq!(length, 2);
+        assert_eq!(
+            map.decode::<Battery>(&mut offset, &mut length),
+            Err(Error::InvalidLocation)
+        );
+
+        // the host discovers the sections through the directory
+        let directory = Directory::parse(map.as_bytes()).unwrap();
+        assert_eq!(directory.header().ver, LAYOUT_VERSION);
+        assert_eq!(directory.entries().count(), 2);
+        assert_eq!({ directory.read::<Battery>().unwrap().cycle_count }, 42);
+        assert_eq!(directory.read::<Thermal>().err(), Some(Error::SectionDisabled));
+        assert!(directory
+            .find(SectionId::Capabilities, SectionVersion { major: 2, minor: 0 })
+            .is_none());
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let layout = Layout::new(&[SectionId::Capabilities]).unwrap();
+        let mut buf = [0u8; 64];
+        let mut map = MemoryMap::new(layout, &mut buf).unwrap();
+        map.update::<Capabilities>(&crate::ec_type::message::CapabilitiesMessage::Events(7))
+            .unwrap();
+        map.update::<Capabilities>(&crate::ec_type::message::CapabilitiesMessage::DebugMask(3))
+            .unwrap();
+
+        // an older EC providing a shorter section, later fields read as absent
+        let mut entry = DirectoryEntry::decode(&buf[Header::LEN..]);
+        entry.length = core::mem::offset_of!(Capabilities, debug_mask) as u16;
+        entry.encode(&mut buf[Header::LEN..]);
+
+        let capabilities = Directory::parse(&buf).unwrap().read::<Capabilities>().unwrap();
+        assert_eq!({ capabilities.events }, 7);
+        assert_eq!({ capabilities.debug_mask }, 0);
+
+        // entries from newer firmware are listed but not known
+        let mut entry = DirectoryEntry::decode(&buf[Header::LEN..]);
+        entry.id = 0x100;
+        entry.encode(&mut buf[Header::LEN..]);
+        let directory = Directory::parse(&buf).unwrap();
+        assert_eq!(
+            directory.entries().next().unwrap().section_id(),
+            Err(Error::UnknownSection)
+        );
+        assert_eq!(directory.read::<Capabilities>().err(), Some(Error::SectionDisabled));
+
+        // maps without a directory are rejected
+        buf[..4].copy_from_slice(&[0, 1, 0, 0]);
+        assert_eq!(Directory::parse(&buf).err(), Some(Error::InvalidDirectory));
+    }
+
+    #[test]
+    fn test_instances() {
+        assert_eq!(instances(0), 0);
+        assert_eq!(instances(0b1), 1);
+        assert_eq!(instances(0b101), 3);
+
+        // dual battery, three fans and no sensors
+        let layout = Layout::with_instances(&[
+            (SectionId::Battery, instances(0b11)),
+            (SectionId::Fan, instances(0b111)),
+            (SectionId::Sensor, instances(0)),
+        ])
+        .unwrap();
+        assert_eq!(layout.entries().len(), 2);
+        assert_eq!(layout.count(SectionId::Battery), 2);
+        assert_eq!(layout.count(SectionId::Fan), 3);
+        assert_eq!(layout.count(SectionId::Sensor), 0);
+
+        let fan = *layout.entry(SectionId::Fan).unwrap();
+        assert_eq!(fan.range().len(), 3 * size_of::<Fan>());
+        let second_fan = fan.instance(1).unwrap();
+        assert_eq!(
+            layout.locate(second_fan.start + core::mem::offset_of!(Fan, cur_rpm)),
+            Some((SectionId::Fan, 1, core::mem::offset_of!(Fan, cur_rpm)))
+        );
+        assert!(fan.instance(3).is_none());
+
+        let mut buf = [0u8; 512];
+        let mut map = MemoryMap::new(layout, &mut buf).unwrap();
+        map.update::<Battery>(&BatteryMessage::RemainCap(MilliWattHours(10)))
+            .unwrap();
+        map.update_instance::<Battery>(1, &BatteryMessage::RemainCap(MilliWattHours(20)))
+            .unwrap();
+        map.update_instance::<Fan>(1, &FanMessage::CurRpm(Rpm(3000))).unwrap();
+        assert_eq!(
+            map.update_instance::<Fan>(3, &FanMessage::CurRpm(Rpm(1))),
+            Err(Error::InvalidIndex)
+        );
+        assert_eq!(map.instance::<Sensor>(0).err(), Some(Error::SectionDisabled));
+
+        // host write to the second fan is decoded with its index
+        let mut offset = second_fan.start + core::mem::offset_of!(Fan, cur_rpm);
+        let mut length = 4;
+        assert_eq!(
+            map.decode::<Fan>(&mut offset, &mut length),
+            Ok(Indexed {
+                index: 1,
+                message: FanMessage::CurRpm(Rpm(3000))
+            })
+        );
+
+        let directory = Directory::parse(map.as_bytes()).unwrap();
+        assert_eq!({ directory.read::<Battery>().unwrap().remain_cap }, 10);
+        assert_eq!({ directory.read_instance::<Battery>(1).unwrap().remain_cap }, 20);
+        assert_eq!({ directory.read_instance::<Fan>(1).unwrap().cur_rpm }, 3000);
+        assert_eq!({ directory.read_instance::<Fan>(0).unwrap().cur_rpm }, 0);
+        assert_eq!(directory.read_instance::<Fan>(3).err(), Some(Error::InvalidIndex));
+
+        // only enabled sections can be duplicates
+        let layout = Layout::with_instances(&[(SectionId::Fan, 0), (SectionId::Fan, 2)]).unwrap();
+        assert_eq!(layout.count(SectionId::Fan), 2);
+        assert_eq!(
+            Layout::with_instances(&[(SectionId::Fan, 2), (SectionId::Fan, 1)]).err(),
+            Some(Error::DuplicateSection)
+        );
+    }
+
+    #[test]
+    fn test_event_queue() {
+        use crate::ec_type::message::EventQueueMessage;
+        use crate::ec_type::structure::{EventEntry, EventQueue};
+
+        assert_eq!(
+            Layout::with_instances(&[(SectionId::EventEntry, 4)]).unwrap_err(),
+            Error::InvalidEventQueue
+        );
+        assert_eq!(
+            Layout::with_instances(&[(SectionId::EventQueue, 1), (SectionId::EventEntry, 1)]).unwrap_err(),
+            Error::InvalidEventQueue
+        );
+
+        let layout = Layout::with_instances(&[(SectionId::EventQueue, 1), (SectionId::EventEntry, 3)]).unwrap();
+        let mut buf = [0u8; 128];
+        let mut map = MemoryMap::new(layout, &mut buf).unwrap();
+        let event = |event| HostEvent {
+            service: 2,
+            event,
+            payload: 0x1234,
+        };
+
+        // one slot is always left empty
+        map.push_event(event(1)).unwrap();
+        map.push_event(event(2)).unwrap();
+        assert_eq!(map.push_event(event(3)), Err(Error::QueueFull));
+        assert_eq!(map.pending_events(), Ok(2));
+
+        let directory = Directory::parse(map.as_bytes()).unwrap();
+        assert!(directory.events().unwrap().eq([event(1), event(2)]));
+        assert_eq!({ directory.read_instance::<EventEntry>(1).unwrap().event }, 2);
+
+        // the host consumes the events, the queue wraps around
+        let producer = map.section::<EventQueue>().unwrap().producer;
+        map.update::<EventQueue>(&EventQueueMessage::Consumer(producer))
+            .unwrap();
+        assert_eq!(map.pending_events(), Ok(0));
+        map.push_event(event(3)).unwrap();
+        map.push_event(event(4)).unwrap();
+        assert_eq!({ map.section::<EventQueue>().unwrap().producer }, 1);
+
+        let directory = Directory::parse(map.as_bytes()).unwrap();
+        assert!(directory.events().unwrap().eq([event(3), event(4)]));
+
+        let layout = Layout::new(&[SectionId::Battery]).unwrap();
+        let mut buf = [0u8; 128];
+        let mut map = MemoryMap::new(layout, &mut buf).unwrap();
+        assert_eq!(map.push_event(event(1)), Err(Error::SectionDisabled));
+    }
+}
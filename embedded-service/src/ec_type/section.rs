@@ -0,0 +1,282 @@
+//! Declarative memory map sections
+//!
+//! Each section of the memory map is described once with [`ec_section!`], which generates the packed struct, the
+//! message enum with one variant per field and the [`Section`] implementation that maps between them.
+//!
+//! # Compatibility
+//!
+//! The host finds sections through the directory at the head of the map, see [`super::layout`]. To keep existing
+//! hosts working when a section changes:
+//! - New fields are only appended to the end of a section and bump its minor version. The directory holds the
+//!   section length, so hosts read the fields they know and treat fields past the length as absent.
+//! - Removing, reordering or resizing fields bumps the major version. Hosts skip sections with a major version they
+//!   don't know.
+//! - New sections get a new [`SectionId`], hosts ignore IDs they don't know. IDs are never reused.
+
+use super::Error;
+
+/// Section identifier in the directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u16)]
+pub enum SectionId {
+    /// EC capabilities, read-only for the host
+    Capabilities = 1,
+    /// Time and alarm
+    TimeAlarm = 2,
+    /// Battery
+    Battery = 3,
+    /// Thermal
+    Thermal = 4,
+    /// Fan, one instance per fan
+    Fan = 5,
+    /// Temperature sensor, one instance per sensor
+    Sensor = 6,
+    /// Host event queue indices
+    EventQueue = 7,
+    /// Host event queue entry, one instance per slot
+    EventEntry = 8,
+}
+
+impl SectionId {
+    /// All sections
+    pub const ALL: &'static [SectionId] = &[
+        SectionId::Capabilities,
+        SectionId::TimeAlarm,
+        SectionId::Battery,
+        SectionId::Thermal,
+        SectionId::Fan,
+        SectionId::Sensor,
+        SectionId::EventQueue,
+        SectionId::EventEntry,
+    ];
+}
+
+impl TryFrom<u16> for SectionId {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        SectionId::ALL
+            .iter()
+            .copied()
+            .find(|id| *id as u16 == value)
+            .ok_or(Error::UnknownSection)
+    }
+}
+
+/// Section layout version
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SectionVersion {
+    /// Incremented on incompatible changes
+    pub major: u8,
+    /// Incremented when fields are appended
+    pub minor: u8,
+}
+
+impl SectionVersion {
+    /// True if a host that knows `known` can read a section with this version
+    pub fn is_compatible(&self, known: SectionVersion) -> bool {
+        self.major == known.major
+    }
+}
+
+/// Location of a field within a section
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// Field name
+    pub name: &'static str,
+    /// Offset in bytes from the start of the section
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// Declared type
+    pub ty: &'static str,
+    /// Unit from [`super::units`] carried by the message, if any
+    pub unit: Option<&'static str>,
+    /// True if the field has a message, reserved fields don't
+    pub message: bool,
+}
+
+/// Memory map section
+///
+/// # Safety
+/// The implementing type must be a packed struct of integer fields so that any byte pattern is a valid value.
+/// [`ec_section!`] upholds this for the types used in the memory map.
+pub unsafe trait Section: Copy + Default {
+    /// Message carrying the value of a single field
+    type Message;
+
+    /// Directory identifier
+    const ID: SectionId;
+
+    /// Layout version
+    const VERSION: SectionVersion;
+
+    /// All fields in memory order
+    const FIELDS: &'static [Field];
+
+    /// Write the field carried by a message, fails if the value doesn't fit the field
+    fn update(&mut self, msg: &Self::Message) -> Result<(), Error>;
+
+    /// Read the field starting `offset` bytes into the section, returns the message and the size of the field
+    fn decode(&self, offset: usize) -> Result<(Self::Message, usize), Error>;
+
+    /// Read the section from `bytes`, fields past the end of `bytes` are left at their default
+    fn read(bytes: &[u8]) -> Self {
+        let mut section = Self::default();
+        let len = bytes.len().min(size_of::<Self>());
+
+        // SAFETY: at most `size_of::<Self>()` bytes are copied and any byte pattern is a valid section
+        unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut section as *mut Self as *mut u8, len) };
+        section
+    }
+
+    /// Write the section to the start of `bytes`
+    fn write(&self, bytes: &mut [u8]) -> Result<(), Error> {
+        if bytes.len() < size_of::<Self>() {
+            return Err(Error::InvalidLocation);
+        }
+
+        // SAFETY: length checked above
+        unsafe { core::ptr::write_unaligned(bytes.as_mut_ptr() as *mut Self, *self) };
+        Ok(())
+    }
+}
+
+/// Define a memory map section and its message
+///
+/// Fields without a message variant are reserved, they are part of the layout but can't be read or written
+/// through messages. Field types must be integers or packed structs of integers. A variant can carry a type from
+/// [`super::units`] instead of the field type, the value is then converted with `TryFrom` in both directions.
+///
+/// ```ignore
+/// ec_section! {
+///     #[section(id = Fan, major = 1, minor = 0)]
+///     pub struct Fan => FanMessage {
+///         rpm: u32 => Rpm(units::Rpm),
+///         res0: u32,
+///     }
+/// }
+/// ```
+macro_rules! ec_section {
+    (@message $variant:ident) => {
+        true
+    };
+    (@message) => {
+        false
+    };
+    (@unit $unit:ty) => {
+        Some(stringify!($unit))
+    };
+    (@unit) => {
+        None
+    };
+    (@type $ty:ty) => {
+        $ty
+    };
+    (@type $ty:ty, $unit:ty) => {
+        $unit
+    };
+    (@convert $value:ident) => {
+        $value
+    };
+    (@convert $value:ident, $unit:ty) => {
+        TryFrom::try_from($value)?
+    };
+    (
+        #[section(id = $id:ident, major = $major:literal, minor = $minor:literal)]
+        $(#[$meta:meta])*
+        pub struct $name:ident => $message:ident {
+            $($field:ident : $ty:ty $(=> $variant:ident $(($unit:ty))?)?),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(missing_docs)]
+        #[repr(C, packed)]
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        #[allow(missing_docs)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum $message {
+            $($($variant(ec_section!(@type $ty $(, $unit)?)),)?)*
+        }
+
+        // SAFETY: packed struct of integer fields
+        unsafe impl $crate::ec_type::section::Section for $name {
+            type Message = $message;
+
+            const ID: $crate::ec_type::section::SectionId = $crate::ec_type::section::SectionId::$id;
+
+            const VERSION: $crate::ec_type::section::SectionVersion = $crate::ec_type::section::SectionVersion {
+                major: $major,
+                minor: $minor,
+            };
+
+            const FIELDS: &'static [$crate::ec_type::section::Field] = &[$(
+                $crate::ec_type::section::Field {
+                    name: stringify!($field),
+                    offset: core::mem::offset_of!($name, $field),
+                    size: size_of::<$ty>(),
+                    ty: stringify!($ty),
+                    unit: ec_section!(@unit $($($unit)?)?),
+                    message: ec_section!(@message $($variant)?),
+                },
+            )*];
+
+            fn update(&mut self, msg: &Self::Message) -> Result<(), $crate::ec_type::Error> {
+                match *msg {
+                    $($($message::$variant(value) => self.$field = ec_section!(@convert value $(, $unit)?),)?)*
+                }
+
+                Ok(())
+            }
+
+            fn decode(&self, offset: usize) -> Result<(Self::Message, usize), $crate::ec_type::Error> {
+                $($(
+                    if offset == core::mem::offset_of!($name, $field) {
+                        let value = self.$field;
+                        return Ok(($message::$variant(ec_section!(@convert value $(, $unit)?)), size_of_val(&value)));
+                    }
+                )?)*
+
+                Err($crate::ec_type::Error::InvalidLocation)
+            }
+        }
+    };
+}
+
+/// Define a packed struct used by the memory map outside of sections, with a field table like [`Section::FIELDS`]
+macro_rules! ec_struct {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $($field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(missing_docs)]
+        #[repr(C, packed)]
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl $name {
+            /// All fields in memory order
+            pub const FIELDS: &'static [$crate::ec_type::section::Field] = &[$(
+                $crate::ec_type::section::Field {
+                    name: stringify!($field),
+                    offset: core::mem::offset_of!($name, $field),
+                    size: size_of::<$ty>(),
+                    ty: stringify!($ty),
+                    unit: None,
+                    message: false,
+                },
+            )*];
+        }
+    };
+}
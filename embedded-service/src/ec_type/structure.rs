@@ -1,4 +1,9 @@
 //! EC Internal Data Structures
+//!
+//! Sections are declared with `ec_section!`, which also generates their messages, see [`super::section`]. The C
+//! header for the host is generated from these declarations, see [`super::header`].
+
+use super::units::{DeciKelvin, MilliAmps, MilliVolts, MilliWattHours, MilliWatts, Rpm, Seconds};
 
 #[allow(missing_docs)]
 pub const EC_MEMMAP_VERSION: Version = Version {
@@ -8,124 +13,170 @@ pub const EC_MEMMAP_VERSION: Version = Version {
     res0: 0,
 };
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Version {
-    pub major: u8,
-    pub minor: u8,
-    pub spin: u8,
-    pub res0: u8,
+ec_struct! {
+    #[derive(PartialEq, Eq)]
+    pub struct Version {
+        major: u8,
+        minor: u8,
+        spin: u8,
+        res0: u8,
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Capabilities {
-    pub events: u32,
-    pub fw_version: Version,
-    pub secure_state: u8,
-    pub boot_status: u8,
-    pub fan_mask: u8,
-    pub battery_mask: u8,
-    pub temp_mask: u16,
-    pub key_mask: u16,
-    pub debug_mask: u16,
-    pub res0: u16,
+ec_section! {
+    #[section(id = Capabilities, major = 1, minor = 0)]
+    pub struct Capabilities => CapabilitiesMessage {
+        events: u32 => Events,
+        fw_version: Version => FwVersion,
+        secure_state: u8 => SecureState,
+        boot_status: u8 => BootStatus,
+        fan_mask: u8 => FanMask,
+        battery_mask: u8 => BatteryMask,
+        temp_mask: u16 => TempMask,
+        key_mask: u16 => KeyMask,
+        debug_mask: u16 => DebugMask,
+        res0: u16,
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct TimeAlarm {
-    pub events: u32,
-    pub capability: u32,
-    pub year: u16,
-    pub month: u8,
-    pub day: u8,
-    pub hour: u8,
-    pub minute: u8,
-    pub second: u8,
-    pub valid: u8,
-    pub daylight: u8,
-    pub res1: u8,
-    pub milli: u16,
-    pub time_zone: u16,
-    pub res2: u16,
-    pub alarm_status: u32,
-    pub ac_time_val: u32,
-    pub dc_time_val: u32,
+ec_section! {
+    #[section(id = TimeAlarm, major = 1, minor = 0)]
+    pub struct TimeAlarm => TimeAlarmMessage {
+        events: u32 => Events,
+        capability: u32 => Capability,
+        year: u16 => Year,
+        month: u8 => Month,
+        day: u8 => Day,
+        hour: u8 => Hour,
+        minute: u8 => Minute,
+        second: u8 => Second,
+        valid: u8 => Valid,
+        daylight: u8 => Daylight,
+        res1: u8 => Res1,
+        milli: u16 => Milli,
+        time_zone: u16 => TimeZone,
+        res2: u16 => Res2,
+        alarm_status: u32 => AlarmStatus,
+        ac_time_val: u32 => AcTimeVal(Seconds),
+        dc_time_val: u32 => DcTimeVal(Seconds),
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Battery {
-    pub events: u32,
-    pub status: u32,
-    pub last_full_charge: u32,
-    pub cycle_count: u32,
-    pub state: u32,
-    pub present_rate: u32,
-    pub remain_cap: u32,
-    pub present_volt: u32,
-    pub psr_state: u32,
-    pub psr_max_out: u32,
-    pub psr_max_in: u32,
-    pub peak_level: u32,
-    pub peak_power: u32,
-    pub sus_level: u32,
-    pub sus_power: u32,
-    pub peak_thres: u32,
-    pub sus_thres: u32,
-    pub trip_thres: u32,
-    pub bmc_data: u32,
-    pub bmd_data: u32,
-    pub bmd_flags: u32,
-    pub bmd_count: u32,
-    pub charge_time: u32,
-    pub run_time: u32,
-    pub sample_time: u32,
+ec_section! {
+    #[section(id = Battery, major = 1, minor = 0)]
+    pub struct Battery => BatteryMessage {
+        events: u32 => Events,
+        status: u32 => Status,
+        last_full_charge: u32 => LastFullCharge(MilliWattHours),
+        cycle_count: u32 => CycleCount,
+        state: u32 => State,
+        present_rate: u32 => PresentRate(MilliWatts),
+        remain_cap: u32 => RemainCap(MilliWattHours),
+        present_volt: u32 => PresentVolt(MilliVolts),
+        psr_state: u32 => PsrState,
+        psr_max_out: u32 => PsrMaxOut(MilliWatts),
+        psr_max_in: u32 => PsrMaxIn(MilliWatts),
+        peak_level: u32 => PeakLevel,
+        peak_power: u32 => PeakPower(MilliWatts),
+        sus_level: u32 => SusLevel,
+        sus_power: u32 => SusPower(MilliWatts),
+        peak_thres: u32 => PeakThres,
+        sus_thres: u32 => SusThres,
+        trip_thres: u32 => TripThres(MilliWattHours),
+        bmc_data: u32 => BmcData,
+        bmd_data: u32 => BmdData,
+        bmd_flags: u32 => BmdFlags,
+        bmd_count: u32 => BmdCount,
+        charge_time: u32 => ChargeTime(Seconds),
+        run_time: u32 => RunTime(Seconds),
+        sample_time: u32 => SampleTime,
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Thermal {
-    pub events: u32,
-    pub cool_mode: u32,
-    pub dba_limit: u32,
-    pub sonne_limit: u32,
-    pub ma_limit: u32,
-    pub fan1_on_temp: u32,
-    pub fan1_ramp_temp: u32,
-    pub fan1_max_temp: u32,
-    pub fan1_crt_temp: u32,
-    pub fan1_hot_temp: u32,
-    pub fan1_max_rpm: u32,
-    pub fan1_cur_rpm: u32,
-    pub tmp1_val: u32,
-    pub tmp1_timeout: u32,
-    pub tmp1_low: u32,
-    pub tmp1_high: u32,
+ec_section! {
+    #[section(id = Thermal, major = 1, minor = 0)]
+    /// Thermal policy, the `fan1_*` and `tmp1_*` fields describe a single fan and sensor. Designs with more use the
+    /// [`Fan`] and [`Sensor`] sections.
+    pub struct Thermal => ThermalMessage {
+        events: u32 => Events,
+        cool_mode: u32 => CoolMode,
+        dba_limit: u32 => DbaLimit,
+        sonne_limit: u32 => SonneLimit,
+        ma_limit: i32 => MaLimit(MilliAmps),
+        fan1_on_temp: u32 => Fan1OnTemp(DeciKelvin),
+        fan1_ramp_temp: u32 => Fan1RampTemp(DeciKelvin),
+        fan1_max_temp: u32 => Fan1MaxTemp(DeciKelvin),
+        fan1_crt_temp: u32 => Fan1CrtTemp(DeciKelvin),
+        fan1_hot_temp: u32 => Fan1HotTemp(DeciKelvin),
+        fan1_max_rpm: u32 => Fan1MaxRpm(Rpm),
+        fan1_cur_rpm: u32 => Fan1CurRpm(Rpm),
+        tmp1_val: u32 => Tmp1Val(DeciKelvin),
+        tmp1_timeout: u32 => Tmp1Timeout,
+        tmp1_low: u32 => Tmp1Low(DeciKelvin),
+        tmp1_high: u32 => Tmp1High(DeciKelvin),
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Notifications {
-    pub service: u16,
-    pub event: u16,
+ec_section! {
+    #[section(id = Fan, major = 1, minor = 0)]
+    pub struct Fan => FanMessage {
+        on_temp: u32 => OnTemp(DeciKelvin),
+        ramp_temp: u32 => RampTemp(DeciKelvin),
+        max_temp: u32 => MaxTemp(DeciKelvin),
+        crt_temp: u32 => CrtTemp(DeciKelvin),
+        hot_temp: u32 => HotTemp(DeciKelvin),
+        max_rpm: u32 => MaxRpm(Rpm),
+        cur_rpm: u32 => CurRpm(Rpm),
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct ECMemory {
-    pub ver: Version,
-    pub caps: Capabilities,
-    pub notif: Notifications,
-    pub alarm: TimeAlarm,
-    pub batt: Battery,
-    pub therm: Thermal,
+ec_section! {
+    #[section(id = Sensor, major = 1, minor = 0)]
+    pub struct Sensor => SensorMessage {
+        val: u32 => Val(DeciKelvin),
+        timeout: u32 => Timeout,
+        low: u32 => Low(DeciKelvin),
+        high: u32 => High(DeciKelvin),
+    }
+}
+
+ec_section! {
+    #[section(id = EventQueue, major = 1, minor = 0)]
+    /// Host event queue indices into the [`EventEntry`] instances. The EC advances `producer` after writing an entry
+    /// and the host advances `consumer` after reading one. The queue is empty when they are equal and full when
+    /// `producer` is one slot behind `consumer`.
+    pub struct EventQueue => EventQueueMessage {
+        producer: u16 => Producer,
+        consumer: u16 => Consumer,
+    }
+}
+
+ec_section! {
+    #[section(id = EventEntry, major = 1, minor = 0)]
+    pub struct EventEntry => EventEntryMessage {
+        service: u16 => Service,
+        event: u16 => Event,
+        payload: u32 => Payload,
+    }
+}
+
+ec_struct! {
+    /// Single event slot of the fixed layout, superseded by [`EventQueue`]
+    pub struct Notifications {
+        service: u16,
+        event: u16,
+    }
+}
+
+ec_struct! {
+    /// Fixed memory map layout of version [`EC_MEMMAP_VERSION`], without a directory. See [`super::layout`] for maps
+    /// that the host can discover.
+    pub struct ECMemory {
+        ver: Version,
+        caps: Capabilities,
+        notif: Notifications,
+        alarm: TimeAlarm,
+        batt: Battery,
+        therm: Thermal,
+    }
 }
@@ -0,0 +1,99 @@
+//! Physical units carried by memory map messages
+//!
+//! Messages carry these types so a value can't be sent in the wrong unit. The memory map stores raw `u32` fields, or
+//! two's complement `i32` fields for signed units such as [`MilliAmps`]. Values are converted with `TryFrom` when they
+//! cross the map and fail with [`Error::OutOfRange`] if they don't fit.
+
+use super::Error;
+
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident($inner:ty)) => {
+        unit!($(#[$meta])* $name($inner) in u32);
+    };
+    ($(#[$meta:meta])* $name:ident($inner:ty) in $raw:ty) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub struct $name(pub $inner);
+
+        impl TryFrom<$raw> for $name {
+            type Error = Error;
+
+            fn try_from(value: $raw) -> Result<Self, Self::Error> {
+                <$inner>::try_from(value).map(Self).map_err(|_| Error::OutOfRange)
+            }
+        }
+
+        impl TryFrom<$name> for $raw {
+            type Error = Error;
+
+            fn try_from(value: $name) -> Result<Self, Self::Error> {
+                <$raw>::try_from(value.0).map_err(|_| Error::OutOfRange)
+            }
+        }
+    };
+}
+
+unit! {
+    /// Temperature in tenths of a Kelvin
+    DeciKelvin(u16)
+}
+
+unit! {
+    /// Voltage in millivolts
+    MilliVolts(u16)
+}
+
+unit! {
+    /// Current in milliamps, negative when discharging
+    MilliAmps(i32) in i32
+}
+
+unit! {
+    /// Power in milliwatts
+    MilliWatts(u32)
+}
+
+unit! {
+    /// Energy in milliwatt hours
+    MilliWattHours(u32)
+}
+
+unit! {
+    /// Fan speed in revolutions per minute
+    Rpm(u16)
+}
+
+unit! {
+    /// Duration in seconds
+    Seconds(u32)
+}
+
+impl From<u16> for MilliAmps {
+    fn from(value: u16) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<i16> for MilliAmps {
+    fn from(value: i16) -> Self {
+        Self(value.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_map_conversion() {
+        assert_eq!(DeciKelvin::try_from(2982u32), Ok(DeciKelvin(2982)));
+        assert_eq!(DeciKelvin::try_from(0x1_0000u32), Err(Error::OutOfRange));
+        assert_eq!(u32::try_from(Rpm(3000)), Ok(3000));
+
+        assert_eq!(i32::try_from(MilliAmps(1500)), Ok(1500));
+        assert_eq!(i32::try_from(MilliAmps(-1500)), Ok(-1500));
+        assert_eq!(MilliAmps::try_from(-1500i32), Ok(MilliAmps(-1500)));
+        assert_eq!(MilliAmps::from(-2i16), MilliAmps(-2));
+    }
+}
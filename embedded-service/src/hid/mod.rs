@@ -1,14 +1,12 @@
 //! HID sevices
 //! See spec at http://msdn.microsoft.com/en-us/library/windows/hardware/hh852380.aspx
-use core::convert::Infallible;
-
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::once_lock::OnceLock;
 use embassy_sync::signal::Signal;
 
 use crate::buffer::SharedRef;
-use crate::comms::{self, Endpoint, EndpointID, External, Internal, MailboxDelegate};
-use crate::{error, intrusive_list, IntrusiveList, Node, NodeContainer};
+use crate::comms::{self, Endpoint, EndpointID, External, Internal};
+use crate::{Keyed, Node, NodeContainer, TypedIntrusiveList};
 
 mod command;
 pub use command::*;
@@ -17,7 +15,7 @@ pub use command::*;
 pub const DESCRIPTOR_LEN: usize = 30;
 
 /// HID errors
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Invalid data
@@ -177,6 +175,14 @@ impl NodeContainer for Device {
     }
 }
 
+impl Keyed for Device {
+    type Key = DeviceId;
+
+    fn key(&self) -> DeviceId {
+        self.id
+    }
+}
+
 impl Device {
     /// Instantiates a new device
     pub fn new(id: DeviceId, regs: RegisterFile) -> Self {
@@ -195,7 +201,7 @@ impl Device {
     }
 
     /// Send a response to the host from this device
-    pub async fn send_response(&self, response: Option<Response<'static>>) -> Result<(), Infallible> {
+    pub async fn send_response(&self, response: Option<Response<'static>>) -> Result<(), comms::SendError> {
         let message = Message {
             id: self.id,
             data: MessageData::Response(response),
@@ -210,13 +216,8 @@ impl DeviceContainer for Device {
     }
 }
 
-impl MailboxDelegate for Device {
-    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
-        let message = message
-            .data
-            .get::<Message>()
-            .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
-
+impl comms::Handler<Message<'static>> for Device {
+    fn handle(&self, _message: &comms::Message, message: &Message<'static>) -> Result<(), comms::MailboxDelegateError> {
         match message.data {
             MessageData::Request(ref request) => {
                 self.request.signal(request.clone());
@@ -228,6 +229,8 @@ impl MailboxDelegate for Device {
     }
 }
 
+crate::impl_mailbox_delegate!(impl for Device { Message<'static> });
+
 /// HID device ID
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -287,48 +290,49 @@ pub struct Message<'a> {
 }
 
 struct Context {
-    devices: IntrusiveList,
+    devices: TypedIntrusiveList<Device>,
 }
 
 impl Context {
     fn new() -> Self {
         Context {
-            devices: IntrusiveList::new(),
+            devices: TypedIntrusiveList::new(),
         }
     }
 }
 
 static CONTEXT: OnceLock<Context> = OnceLock::new();
 
+/// HID service readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("hid", &[&comms::READINESS]);
+
 /// Init HID service
 pub fn init() {
     CONTEXT.get_or_init(Context::new);
+    READINESS.ready();
 }
 
 /// Register a device with the HID service
-pub async fn register_device(device: &'static impl DeviceContainer) -> Result<(), intrusive_list::Error> {
+pub async fn register_device(device: &'static impl DeviceContainer) -> Result<(), comms::RegistrationError> {
     let device = device.get_hid_device();
     CONTEXT.get().await.devices.push(device)?;
     comms::register_endpoint(device, &device.tp).await
 }
 
+/// Deregister a device from the HID service
+pub async fn deregister_device(device: &'static impl DeviceContainer) -> Result<(), comms::RegistrationError> {
+    let device = device.get_hid_device();
+    CONTEXT.get().await.devices.remove(device)?;
+    comms::deregister_endpoint(&device.tp).await
+}
+
 /// Find a device by its ID
 pub async fn get_device(id: DeviceId) -> Option<&'static Device> {
-    for device in &CONTEXT.get().await.devices {
-        if let Some(data) = device.data::<Device>() {
-            if data.id == id {
-                return Some(data);
-            }
-        } else {
-            error!("Non-device located in devices list");
-        }
-    }
-
-    None
+    CONTEXT.get().await.devices.get(id)
 }
 
 /// Convenience function to send a request to a HID device
-pub async fn send_request(tp: &Endpoint, to: DeviceId, request: Request<'static>) -> Result<(), Infallible> {
+pub async fn send_request(tp: &Endpoint, to: DeviceId, request: Request<'static>) -> Result<(), comms::SendError> {
     let message = Message {
         id: to,
         data: MessageData::Request(request),
@@ -1,6 +1,21 @@
 //! Code related to initialization states and ordering
+//!
+//! Each service declares a static [`Service`] listing the services it depends on and reports the [`Stage`]s it
+//! reaches while coming up. Tasks wait for a service to reach a stage with [`Service::wait`], which returns an error
+//! instead of waiting forever once the service or one of its dependencies failed. [`wait_for_boot`] gives services
+//! a deadline to come up and reports the ones that didn't.
 
+use core::cell::{Cell, RefCell};
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_time::{with_timeout, Duration};
+
+use crate::{error, info, warn};
 
 static REGISTRATION_DONE: OnceLock<()> = OnceLock::new();
 
@@ -13,3 +28,361 @@ pub async fn wait_for_registration() {
 pub fn registration_done() {
     REGISTRATION_DONE.get_or_init(|| ());
 }
+
+/// Maximum number of services tracked for the boot report
+pub const MAX_SERVICES: usize = 32;
+
+/// Maximum number of tasks woken directly on a state change, further waiters are woken early and re-register
+const MAX_WAITERS: usize = 8;
+
+/// Services known to the boot report
+static SERVICES: Mutex<CriticalSectionRawMutex, RefCell<heapless::Vec<&'static Service, MAX_SERVICES>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Tasks waiting on any service state change
+static WAITERS: Mutex<CriticalSectionRawMutex, RefCell<MultiWakerRegistration<MAX_WAITERS>>> =
+    Mutex::new(RefCell::new(MultiWakerRegistration::new()));
+
+/// Core static interfaces, ready once [`crate::init`] returns. Each interface has its own `READINESS` service that
+/// other services can depend on directly.
+pub static CORE: Service = Service::new(
+    "embedded-services",
+    &[
+        &crate::comms::READINESS,
+        &crate::activity::READINESS,
+        &crate::hid::READINESS,
+        &crate::cfu::READINESS,
+        &crate::keyboard::READINESS,
+        &crate::power::policy::READINESS,
+        &crate::type_c::controller::READINESS,
+    ],
+);
+
+/// Service readiness stage, each stage implies the ones before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Stage {
+    /// Static state is initialized
+    StaticInit,
+    /// Comms endpoints and devices are registered
+    Registered,
+    /// Hardware is initialized, the service is fully operational
+    HardwareReady,
+}
+
+/// Initialization error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The named service failed before reaching the requested stage
+    Failed(&'static str),
+}
+
+/// Service status
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// Still coming up, holds the last stage reached
+    Pending(Option<Stage>),
+    /// Reached [`Stage::HardwareReady`]
+    Ready,
+    /// Failed, holds the last stage reached
+    Failed(Option<Stage>),
+    /// Can't come up because the named dependency failed
+    Blocked(&'static str),
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    stage: Option<Stage>,
+    failed: bool,
+}
+
+/// Service readiness tracking
+///
+/// Dependencies must not form a cycle.
+pub struct Service {
+    name: &'static str,
+    dependencies: &'static [&'static Service],
+    state: Mutex<CriticalSectionRawMutex, Cell<State>>,
+}
+
+impl Service {
+    /// Create a new service
+    pub const fn new(name: &'static str, dependencies: &'static [&'static Service]) -> Self {
+        Self {
+            name,
+            dependencies,
+            state: Mutex::new(Cell::new(State {
+                stage: None,
+                failed: false,
+            })),
+        }
+    }
+
+    /// Service name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Services this service depends on
+    pub fn dependencies(&self) -> &'static [&'static Service] {
+        self.dependencies
+    }
+
+    /// Add the service and its dependencies to the boot report, also done by reporting or waiting on a stage
+    pub fn register(&'static self) {
+        let added = SERVICES.lock(|services| {
+            let mut services = services.borrow_mut();
+            if services.iter().any(|service| core::ptr::eq(*service, self)) {
+                return false;
+            }
+
+            if services.push(self).is_err() {
+                warn!("Too many services, {} missing from boot report", self.name);
+            }
+            true
+        });
+
+        if added {
+            for dependency in self.dependencies {
+                dependency.register();
+            }
+        }
+    }
+
+    fn state(&self) -> State {
+        self.state.lock(|state| state.get())
+    }
+
+    fn update(&'static self, f: impl FnOnce(&mut State)) {
+        self.state.lock(|state| {
+            let mut new = state.get();
+            f(&mut new);
+            state.set(new);
+        });
+        // registered after the update so the report never sees a service that is already ready as pending
+        self.register();
+        WAITERS.lock(|waiters| waiters.borrow_mut().wake());
+    }
+
+    /// Last stage reached
+    pub fn stage(&self) -> Option<Stage> {
+        self.state().stage
+    }
+
+    /// Report that the service reached a stage
+    pub fn reached(&'static self, stage: Stage) {
+        self.update(|state| {
+            if !state.failed && state.stage < Some(stage) {
+                state.stage = Some(stage);
+            }
+        });
+    }
+
+    /// Report that the service is fully operational
+    pub fn ready(&'static self) {
+        self.reached(Stage::HardwareReady);
+    }
+
+    /// Report that the service failed to come up, stages already reached remain valid
+    pub fn fail(&'static self) {
+        error!("Service {} failed", self.name);
+        self.update(|state| state.failed = true);
+    }
+
+    /// First failed service among the dependencies, checked recursively
+    fn failed_dependency(&self) -> Option<&'static str> {
+        self.dependencies.iter().find_map(|dependency| {
+            if dependency.state().failed {
+                Some(dependency.name)
+            } else {
+                dependency.failed_dependency()
+            }
+        })
+    }
+
+    /// Current status, a service that is already ready stays ready if a dependency fails later
+    pub fn status(&self) -> Status {
+        let state = self.state();
+        if state.failed {
+            Status::Failed(state.stage)
+        } else if state.stage == Some(Stage::HardwareReady) {
+            Status::Ready
+        } else if let Some(name) = self.failed_dependency() {
+            Status::Blocked(name)
+        } else {
+            Status::Pending(state.stage)
+        }
+    }
+
+    fn poll_stage(&self, stage: Stage) -> Poll<Result<(), Error>> {
+        let state = self.state();
+        if state.stage >= Some(stage) {
+            Poll::Ready(Ok(()))
+        } else if state.failed {
+            Poll::Ready(Err(Error::Failed(self.name)))
+        } else if let Some(name) = self.failed_dependency() {
+            Poll::Ready(Err(Error::Failed(name)))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Wait for the service to reach a stage
+    ///
+    /// Returns an error if the service or one of its dependencies fails first.
+    pub async fn wait(&'static self, stage: Stage) -> Result<(), Error> {
+        self.register();
+        poll_fn(|cx| {
+            // Check and register under the lock so an update can't slip in between
+            WAITERS.lock(|waiters| {
+                let poll = self.poll_stage(stage);
+                if poll.is_pending() {
+                    waiters.borrow_mut().register(cx.waker());
+                }
+                poll
+            })
+        })
+        .await
+    }
+
+    /// Wait for all dependencies to reach a stage
+    pub async fn wait_dependencies(&'static self, stage: Stage) -> Result<(), Error> {
+        for dependency in self.dependencies {
+            dependency.wait(stage).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Status of all known services
+pub struct BootReport {
+    services: heapless::Vec<&'static Service, MAX_SERVICES>,
+}
+
+impl BootReport {
+    /// All known services
+    pub fn services(&self) -> impl Iterator<Item = &'static Service> + '_ {
+        self.services.iter().copied()
+    }
+
+    /// Services that failed or are blocked by a failed dependency
+    pub fn failed(&self) -> impl Iterator<Item = &'static Service> + '_ {
+        self.services()
+            .filter(|service| matches!(service.status(), Status::Failed(_) | Status::Blocked(_)))
+    }
+
+    /// Services still coming up
+    pub fn pending(&self) -> impl Iterator<Item = &'static Service> + '_ {
+        self.services()
+            .filter(|service| matches!(service.status(), Status::Pending(_)))
+    }
+
+    /// True if all services are ready
+    pub fn is_ok(&self) -> bool {
+        self.services().all(|service| service.status() == Status::Ready)
+    }
+
+    /// Log the status of each service
+    pub fn log(&self) {
+        for service in self.services() {
+            match service.status() {
+                Status::Ready => info!("{}: ready", service.name()),
+                Status::Pending(stage) => warn!("{}: pending, reached {:?}", service.name(), stage),
+                Status::Failed(stage) => error!("{}: failed, reached {:?}", service.name(), stage),
+                Status::Blocked(name) => error!("{}: blocked by {}", service.name(), name),
+            }
+        }
+    }
+}
+
+/// Get the current status of all known services
+pub fn boot_report() -> BootReport {
+    BootReport {
+        services: SERVICES.lock(|services| services.borrow().clone()),
+    }
+}
+
+/// Wait for all known services to be ready or fail
+///
+/// Services still pending after `timeout` are marked as failed, which releases any task waiting on them.
+pub async fn wait_for_boot(timeout: Duration) -> BootReport {
+    let settled = poll_fn(|cx| {
+        WAITERS.lock(|waiters| {
+            if boot_report().pending().next().is_none() {
+                Poll::Ready(())
+            } else {
+                waiters.borrow_mut().register(cx.waker());
+                Poll::Pending
+            }
+        })
+    });
+
+    if with_timeout(timeout, settled).await.is_err() {
+        for service in boot_report().pending() {
+            service.fail();
+        }
+    }
+
+    let report = boot_report();
+    report.log();
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::{block_on, poll_once};
+
+    use super::*;
+
+    static BUS: Service = Service::new("bus", &[]);
+    static SENSOR: Service = Service::new("sensor", &[&BUS]);
+    static FAN: Service = Service::new("fan", &[&SENSOR]);
+    static STUCK: Service = Service::new("stuck", &[]);
+
+    #[test]
+    fn test_boot() {
+        BUS.reached(Stage::StaticInit);
+        assert_eq!(BUS.stage(), Some(Stage::StaticInit));
+        assert_eq!(BUS.status(), Status::Pending(Some(Stage::StaticInit)));
+
+        // waiting on a service pulls its dependencies into the report
+        assert!(poll_once(SENSOR.wait(Stage::Registered)).is_pending());
+        assert!(poll_once(SENSOR.wait_dependencies(Stage::Registered)).is_pending());
+        assert!(boot_report().services().any(|service| core::ptr::eq(service, &BUS)));
+
+        BUS.reached(Stage::Registered);
+        assert_eq!(block_on(SENSOR.wait_dependencies(Stage::Registered)), Ok(()));
+
+        // stages only move forward
+        SENSOR.ready();
+        SENSOR.reached(Stage::StaticInit);
+        assert_eq!(SENSOR.status(), Status::Ready);
+
+        // a failure releases everyone waiting downstream
+        let mut fan = core::pin::pin!(FAN.wait(Stage::HardwareReady));
+        assert!(poll_once(fan.as_mut()).is_pending());
+        BUS.fail();
+        assert_eq!(block_on(fan), Err(Error::Failed("bus")));
+        assert_eq!(FAN.status(), Status::Blocked("bus"));
+        assert_eq!(BUS.status(), Status::Failed(Some(Stage::Registered)));
+        assert_eq!(SENSOR.status(), Status::Ready);
+
+        // stages reached before the failure still hold
+        assert_eq!(block_on(BUS.wait(Stage::Registered)), Ok(()));
+        assert_eq!(block_on(BUS.wait(Stage::HardwareReady)), Err(Error::Failed("bus")));
+
+        // services that don't come up in time are failed
+        STUCK.register();
+        let report = block_on(wait_for_boot(Duration::from_millis(10)));
+        assert!(!report.is_ok());
+        assert_eq!(report.pending().count(), 0);
+        assert_eq!(STUCK.status(), Status::Failed(None));
+        assert_eq!(block_on(STUCK.wait(Stage::StaticInit)), Err(Error::Failed("stuck")));
+
+        let failed = report.failed().map(Service::name);
+        assert!(failed.eq(["bus", "fan", "stuck"]));
+    }
+}
@@ -1,14 +1,18 @@
-//! A static lifetime'd intrusive linked list, construction only (never remove/delete)
+//! A static lifetime'd intrusive linked list. Nodes may be removed, but are never deallocated
 
 // Any type used for dynamic type coercion
 pub use core::any::Any;
 pub use core::cell::Cell;
+use core::cmp::Ordering;
 
 /// Interface error class information
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// cannot push a node to any list if it's already in one
     NodeAlreadyInList,
+    /// cannot remove a node from a list it isn't in
+    NodeNotInList,
 }
 
 /// override Result type for shorthand -> Result<T>
@@ -20,14 +24,15 @@ pub struct IntrusiveNode {
     /// offset from &self to struct data. Typically := sizeof(IntrusiveNode)
     address_of_data: *const dyn Any,
 
-    /// unsafe iterator type
-    next: Option<&'static IntrusiveNode>,
+    /// next node in the list, referenced through its cell so links can be updated on removal
+    next: Option<&'static Node>,
 
     /// valid address flag: used to ensure proper initialization sequencing over address_of_data
     valid: bool,
 }
 
 /// node type for list allocation. Embed this in the "list wrapper" object, and init with Node::uninit()
+#[derive(Debug)]
 pub struct Node {
     inner: Cell<IntrusiveNode>,
 }
@@ -61,7 +66,7 @@ pub trait NodeContainer: Any {
 /// List of intruded nodes of unknown type(s), must be allocated statically
 pub struct IntrusiveList {
     /// traditional head pointer on list. Static reference type is used to ensure static allocations (for safety)
-    head: Cell<Option<&'static IntrusiveNode>>,
+    head: Cell<Option<&'static Node>>,
 }
 
 impl IntrusiveNode {
@@ -98,19 +103,50 @@ impl IntrusiveList {
     }
 
     /// only allow pushing to the head of the list
-    fn push_front(&self, node: &'static mut IntrusiveNode) {
+    fn push_front(&self, node: &'static Node) {
         // critical section in case of multi-threaded implementation:
         critical_section::with(|_cs| {
-            if let Some(old_head) = self.head.get() {
-                node.next = Some(old_head);
-            }
+            let mut inner = node.inner.get();
+            inner.next = self.head.get();
+            node.inner.set(inner);
 
             self.head.set(Some(node));
         });
     }
 
-    /// generic over T: NodeContainer for list.push() proper node construction
-    pub fn push<T: NodeContainer>(&self, object: &'static T) -> Result<()> {
+    /// link a node in front of the first node matching `insert_before`, or at the tail if no node matches
+    fn insert_node(&self, node: &'static Node, mut insert_before: impl FnMut(&IntrusiveNode) -> bool) {
+        critical_section::with(|_cs| {
+            let mut prev: Option<&'static Node> = None;
+            let mut current = self.head.get();
+
+            while let Some(other) = current {
+                let other_inner = other.inner.get();
+                if insert_before(&other_inner) {
+                    break;
+                }
+
+                prev = Some(other);
+                current = other_inner.next;
+            }
+
+            let mut inner = node.inner.get();
+            inner.next = current;
+            node.inner.set(inner);
+
+            match prev {
+                Some(prev) => {
+                    let mut prev_inner = prev.inner.get();
+                    prev_inner.next = Some(node);
+                    prev.inner.set(prev_inner);
+                }
+                None => self.head.set(Some(node)),
+            }
+        });
+    }
+
+    /// mark the object's node as valid, failing if it is already in a list
+    fn claim<T: NodeContainer>(object: &'static T) -> Result<&'static Node> {
         // check if node is in the list already. Valid flag will only be set if
         // the element has been constructed and inserted into a linked list, so
         // this check covers both same list and other list conditions.
@@ -123,13 +159,75 @@ impl IntrusiveList {
         let node = IntrusiveNode::new(object);
         object.get_node().inner.set(node);
 
-        self.push_front(
-            // SAFETY: known safe operation due to valid flag and static lifetime
-            unsafe { &mut *object.get_node().inner.as_ptr() },
-        );
+        Ok(object.get_node())
+    }
+
+    /// generic over T: NodeContainer for list.push() proper node construction
+    pub fn push<T: NodeContainer>(&self, object: &'static T) -> Result<()> {
+        self.push_front(Self::claim(object)?);
+        Ok(())
+    }
+
+    /// push an object to the tail of the list, so that iteration follows push order
+    pub fn push_back<T: NodeContainer>(&self, object: &'static T) -> Result<()> {
+        self.insert_node(Self::claim(object)?, |_| false);
+        Ok(())
+    }
+
+    /// insert an object in front of the first node of type `T` that compares greater than it. Objects that compare
+    /// equal keep their push order, nodes of other types are skipped over
+    pub fn insert_sorted_by<T: NodeContainer>(
+        &self,
+        object: &'static T,
+        mut compare: impl FnMut(&T, &T) -> Ordering,
+    ) -> Result<()> {
+        self.insert_node(Self::claim(object)?, |other| {
+            other
+                .data::<T>()
+                .is_some_and(|other| compare(object, other) == Ordering::Less)
+        });
         Ok(())
     }
 
+    /// remove a previously pushed object from the list. The node is invalidated and may be pushed again afterwards
+    pub fn remove<T: NodeContainer>(&self, object: &'static T) -> Result<()> {
+        let target = object.get_node();
+
+        critical_section::with(|_cs| {
+            let mut prev: Option<&'static Node> = None;
+            let mut current = self.head.get();
+
+            while let Some(node) = current {
+                let inner = node.inner.get();
+
+                if core::ptr::eq(node, target) {
+                    match prev {
+                        Some(prev) => {
+                            let mut prev_inner = prev.inner.get();
+                            prev_inner.next = inner.next;
+                            prev.inner.set(prev_inner);
+                        }
+                        None => self.head.set(inner.next),
+                    }
+
+                    // keep the forward link so that any iterator currently parked on this node can continue,
+                    // but clear the valid flag so data access is blocked and the node can be pushed again
+                    target.inner.set(IntrusiveNode {
+                        next: inner.next,
+                        ..Node::EMPTY
+                    });
+
+                    return Ok(());
+                }
+
+                prev = Some(node);
+                current = inner.next;
+            }
+
+            Err(Error::NodeNotInList)
+        })
+    }
+
     /// Iterate over the list as if it were items of type `T`, skipping any nodes that are of a different type.
     pub fn iter_only<T: NodeContainer>(&self) -> OnlyT<T> {
         OnlyT::new(self.into_iter())
@@ -138,7 +236,7 @@ impl IntrusiveList {
 
 /// iterator wrapper type for IntrusiveNode
 pub struct IntrusiveIterator {
-    current: Option<&'static IntrusiveNode>,
+    current: Option<&'static Node>,
 }
 
 impl IntoIterator for &IntrusiveList {
@@ -159,6 +257,8 @@ impl Iterator for IntrusiveIterator {
         let mut iter = None;
 
         if let Some(current) = self.current {
+            // SAFETY: nodes are only ever linked with static lifetime, and the cell contents are plain copy data
+            let current = unsafe { &*current.inner.as_ptr() };
             self.current = current.next;
             iter = Some(current);
         }
@@ -193,6 +293,110 @@ impl<'a, T: NodeContainer> Iterator for OnlyT<'a, T> {
     }
 }
 
+/// Implemented by list items that are looked up by a unique key, see [`TypedIntrusiveList::get`]
+pub trait Keyed {
+    /// Key type, typically a device or component ID
+    type Key: PartialEq;
+
+    /// Return the key of this item
+    fn key(&self) -> Self::Key;
+}
+
+/// List of intruded nodes of a single type `T`, must be allocated statically
+pub struct TypedIntrusiveList<T: NodeContainer> {
+    list: IntrusiveList,
+    _marker: core::marker::PhantomData<&'static T>,
+}
+
+impl<T: NodeContainer> Default for TypedIntrusiveList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: NodeContainer> TypedIntrusiveList<T> {
+    /// construct an empty intrusive list
+    pub fn new() -> Self {
+        Self {
+            list: IntrusiveList::new(),
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    /// push an object to the tail of the list, so that iteration follows push order
+    pub fn push(&self, object: &'static T) -> Result<()> {
+        self.list.push_back(object)
+    }
+
+    /// insert an object in front of the first object that compares greater than it, equal objects keep their push
+    /// order
+    pub fn insert_sorted_by(&self, object: &'static T, compare: impl FnMut(&T, &T) -> Ordering) -> Result<()> {
+        self.list.insert_sorted_by(object, compare)
+    }
+
+    /// insert an object ordered by the key returned from `f`, e.g. an explicit priority, equal keys keep their push
+    /// order
+    pub fn insert_sorted_by_key<K: Ord>(&self, object: &'static T, mut f: impl FnMut(&T) -> K) -> Result<()> {
+        self.list.insert_sorted_by(object, |a, b| f(a).cmp(&f(b)))
+    }
+
+    /// remove a previously pushed object from the list. The object may be pushed again afterwards
+    pub fn remove(&self, object: &'static T) -> Result<()> {
+        self.list.remove(object)
+    }
+
+    /// iterate over the objects in the list
+    pub fn iter(&self) -> OnlyT<'static, T> {
+        OnlyT::new(self.list.into_iter())
+    }
+
+    /// find the first object matching the predicate
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<&'static T> {
+        self.iter().find(|object| predicate(object))
+    }
+
+    /// returns true if the object is in the list
+    pub fn contains(&self, object: &T) -> bool {
+        self.iter().any(|other| core::ptr::eq(other, object))
+    }
+
+    /// number of objects in the list
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// returns true if the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.list.head.get().is_none()
+    }
+}
+
+impl<T: NodeContainer + Keyed> TypedIntrusiveList<T> {
+    /// find an object by its key
+    pub fn get(&self, key: T::Key) -> Option<&'static T> {
+        self.find(|object| object.key() == key)
+    }
+}
+
+impl<T: NodeContainer + Keyed> TypedIntrusiveList<T>
+where
+    T::Key: Ord,
+{
+    /// insert an object ordered by its key
+    pub fn insert_sorted(&self, object: &'static T) -> Result<()> {
+        self.insert_sorted_by_key(object, Keyed::key)
+    }
+}
+
+impl<T: NodeContainer> IntoIterator for &TypedIntrusiveList<T> {
+    type IntoIter = OnlyT<'static, T>;
+    type Item = &'static T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -551,6 +755,69 @@ mod test {
         assert_eq!(B.len() + AB.len(), list_b.iter_only::<RegistrationB>().count());
     }
 
+    #[test]
+    fn test_remove() {
+        let list = IntrusiveList::new();
+        static A: [OnceLock<RegistrationA>; 3] = [const { OnceLock::new() }; 3];
+        let [first, middle, last] = A.each_ref().map(|a| a.get_or_init(RegistrationA::new));
+
+        // removing a node that was never pushed fails
+        assert!(matches!(list.remove(first), Err(Error::NodeNotInList)));
+
+        // NOTE: `push` pushes to the front, so the order of the list will be [last, middle, first]
+        assert!(list.push(first).is_ok());
+        assert!(list.push(middle).is_ok());
+        assert!(list.push(last).is_ok());
+        assert_eq!(A.len(), list.into_iter().count());
+
+        // remove from the middle
+        assert!(list.remove(middle).is_ok());
+        assert_eq!(2, list.into_iter().count());
+        assert!(list.iter_only::<RegistrationA>().all(|a| !core::ptr::eq(a, middle)));
+        assert!(matches!(list.remove(middle), Err(Error::NodeNotInList)));
+
+        // remove the head and the tail
+        assert!(list.remove(last).is_ok());
+        assert!(list
+            .iter_only::<RegistrationA>()
+            .map(|a| a as *const _)
+            .eq([first as *const _]));
+        assert!(list.remove(first).is_ok());
+        assert_eq!(0, list.into_iter().count());
+
+        // removed nodes can be pushed again, to this or another list
+        let list2 = IntrusiveList::new();
+        assert!(list.push(first).is_ok());
+        assert!(list2.push(middle).is_ok());
+        assert!(list.remove(middle).is_err());
+        assert!(list2.remove(middle).is_ok());
+        assert!(list
+            .iter_only::<RegistrationA>()
+            .map(|a| a as *const _)
+            .eq([first as *const _]));
+        assert_eq!(0, list2.into_iter().count());
+    }
+
+    #[test]
+    fn test_remove_while_iterating() {
+        let list = IntrusiveList::new();
+        static A: [OnceLock<RegistrationA>; 3] = [const { OnceLock::new() }; 3];
+        let [first, middle, last] = A.each_ref().map(|a| a.get_or_init(RegistrationA::new));
+
+        assert!(list.push(first).is_ok());
+        assert!(list.push(middle).is_ok());
+        assert!(list.push(last).is_ok());
+
+        // the iterator has yielded `last` and is parked on `middle`
+        let mut iter = list.iter_only::<RegistrationA>();
+        assert!(iter.next().is_some_and(|a| core::ptr::eq(a, last)));
+
+        // removing the parked node skips it without cutting the iteration short
+        assert!(list.remove(middle).is_ok());
+        assert!(iter.next().is_some_and(|a| core::ptr::eq(a, first)));
+        assert!(iter.next().is_none());
+    }
+
     #[test]
     fn test_multi_registration_list() {
         // list with multiple registration types
@@ -594,4 +861,125 @@ mod test {
         assert_eq!(A.len(), list.iter_only::<RegistrationA>().count());
         assert_eq!(B.len(), list.iter_only::<RegistrationB>().count());
     }
+
+    struct KeyedRegistration {
+        node: Node,
+        id: u8,
+    }
+
+    impl NodeContainer for KeyedRegistration {
+        fn get_node(&self) -> &Node {
+            &self.node
+        }
+    }
+
+    impl Keyed for KeyedRegistration {
+        type Key = u8;
+
+        fn key(&self) -> u8 {
+            self.id
+        }
+    }
+
+    #[test]
+    fn test_typed_list() {
+        let list = TypedIntrusiveList::new();
+        static K: [OnceLock<KeyedRegistration>; 3] = [const { OnceLock::new() }; 3];
+        let [first, second, unlisted] = [0, 1, 2].map(|id| {
+            K[id as usize].get_or_init(|| KeyedRegistration {
+                node: Node::uninit(),
+                id,
+            })
+        });
+
+        assert!(list.is_empty());
+        assert!(list.push(first).is_ok());
+        assert!(list.push(second).is_ok());
+        assert!(list.push(first).is_err());
+
+        assert_eq!(2, list.len());
+        assert!(!list.is_empty());
+        assert!(list.contains(first));
+        assert!(!list.contains(unlisted));
+
+        assert!(list.get(1).is_some_and(|k| core::ptr::eq(k, second)));
+        assert!(list.get(2).is_none());
+        assert!(list.find(|k| k.id == 0).is_some_and(|k| core::ptr::eq(k, first)));
+        assert_eq!(1u8, (&list).into_iter().map(|k| k.id).sum());
+
+        assert!(list.remove(second).is_ok());
+        assert!(list.get(1).is_none());
+        assert_eq!(1, list.len());
+    }
+
+    #[test]
+    fn test_ordered_insertion() {
+        static K: [OnceLock<KeyedRegistration>; 6] = [const { OnceLock::new() }; 6];
+        let k = |index: usize, id: u8| {
+            K[index].get_or_init(|| KeyedRegistration {
+                node: Node::uninit(),
+                id,
+            })
+        };
+        let ids = |list: &TypedIntrusiveList<KeyedRegistration>| {
+            let mut ids = heapless::Vec::<u8, 6>::new();
+            for k in list {
+                ids.push(k.id).unwrap();
+            }
+            ids
+        };
+
+        // typed lists iterate in push order
+        let pushed = TypedIntrusiveList::new();
+        assert!(pushed.push(k(0, 3)).is_ok());
+        assert!(pushed.push(k(1, 1)).is_ok());
+        assert_eq!(ids(&pushed), [3, 1]);
+        assert!(pushed.remove(k(0, 3)).is_ok());
+        assert!(pushed.remove(k(1, 1)).is_ok());
+
+        // sorted insertion at the head, middle and tail, equal keys keep their push order
+        let sorted = TypedIntrusiveList::new();
+        assert!(sorted.insert_sorted(k(0, 3)).is_ok());
+        assert!(sorted.insert_sorted(k(1, 1)).is_ok());
+        assert!(sorted.insert_sorted(k(2, 5)).is_ok());
+        assert!(sorted.insert_sorted(k(3, 3)).is_ok());
+        assert!(sorted.insert_sorted(k(0, 3)).is_err());
+        assert_eq!(ids(&sorted), [1, 3, 3, 5]);
+        assert!(sorted
+            .iter()
+            .nth(1)
+            .is_some_and(|k| core::ptr::eq(k, K[0].try_get().unwrap())));
+        assert!(sorted
+            .iter()
+            .nth(2)
+            .is_some_and(|k| core::ptr::eq(k, K[3].try_get().unwrap())));
+
+        // descending priority order
+        let by_priority = TypedIntrusiveList::new();
+        assert!(by_priority
+            .insert_sorted_by_key(k(4, 2), |k| core::cmp::Reverse(k.id))
+            .is_ok());
+        assert!(by_priority
+            .insert_sorted_by_key(k(5, 7), |k| core::cmp::Reverse(k.id))
+            .is_ok());
+        assert_eq!(ids(&by_priority), [7, 2]);
+
+        // untyped lists can also append and skip other types when sorting
+        let list = IntrusiveList::new();
+        static A: OnceLock<RegistrationA> = OnceLock::new();
+        let a = A.get_or_init(RegistrationA::new);
+        assert!(list.push_back(k(4, 2)).is_err());
+        assert!(by_priority.remove(k(4, 2)).is_ok());
+        assert!(list.push_back(k(4, 2)).is_ok());
+        assert!(list.push_back(a).is_ok());
+        assert!(by_priority.remove(k(5, 7)).is_ok());
+        assert!(list
+            .insert_sorted_by(k(5, 7), |a: &KeyedRegistration, b| a.id.cmp(&b.id))
+            .is_ok());
+        assert!(list
+            .into_iter()
+            .nth(1)
+            .is_some_and(|node| node.data::<RegistrationA>().is_some()));
+        assert!(list.iter_only::<KeyedRegistration>().map(|k| k.id).eq([2, 7]));
+    }
 }
@@ -1,19 +1,29 @@
 //! Definitions for deferred execution of commands
+use core::cell::{Cell, RefCell};
+use core::future::poll_fn;
 use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Poll;
 
-use crate::debug;
+use crate::{debug, warn};
+use embassy_sync::blocking_mutex;
+use embassy_sync::channel;
+use embassy_sync::waitqueue::{MultiWakerRegistration, WakerRegistration};
 use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{with_timeout, Duration, TimeoutError};
 
 /// A unique identifier for a particular command invocation
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-struct RequestId(usize);
+pub struct RequestId(usize);
 
 /// A simple channel for executing deferred commands.
 ///
 /// This implementation provides synchronization for command invocations
 /// and ensures that responses are sent back to the correct sender
 /// using a unique invocation ID.
+///
+/// Invocations are cancellation safe, if the future returned by [`Channel::execute`] is dropped before a response
+/// is received the command is withdrawn and any late response is discarded.
 pub struct Channel<M: RawMutex, C, R> {
     /// Signal for sending commands
     command: Signal<M, (C, RequestId)>,
@@ -21,6 +31,8 @@ pub struct Channel<M: RawMutex, C, R> {
     response: Signal<M, (R, RequestId)>,
     /// Mutex for synchronizing access to command invocation
     request_lock: Mutex<M, ()>,
+    /// Request currently waiting for a response
+    active_request: blocking_mutex::Mutex<M, Cell<Option<RequestId>>>,
     /// Unique ID for the next invocation
     next_request_id: AtomicUsize,
 }
@@ -32,6 +44,7 @@ impl<M: RawMutex, C, R> Channel<M, C, R> {
             command: Signal::new(),
             response: Signal::new(),
             request_lock: Mutex::new(()),
+            active_request: blocking_mutex::Mutex::new(Cell::new(None)),
             next_request_id: AtomicUsize::new(0),
         }
     }
@@ -42,25 +55,47 @@ impl<M: RawMutex, C, R> Channel<M, C, R> {
         RequestId(id)
     }
 
+    /// Returns true if the given request is still waiting for a response
+    fn is_active(&self, request_id: RequestId) -> bool {
+        self.active_request.lock(|active| active.get() == Some(request_id))
+    }
+
     /// Send a command and return the response
     /// This locks to ensure that commands are executed atomically
     pub async fn execute(&self, command: C) -> R {
         let _guard = self.request_lock.lock().await;
         let request_id = self.get_next_request_id();
+
+        self.active_request.lock(|active| active.set(Some(request_id)));
+        // Withdraw the request if this future is dropped before the response arrives
+        let _cancel = OnDrop(|| {
+            if self.active_request.lock(|active| active.take()).is_some() {
+                debug!("Request {} cancelled", request_id.0);
+                // Only this request can be pending while the lock is held
+                self.command.reset();
+            }
+        });
+
         self.command.signal((command, request_id));
         loop {
             // Wait until we receive a response for out particular request
             let (response, id) = self.response.wait().await;
             if id == request_id {
+                self.active_request.lock(|active| active.set(None));
                 return response;
             } else {
-                // Not an error because this is the expected behavior in certain cases,
-                // particularly if the sender times out before the response is received.
-                debug!("Received response for different invocation: {}", id.0);
+                warn!("Received response for different invocation: {}", id.0);
             }
         }
     }
 
+    /// Send a command and return the response, or an error if no response was received within `timeout`
+    ///
+    /// The command is withdrawn on timeout, see [`Channel::execute`]
+    pub async fn execute_with_timeout(&self, command: C, timeout: Duration) -> Result<R, TimeoutError> {
+        with_timeout(timeout, self.execute(command)).await
+    }
+
     /// Wait for an invocation
     pub async fn receive(&self) -> Request<'_, M, C, R> {
         let (command, request_id) = self.command.wait().await;
@@ -90,10 +125,350 @@ pub struct Request<'a, M: RawMutex, C, R> {
 }
 
 impl<M: RawMutex, C, R> Request<'_, M, C, R> {
+    /// Returns the ID of this request
+    pub fn id(&self) -> RequestId {
+        self.request_id
+    }
+
+    /// Returns true if the sender stopped waiting for a response, e.g. because it timed out
+    pub fn is_cancelled(&self) -> bool {
+        !self.channel.is_active(self.request_id)
+    }
+
     /// Send a response to the command, consuming the command in the process.
     ///
     /// Consuming the command ensures each command may only be responded to once.
+    /// Responses to cancelled requests are discarded.
     pub fn respond(self, response: R) {
+        if self.is_cancelled() {
+            warn!("Discarding response to cancelled request {}", self.request_id.0);
+            return;
+        }
+
         self.channel.response.signal((response, self.request_id));
     }
 }
+
+/// Response slot of a [`MultiChannel`]
+struct Slot<R> {
+    /// Request occupying this slot
+    request_id: Option<RequestId>,
+    /// Response, once received
+    response: Option<R>,
+    /// Sender waiting for the response
+    waker: WakerRegistration,
+}
+
+impl<R> Slot<R> {
+    const EMPTY: Self = Self {
+        request_id: None,
+        response: None,
+        waker: WakerRegistration::new(),
+    };
+}
+
+struct Slots<R, const N: usize> {
+    slots: [Slot<R>; N],
+    /// Senders waiting for a free slot
+    waiters: MultiWakerRegistration<N>,
+}
+
+/// A channel for executing deferred commands with up to `N` requests outstanding at once.
+///
+/// Responses are matched to their sender by [`RequestId`], so a responder may answer requests in any order.
+/// Invocations are cancellation safe in the same way as [`Channel`].
+pub struct MultiChannel<M: RawMutex, C, R, const N: usize> {
+    /// Queue of pending commands
+    commands: channel::Channel<M, (C, RequestId), N>,
+    /// Response slots, one per outstanding request
+    slots: blocking_mutex::Mutex<M, RefCell<Slots<R, N>>>,
+    /// Unique ID for the next invocation
+    next_request_id: AtomicUsize,
+}
+
+impl<M: RawMutex, C, R, const N: usize> MultiChannel<M, C, R, N> {
+    /// Create a new channel
+    pub const fn new() -> Self {
+        Self {
+            commands: channel::Channel::new(),
+            slots: blocking_mutex::Mutex::new(RefCell::new(Slots {
+                slots: [const { Slot::EMPTY }; N],
+                waiters: MultiWakerRegistration::new(),
+            })),
+            next_request_id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a free slot and claim it for a new request
+    async fn claim_slot(&self) -> (usize, RequestId) {
+        poll_fn(|cx| {
+            self.slots.lock(|slots| {
+                let mut slots = slots.borrow_mut();
+                match slots.slots.iter().position(|slot| slot.request_id.is_none()) {
+                    Some(index) => {
+                        let request_id = RequestId(self.next_request_id.fetch_add(1, Ordering::SeqCst));
+                        slots.slots[index].request_id = Some(request_id);
+                        Poll::Ready((index, request_id))
+                    }
+                    None => {
+                        slots.waiters.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    /// Free a slot and wake any senders waiting for one
+    fn release_slot(&self, index: usize) {
+        self.slots.lock(|slots| {
+            let mut slots = slots.borrow_mut();
+            slots.slots[index] = Slot::EMPTY;
+            slots.waiters.wake();
+        });
+    }
+
+    /// Returns true if the given request is still waiting for a response
+    fn is_active(&self, request_id: RequestId) -> bool {
+        self.slots.lock(|slots| {
+            slots
+                .borrow()
+                .slots
+                .iter()
+                .any(|slot| slot.request_id == Some(request_id))
+        })
+    }
+
+    /// Send a command and return the response, waits for a free slot if `N` requests are already outstanding
+    pub async fn execute(&self, command: C) -> R {
+        let (index, request_id) = self.claim_slot().await;
+        // Free the slot if this future is dropped, a queued command is then skipped by the receiver
+        let _release = OnDrop(|| self.release_slot(index));
+
+        self.commands.send((command, request_id)).await;
+        poll_fn(|cx| {
+            self.slots.lock(|slots| {
+                let mut slots = slots.borrow_mut();
+                let slot = &mut slots.slots[index];
+                match slot.response.take() {
+                    Some(response) => Poll::Ready(response),
+                    None => {
+                        slot.waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    /// Send a command and return the response, or an error if no response was received within `timeout`
+    ///
+    /// The command is withdrawn on timeout, see [`MultiChannel::execute`]
+    pub async fn execute_with_timeout(&self, command: C, timeout: Duration) -> Result<R, TimeoutError> {
+        with_timeout(timeout, self.execute(command)).await
+    }
+
+    /// Wait for an invocation, commands whose sender has already stopped waiting are skipped
+    pub async fn receive(&self) -> MultiRequest<'_, M, C, R, N> {
+        loop {
+            let (command, request_id) = self.commands.receive().await;
+            if self.is_active(request_id) {
+                return MultiRequest {
+                    channel: self,
+                    request_id,
+                    command,
+                };
+            }
+
+            debug!("Skipping cancelled request {}", request_id.0);
+        }
+    }
+
+    fn respond(&self, request_id: RequestId, response: R) {
+        let delivered = self.slots.lock(|slots| {
+            let mut slots = slots.borrow_mut();
+            match slots.slots.iter_mut().find(|slot| slot.request_id == Some(request_id)) {
+                Some(slot) => {
+                    slot.response = Some(response);
+                    slot.waker.wake();
+                    true
+                }
+                None => false,
+            }
+        });
+
+        if !delivered {
+            warn!("Discarding response to cancelled request {}", request_id.0);
+        }
+    }
+}
+
+impl<M: RawMutex, C, R, const N: usize> Default for MultiChannel<M, C, R, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A specific request received from a [`MultiChannel`]
+pub struct MultiRequest<'a, M: RawMutex, C, R, const N: usize> {
+    /// The channel this invocation came from
+    channel: &'a MultiChannel<M, C, R, N>,
+    /// Request ID
+    request_id: RequestId,
+    /// Command to execute
+    pub command: C,
+}
+
+impl<M: RawMutex, C, R, const N: usize> MultiRequest<'_, M, C, R, N> {
+    /// Returns the ID of this request
+    pub fn id(&self) -> RequestId {
+        self.request_id
+    }
+
+    /// Returns true if the sender stopped waiting for a response, e.g. because it timed out
+    pub fn is_cancelled(&self) -> bool {
+        !self.channel.is_active(self.request_id)
+    }
+
+    /// Send a response to the command, consuming the command in the process.
+    ///
+    /// Responses to cancelled requests are discarded.
+    pub fn respond(self, response: R) {
+        self.channel.respond(self.request_id, response);
+    }
+}
+
+/// Runs a closure when dropped
+struct OnDrop<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for OnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin::pin;
+
+    use embassy_futures::join::join;
+    use embassy_futures::{block_on, poll_once};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let channel: Channel<NoopRawMutex, u32, u32> = Channel::new();
+
+        let (response, _) = block_on(join(channel.execute(2), async {
+            let request = channel.receive().await;
+            assert!(!request.is_cancelled());
+            let response = request.command * 10;
+            request.respond(response);
+        }));
+        assert_eq!(response, 20);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let channel: Channel<NoopRawMutex, u32, u32> = Channel::new();
+
+        // cancelled before the command was received, the command is withdrawn
+        {
+            let mut execute = pin!(channel.execute(1));
+            assert!(poll_once(execute.as_mut()).is_pending());
+        }
+        assert!(poll_once(pin!(channel.receive())).is_pending());
+
+        // cancelled after the command was received, the late response is discarded
+        let request = {
+            let mut execute = pin!(channel.execute(2));
+            assert!(poll_once(execute.as_mut()).is_pending());
+            let Poll::Ready(request) = poll_once(pin!(channel.receive())) else {
+                panic!("Command not received");
+            };
+            request
+        };
+        assert!(request.is_cancelled());
+        request.respond(20);
+
+        // the next request gets its own response
+        let (response, _) = block_on(join(channel.execute(3), async {
+            let request = channel.receive().await;
+            assert_eq!(request.command, 3);
+            request.respond(30);
+        }));
+        assert_eq!(response, 30);
+    }
+
+    #[test]
+    fn test_multi_channel() {
+        let channel: MultiChannel<NoopRawMutex, u32, u32, 2> = MultiChannel::new();
+
+        let mut first = pin!(channel.execute(1));
+        let mut second = pin!(channel.execute(2));
+        let mut third = pin!(channel.execute(3));
+        assert!(poll_once(first.as_mut()).is_pending());
+        assert!(poll_once(second.as_mut()).is_pending());
+
+        // both slots are in use
+        assert!(poll_once(third.as_mut()).is_pending());
+
+        let Poll::Ready(request_1) = poll_once(pin!(channel.receive())) else {
+            panic!("Command not received");
+        };
+        let Poll::Ready(request_2) = poll_once(pin!(channel.receive())) else {
+            panic!("Command not received");
+        };
+        assert_eq!((request_1.command, request_2.command), (1, 2));
+
+        // respond out of order
+        request_2.respond(20);
+        assert!(poll_once(first.as_mut()).is_pending());
+        assert_eq!(poll_once(second.as_mut()), Poll::Ready(20));
+        request_1.respond(10);
+        assert_eq!(poll_once(first.as_mut()), Poll::Ready(10));
+
+        // a slot is free again
+        assert!(poll_once(third.as_mut()).is_pending());
+        let Poll::Ready(request_3) = poll_once(pin!(channel.receive())) else {
+            panic!("Command not received");
+        };
+        request_3.respond(30);
+        assert_eq!(poll_once(third.as_mut()), Poll::Ready(30));
+    }
+
+    #[test]
+    fn test_multi_channel_cancel() {
+        let channel: MultiChannel<NoopRawMutex, u32, u32, 2> = MultiChannel::new();
+
+        // cancelled requests are skipped by the receiver
+        {
+            let mut execute = pin!(channel.execute(1));
+            assert!(poll_once(execute.as_mut()).is_pending());
+        }
+
+        let (response, _) = block_on(join(channel.execute(2), async {
+            let request = channel.receive().await;
+            assert_eq!(request.command, 2);
+            request.respond(20);
+        }));
+        assert_eq!(response, 20);
+
+        // late responses are discarded
+        let request = {
+            let mut execute = pin!(channel.execute(3));
+            assert!(poll_once(execute.as_mut()).is_pending());
+            let Poll::Ready(request) = poll_once(pin!(channel.receive())) else {
+                panic!("Command not received");
+            };
+            request
+        };
+        assert!(request.is_cancelled());
+        request.respond(30);
+        assert!(poll_once(pin!(channel.receive())).is_pending());
+    }
+}
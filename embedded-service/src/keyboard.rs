@@ -12,12 +12,12 @@ use crate::comms::{self, EndpointID, External, Internal};
 pub struct DeviceId(pub u8);
 
 /// Keyboard key
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Key(pub u8);
 
 /// Key event data
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum KeyEvent {
     /// Key release
@@ -26,11 +26,28 @@ pub enum KeyEvent {
     Make(Key),
 }
 
+/// OEM hotkey identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Hotkey(pub u8);
+
+/// OEM hotkey event data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HotkeyEvent {
+    /// Hotkey release
+    Break(Hotkey),
+    /// Hotkey press
+    Make(Hotkey),
+}
+
 /// Keyboard event messages
 #[derive(Clone)]
 pub enum Event<'a> {
     /// Key press event
     KeyEvent(DeviceId, SharedRef<'a, KeyEvent>),
+    /// OEM hotkey event, hotkeys are handled by the EC and are not reported as keys
+    Hotkey(DeviceId, HotkeyEvent),
 }
 
 /// Top-level message data enum
@@ -65,11 +82,15 @@ struct Context {
 
 static CONTEXT: OnceLock<Context> = OnceLock::new();
 
+/// Common keyboard service readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("keyboard", &[&comms::READINESS]);
+
 /// Initialize common keyboard service functionality
 pub fn init() {
     CONTEXT.get_or_init(|| Context {
         broadcast_config: Cell::new(BroadcastConfig::default()),
     });
+    READINESS.ready();
 }
 
 /// Enable broadcasting messages to the host endpoint
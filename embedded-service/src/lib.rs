@@ -29,4 +29,6 @@ pub async fn init() {
     keyboard::init();
     power::policy::init();
     type_c::controller::init();
+
+    init::CORE.ready();
 }
@@ -26,7 +26,7 @@ pub trait ChargeController: embedded_batteries_async::charger::Charger {
 }
 
 /// Charger Device ID new type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ChargerId(pub u8);
 
@@ -205,6 +205,14 @@ impl intrusive_list::NodeContainer for Device {
     }
 }
 
+impl intrusive_list::Keyed for Device {
+    type Key = ChargerId;
+
+    fn key(&self) -> ChargerId {
+        self.id
+    }
+}
+
 /// Trait for any container that holds a device
 pub trait ChargerContainer {
     /// Get the underlying device struct
@@ -3,11 +3,16 @@ use core::ops::DerefMut;
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
+use embassy_time::Duration;
 
 use super::{action, DeviceId, Error, PowerCapability};
 use crate::intrusive_list;
 use crate::ipc::deferred;
 
+/// Default command timeout
+/// set to high value since this is intended to prevent an unresponsive device from blocking the service implementation
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(5000);
+
 /// Most basic device states
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -175,9 +180,12 @@ impl Device {
         self.state().await.kind() == StateKind::ConnectedProvider
     }
 
-    /// Execute a command on the device
+    /// Execute a command on the device, the command is withdrawn if the device doesn't respond in time
     pub(super) async fn execute_device_command(&self, command: CommandData) -> Result<ResponseData, Error> {
-        self.command.execute(command).await
+        self.command
+            .execute_with_timeout(command, DEFAULT_TIMEOUT)
+            .await
+            .map_err(|_| Error::Timeout)?
     }
 
     /// Create a handler for the command channel
@@ -271,6 +279,14 @@ impl intrusive_list::NodeContainer for Device {
     }
 }
 
+impl intrusive_list::Keyed for Device {
+    type Key = DeviceId;
+
+    fn key(&self) -> DeviceId {
+        self.id
+    }
+}
+
 /// Trait for any container that holds a device
 pub trait DeviceContainer {
     /// Get the underlying device struct
@@ -4,7 +4,7 @@ pub mod charger;
 pub mod device;
 pub mod policy;
 
-pub use policy::{init, register_device};
+pub use policy::{deregister_device, init, register_device, READINESS};
 
 /// Error type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,7 +29,7 @@ pub enum Error {
 }
 
 /// Device ID new type
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DeviceId(pub u8);
 
@@ -72,6 +72,16 @@ pub enum CommsData {
     ConsumerConnected(DeviceId, PowerCapability),
 }
 
+impl CommsData {
+    /// Priority to send the data with, losing the consumer leaves the system on battery
+    pub fn priority(&self) -> crate::comms::Priority {
+        match self {
+            CommsData::ConsumerDisconnected(_) => crate::comms::Priority::Critical,
+            CommsData::ConsumerConnected(_, _) => crate::comms::Priority::Normal,
+        }
+    }
+}
+
 /// Message to send with the comms service
 pub struct CommsMessage {
     /// Message data
@@ -8,8 +8,8 @@ use embassy_sync::once_lock::OnceLock;
 use super::charger::ChargerResponse;
 use super::device::{self};
 use super::{action, charger, DeviceId, Error, PowerCapability};
+use crate::intrusive_list;
 use crate::power::policy::charger::ChargerResponseData::Ack;
-use crate::{error, intrusive_list};
 
 /// Number of slots for policy requests
 const POLICY_CHANNEL_SIZE: usize = 1;
@@ -73,20 +73,20 @@ type InternalResponseData = Result<ResponseData, Error>;
 /// Power policy context
 struct Context {
     /// Registered devices
-    devices: intrusive_list::IntrusiveList,
+    devices: intrusive_list::TypedIntrusiveList<device::Device>,
     /// Policy request
     policy_request: Channel<NoopRawMutex, Request, POLICY_CHANNEL_SIZE>,
     /// Policy response
     policy_response: Channel<NoopRawMutex, InternalResponseData, POLICY_CHANNEL_SIZE>,
     /// Registered chargers
-    chargers: intrusive_list::IntrusiveList,
+    chargers: intrusive_list::TypedIntrusiveList<charger::Device>,
 }
 
 impl Context {
     fn new() -> Self {
         Self {
-            devices: intrusive_list::IntrusiveList::new(),
-            chargers: intrusive_list::IntrusiveList::new(),
+            devices: intrusive_list::TypedIntrusiveList::new(),
+            chargers: intrusive_list::TypedIntrusiveList::new(),
             policy_request: Channel::new(),
             policy_response: Channel::new(),
         }
@@ -95,59 +95,53 @@ impl Context {
 
 static CONTEXT: OnceLock<Context> = OnceLock::new();
 
+/// Power policy service readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("power-policy", &[]);
+
 /// Init power policy service
 pub fn init() {
     CONTEXT.get_or_init(Context::new);
+    READINESS.ready();
 }
 
-/// Register a device with the power policy service
+/// Register a device with the power policy service, devices are kept ordered by ID
 pub async fn register_device(device: &'static impl device::DeviceContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_power_policy_device();
     if get_device(device.id()).await.is_some() {
         return Err(intrusive_list::Error::NodeAlreadyInList);
     }
 
-    CONTEXT.get().await.devices.push(device)
+    CONTEXT.get().await.devices.insert_sorted(device)
+}
+
+/// Deregister a device from the power policy service
+pub async fn deregister_device(device: &'static impl device::DeviceContainer) -> Result<(), intrusive_list::Error> {
+    CONTEXT.get().await.devices.remove(device.get_power_policy_device())
 }
 
-/// Register a charger with the power policy service
+/// Register a charger with the power policy service, chargers are kept ordered by ID
 pub async fn register_charger(device: &'static impl charger::ChargerContainer) -> Result<(), intrusive_list::Error> {
     let device = device.get_charger();
     if get_charger(device.id()).await.is_some() {
         return Err(intrusive_list::Error::NodeAlreadyInList);
     }
 
-    CONTEXT.get().await.chargers.push(device)
+    CONTEXT.get().await.chargers.insert_sorted(device)
+}
+
+/// Deregister a charger from the power policy service
+pub async fn deregister_charger(device: &'static impl charger::ChargerContainer) -> Result<(), intrusive_list::Error> {
+    CONTEXT.get().await.chargers.remove(device.get_charger())
 }
 
 /// Find a device by its ID
 async fn get_device(id: DeviceId) -> Option<&'static device::Device> {
-    for device in &CONTEXT.get().await.devices {
-        if let Some(data) = device.data::<device::Device>() {
-            if data.id() == id {
-                return Some(data);
-            }
-        } else {
-            error!("Non-device located in devices list");
-        }
-    }
-
-    None
+    CONTEXT.get().await.devices.get(id)
 }
 
 /// Find a device by its ID
 async fn get_charger(id: charger::ChargerId) -> Option<&'static charger::Device> {
-    for charger in &CONTEXT.get().await.chargers {
-        if let Some(data) = charger.data::<charger::Device>() {
-            if data.id() == id {
-                return Some(data);
-            }
-        } else {
-            error!("Non-device located in charger list");
-        }
-    }
-
-    None
+    CONTEXT.get().await.chargers.get(id)
 }
 
 /// Convenience function to send a request to the power policy service
@@ -166,9 +160,7 @@ pub(super) async fn send_request(from: DeviceId, request: RequestData) -> Result
 /// Initialize chargers in hardware
 pub async fn init_chargers() -> ChargerResponse {
     for charger in &CONTEXT.get().await.chargers {
-        if let Some(data) = charger.data::<charger::Device>() {
-            data.execute_command(charger::PolicyEvent::InitRequest).await?;
-        }
+        charger.execute_command(charger::PolicyEvent::InitRequest).await?;
     }
     Ok(Ack)
 }
@@ -212,7 +204,7 @@ impl ContextToken {
     }
 
     /// Provides access to the device list
-    pub async fn devices(&self) -> &intrusive_list::IntrusiveList {
+    pub async fn devices(&self) -> &intrusive_list::TypedIntrusiveList<device::Device> {
         &CONTEXT.get().await.devices
     }
 
@@ -222,7 +214,7 @@ impl ContextToken {
     }
 
     /// Provides access to the charger list
-    pub async fn chargers(&self) -> &intrusive_list::IntrusiveList {
+    pub async fn chargers(&self) -> &intrusive_list::TypedIntrusiveList<charger::Device> {
         &CONTEXT.get().await.chargers
     }
 
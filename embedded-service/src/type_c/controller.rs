@@ -377,7 +377,8 @@ struct Context {
     controllers: intrusive_list::IntrusiveList,
     port_events: Signal<NoopRawMutex, PortEventFlags>,
     /// Channel for receiving commands to the type-C service
-    external_command: deferred::Channel<NoopRawMutex, external::Command, external::Response<'static>>,
+    external_command:
+        deferred::MultiChannel<NoopRawMutex, external::Command, external::Response<'static>, EXTERNAL_COMMAND_SLOTS>,
 }
 
 impl Context {
@@ -385,16 +386,20 @@ impl Context {
         Self {
             controllers: intrusive_list::IntrusiveList::new(),
             port_events: Signal::new(),
-            external_command: deferred::Channel::new(),
+            external_command: deferred::MultiChannel::new(),
         }
     }
 }
 
 static CONTEXT: OnceLock<Context> = OnceLock::new();
 
+/// PD controller context readiness
+pub static READINESS: crate::init::Service = crate::init::Service::new("type-c", &[]);
+
 /// Initialize the PD controller context
 pub fn init() {
     CONTEXT.get_or_init(Context::new);
+    READINESS.ready();
 }
 
 /// Register a PD controller
@@ -421,6 +426,9 @@ pub(super) async fn lookup_controller(controller_id: ControllerId) -> Result<&'s
 /// set to high value since this is intended to prevent an unresponsive device from blocking the service implementation
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(5000);
 
+/// Number of external commands that can be outstanding at once, external commands come from several services
+pub const EXTERNAL_COMMAND_SLOTS: usize = 4;
+
 /// Type to provide access to the PD controller context for service implementations
 pub struct ContextToken(());
 
@@ -679,7 +687,8 @@ impl ContextToken {
     /// Wait for an external command
     pub async fn wait_external_command(
         &self,
-    ) -> deferred::Request<NoopRawMutex, external::Command, external::Response<'static>> {
+    ) -> deferred::MultiRequest<NoopRawMutex, external::Command, external::Response<'static>, EXTERNAL_COMMAND_SLOTS>
+    {
         CONTEXT.get().await.external_command.receive().await
     }
 }
@@ -689,7 +698,12 @@ pub(super) async fn execute_external_port_command(
     command: external::Command,
 ) -> Result<external::PortResponseData, PdError> {
     let context = CONTEXT.get().await;
-    match context.external_command.execute(command).await {
+    match context
+        .external_command
+        .execute_with_timeout(command, DEFAULT_TIMEOUT)
+        .await
+        .map_err(|_| PdError::Timeout)?
+    {
         external::Response::Port(response) => response,
         r => {
             error!("Invalid response: expected external port, got {:?}", r);
@@ -703,7 +717,12 @@ pub(super) async fn execute_external_controller_command(
     command: external::Command,
 ) -> Result<external::ControllerResponseData<'static>, PdError> {
     let context = CONTEXT.get().await;
-    match context.external_command.execute(command).await {
+    match context
+        .external_command
+        .execute_with_timeout(command, DEFAULT_TIMEOUT)
+        .await
+        .map_err(|_| PdError::Timeout)?
+    {
         external::Response::Controller(response) => response,
         r => {
             error!("Invalid response: expected external controller, got {:?}", r);
@@ -0,0 +1,71 @@
+//! Routing policy enforcement
+//!
+//! The routing policy is global and can only be installed once, so it's tested in its own binary to keep it away
+//! from the unit tests that send from external endpoints.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use embassy_sync::once_lock::OnceLock;
+use embedded_services::comms::{
+    self, Endpoint, EndpointID, External, Internal, MailboxDelegate, MailboxDelegateError, Message, Rule, SendError,
+};
+
+struct Receiver {
+    tp: Endpoint,
+    received: AtomicUsize,
+}
+
+impl MailboxDelegate for Receiver {
+    fn receive(&self, _message: &Message) -> Result<(), MailboxDelegateError> {
+        self.received.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+static POLICY: &[Rule] = &[Rule::allow::<u32>(External::Host, Internal::Battery)];
+
+#[test]
+fn test_routing_policy() {
+    static FIRST: OnceLock<Receiver> = OnceLock::new();
+    static SECOND: OnceLock<Receiver> = OnceLock::new();
+    let receiver = || Receiver {
+        tp: Endpoint::uninit(Internal::Battery.into()),
+        received: AtomicUsize::new(0),
+    };
+    let first = FIRST.get_or_init(receiver);
+    let second = SECOND.get_or_init(receiver);
+
+    embassy_futures::block_on(async {
+        embedded_services::init().await;
+        comms::register_endpoint(first, &first.tp).await.unwrap();
+        comms::register_endpoint(second, &second.tp).await.unwrap();
+        comms::set_routing_policy(POLICY).unwrap();
+
+        let host = EndpointID::External(External::Host);
+        let battery = EndpointID::Internal(Internal::Battery);
+
+        // allowed messages reach every receiver
+        assert_eq!(Ok(()), comms::send(host, battery, &1u32).await);
+        assert_eq!(0, comms::policy_violations());
+
+        // a refused message is counted once, however many receivers share the destination
+        assert_eq!(
+            Err(SendError::Rejected(MailboxDelegateError::InvalidSource)),
+            comms::send(host, battery, &1u8).await
+        );
+        assert_eq!(1, comms::policy_violations());
+
+        // destinations without receivers are checked too
+        assert_eq!(
+            Err(SendError::Rejected(MailboxDelegateError::InvalidSource)),
+            comms::send(host, Internal::Security.into(), &1u32).await
+        );
+        assert_eq!(2, comms::policy_violations());
+
+        // internal sources are not restricted
+        assert_eq!(Ok(()), comms::send(Internal::Power.into(), battery, &1u8).await);
+        assert_eq!(2, comms::policy_violations());
+
+        assert_eq!(2, first.received.load(Ordering::SeqCst));
+        assert_eq!(2, second.received.load(Ordering::SeqCst));
+    });
+}
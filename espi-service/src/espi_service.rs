@@ -1,175 +1,303 @@
 use core::cell::RefCell;
-use core::mem::offset_of;
 use core::slice;
 
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::once_lock::OnceLock;
+use embassy_sync::signal::Signal;
 use embedded_services::comms::{self, EndpointID, External, Internal};
-use embedded_services::{ec_type, error, info};
+use embedded_services::ec_type::event::{self, Doorbell};
+use embedded_services::ec_type::message::{EventQueueMessage, Indexed};
+use embedded_services::ec_type::{self, structure, SectionId};
+use embedded_services::{error, info};
+
+/// Routing policy for host writes forwarded by the eSPI service, install with [`comms::set_routing_policy`]
+pub const ROUTING_POLICY: &[comms::Rule] = &[
+    comms::Rule::allow::<ec_type::message::BatteryMessage>(External::Host, Internal::Battery),
+    comms::Rule::allow::<Indexed<ec_type::message::BatteryMessage>>(External::Host, Internal::Battery),
+    comms::Rule::allow::<ec_type::message::ThermalMessage>(External::Host, Internal::Thermal),
+    comms::Rule::allow::<ec_type::message::FanMessage>(External::Host, Internal::Thermal),
+    comms::Rule::allow::<Indexed<ec_type::message::FanMessage>>(External::Host, Internal::Thermal),
+    comms::Rule::allow::<ec_type::message::SensorMessage>(External::Host, Internal::Thermal),
+    comms::Rule::allow::<Indexed<ec_type::message::SensorMessage>>(External::Host, Internal::Thermal),
+    comms::Rule::allow::<ec_type::message::TimeAlarmMessage>(External::Host, Internal::TimeAlarm),
+];
 
 pub struct Service<'a> {
     endpoint: comms::Endpoint,
-    ec_memory: RefCell<&'a mut ec_type::structure::ECMemory>,
+    memory_map: RefCell<ec_type::MemoryMap<'a>>,
+    consumer_moved: Signal<CriticalSectionRawMutex, ()>,
 }
 
-impl Service<'_> {
-    pub fn new(ec_memory: &'static mut ec_type::structure::ECMemory) -> Self {
+impl<'a> Service<'a> {
+    pub fn new(memory_map: ec_type::MemoryMap<'a>) -> Self {
         Service {
             endpoint: comms::Endpoint::uninit(EndpointID::External(External::Host)),
-            ec_memory: RefCell::new(ec_memory),
+            memory_map: RefCell::new(memory_map),
+            consumer_moved: Signal::new(),
         }
     }
 
-    fn update_battery_section(&self, msg: &ec_type::message::BatteryMessage) {
-        let mut memory_map = self.ec_memory.borrow_mut();
-        ec_type::update_battery_section(msg, &mut memory_map);
-    }
-
-    fn update_capabilities_section(&self, msg: &ec_type::message::CapabilitiesMessage) {
-        let mut memory_map = self.ec_memory.borrow_mut();
-        ec_type::update_capabilities_section(msg, &mut memory_map);
-    }
-
-    fn update_thermal_section(&self, msg: &ec_type::message::ThermalMessage) {
-        let mut memory_map = self.ec_memory.borrow_mut();
-        ec_type::update_thermal_section(msg, &mut memory_map);
-    }
-
-    fn update_time_alarm_section(&self, msg: &ec_type::message::TimeAlarmMessage) {
-        let mut memory_map = self.ec_memory.borrow_mut();
-        ec_type::update_time_alarm_section(msg, &mut memory_map);
+    fn update_section<S: ec_type::Section>(
+        &self,
+        index: u8,
+        msg: &S::Message,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.memory_map
+            .borrow_mut()
+            .update_instance::<S>(index as usize, msg)
+            .map_err(|e| match e {
+                ec_type::Error::OutOfRange => {
+                    error!("Value for section {:?} instance {} is out of range", S::ID, index);
+                    comms::MailboxDelegateError::InvalidData
+                }
+                _ => {
+                    error!(
+                        "Section {:?} instance {} is not enabled in the memory map",
+                        S::ID,
+                        index
+                    );
+                    comms::MailboxDelegateError::InvalidDestination
+                }
+            })
     }
 
     async fn route_to_service(&self, offset: usize, length: usize) -> Result<(), ec_type::Error> {
         let mut offset = offset;
         let mut length = length;
 
-        if offset + length > size_of::<ec_type::structure::ECMemory>() {
+        if offset + length > self.memory_map.borrow().layout().size() {
             return Err(ec_type::Error::InvalidLocation);
         }
 
         while length > 0 {
-            if (offset >= offset_of!(ec_type::structure::ECMemory, ver)
-                && offset < offset_of!(ec_type::structure::ECMemory, ver) + size_of::<ec_type::structure::Version>())
-                || (offset >= offset_of!(ec_type::structure::ECMemory, caps)
-                    && offset
-                        < offset_of!(ec_type::structure::ECMemory, caps)
-                            + size_of::<ec_type::structure::Capabilities>())
-            {
-                // This is a read-only section. eSPI master should not write to it.
-                return Err(ec_type::Error::InvalidLocation);
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, batt)
-                && offset < offset_of!(ec_type::structure::ECMemory, batt) + size_of::<ec_type::structure::Battery>()
-            {
-                self.route_to_battery_service(&mut offset, &mut length).await?;
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, therm)
-                && offset < offset_of!(ec_type::structure::ECMemory, therm) + size_of::<ec_type::structure::Thermal>()
-            {
-                self.route_to_thermal_service(&mut offset, &mut length).await?;
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, alarm)
-                && offset < offset_of!(ec_type::structure::ECMemory, alarm) + size_of::<ec_type::structure::TimeAlarm>()
-            {
-                self.route_to_time_alarm_service(&mut offset, &mut length).await?;
+            let section = self.memory_map.borrow().layout().locate(offset).map(|(id, _, _)| id);
+            match section {
+                Some(SectionId::Battery) => {
+                    self.route::<structure::Battery>(Internal::Battery, &mut offset, &mut length)
+                        .await?
+                }
+                Some(SectionId::Thermal) => {
+                    self.route::<structure::Thermal>(Internal::Thermal, &mut offset, &mut length)
+                        .await?
+                }
+                Some(SectionId::TimeAlarm) => {
+                    self.route::<structure::TimeAlarm>(Internal::TimeAlarm, &mut offset, &mut length)
+                        .await?
+                }
+                Some(SectionId::Fan) => {
+                    self.route::<structure::Fan>(Internal::Thermal, &mut offset, &mut length)
+                        .await?
+                }
+                Some(SectionId::Sensor) => {
+                    self.route::<structure::Sensor>(Internal::Thermal, &mut offset, &mut length)
+                        .await?
+                }
+                Some(SectionId::EventQueue) => {
+                    // Only the consumer index belongs to the host, it frees slots for queued events
+                    let msg = self
+                        .memory_map
+                        .borrow()
+                        .decode::<structure::EventQueue>(&mut offset, &mut length)?;
+                    match msg.message {
+                        EventQueueMessage::Consumer(_) => self.consumer_moved.signal(()),
+                        EventQueueMessage::Producer(_) => return Err(ec_type::Error::InvalidLocation),
+                    }
+                }
+                // The header, directory, capabilities and event entries are read-only for the eSPI master
+                Some(SectionId::Capabilities) | Some(SectionId::EventEntry) | None => {
+                    return Err(ec_type::Error::InvalidLocation)
+                }
             }
         }
 
         Ok(())
     }
 
-    async fn route_to_battery_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
-        let msg = {
-            let memory_map = self.ec_memory.borrow();
-            ec_type::mem_map_to_battery_msg(&memory_map, offset, length)?
+    async fn route<S: ec_type::Section>(
+        &self,
+        to: Internal,
+        offset: &mut usize,
+        length: &mut usize,
+    ) -> Result<(), ec_type::Error>
+    where
+        S::Message: 'static,
+    {
+        let (msg, single) = {
+            let memory_map = self.memory_map.borrow();
+            (
+                memory_map.decode::<S>(offset, length)?,
+                memory_map.layout().count(S::ID) == 1,
+            )
         };
 
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::Battery),
-            &msg,
-        )
-        .await
-        .unwrap();
+        // Sections with a single instance send bare messages so existing services keep working
+        let from = EndpointID::External(External::Host);
+        let result = if single {
+            comms::send(from, EndpointID::Internal(to), &msg.message).await
+        } else {
+            comms::send(from, EndpointID::Internal(to), &msg).await
+        };
+
+        if result.is_err() {
+            error!("Failed to route host write to {:?} service", S::ID);
+        }
 
         Ok(())
     }
+}
 
-    async fn route_to_thermal_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
-        let msg = {
-            let memory_map = self.ec_memory.borrow();
-            ec_type::mem_map_to_thermal_msg(&memory_map, offset, length)?
-        };
+impl comms::Handler<ec_type::message::CapabilitiesMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::CapabilitiesMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Capabilities>(0, msg)
+    }
+}
 
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::Thermal),
-            &msg,
-        )
-        .await
-        .unwrap();
+impl comms::Handler<ec_type::message::BatteryMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::BatteryMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Battery>(0, msg)
+    }
+}
 
-        Ok(())
+impl comms::Handler<Indexed<ec_type::message::BatteryMessage>> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &Indexed<ec_type::message::BatteryMessage>,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Battery>(msg.index, &msg.message)
     }
+}
 
-    async fn route_to_time_alarm_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
-        let msg = {
-            let memory_map = self.ec_memory.borrow();
-            ec_type::mem_map_to_time_alarm_msg(&memory_map, offset, length)?
-        };
+impl comms::Handler<ec_type::message::ThermalMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::ThermalMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Thermal>(0, msg)
+    }
+}
 
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::TimeAlarm),
-            &msg,
-        )
-        .await
-        .unwrap();
+impl comms::Handler<ec_type::message::FanMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::FanMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Fan>(0, msg)
+    }
+}
 
-        Ok(())
+impl comms::Handler<Indexed<ec_type::message::FanMessage>> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &Indexed<ec_type::message::FanMessage>,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Fan>(msg.index, &msg.message)
     }
 }
 
-impl comms::MailboxDelegate for Service<'_> {
-    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
-        if let Some(msg) = message.data.get::<ec_type::message::CapabilitiesMessage>() {
-            self.update_capabilities_section(msg);
-        } else if let Some(msg) = message.data.get::<ec_type::message::BatteryMessage>() {
-            self.update_battery_section(msg);
-        } else if let Some(msg) = message.data.get::<ec_type::message::ThermalMessage>() {
-            self.update_thermal_section(msg);
-        } else if let Some(msg) = message.data.get::<ec_type::message::TimeAlarmMessage>() {
-            self.update_time_alarm_section(msg);
-        } else {
-            return Err(comms::MailboxDelegateError::MessageNotFound);
-        }
+impl comms::Handler<ec_type::message::SensorMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::SensorMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Sensor>(0, msg)
+    }
+}
 
-        Ok(())
+impl comms::Handler<Indexed<ec_type::message::SensorMessage>> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &Indexed<ec_type::message::SensorMessage>,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::Sensor>(msg.index, &msg.message)
     }
 }
 
+impl comms::Handler<ec_type::message::TimeAlarmMessage> for Service<'_> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        msg: &ec_type::message::TimeAlarmMessage,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        self.update_section::<structure::TimeAlarm>(0, msg)
+    }
+}
+
+embedded_services::impl_mailbox_delegate!(impl for Service<'_> {
+    ec_type::message::CapabilitiesMessage,
+    ec_type::message::BatteryMessage,
+    Indexed<ec_type::message::BatteryMessage>,
+    ec_type::message::ThermalMessage,
+    ec_type::message::FanMessage,
+    Indexed<ec_type::message::FanMessage>,
+    ec_type::message::SensorMessage,
+    Indexed<ec_type::message::SensorMessage>,
+    ec_type::message::TimeAlarmMessage,
+});
+
 static ESPI_SERVICE: OnceLock<Service> = OnceLock::new();
 
 use embassy_imxrt::espi;
 
-#[embassy_executor::task]
-pub async fn espi_service(mut espi: espi::Espi<'static>, memory_map_buffer: &'static mut [u8]) {
-    info!("Reserved eSPI memory map buffer size: {}", memory_map_buffer.len());
-    info!("eSPI MemoryMap size: {}", size_of::<ec_type::structure::ECMemory>());
+/// Move events posted with [`event::post`] to the memory map event queue and ring the doorbell
+///
+/// Events wait here while the memory map queue is full until the host moves the consumer index. Boards wrap this in
+/// a task with their doorbell, such as an SCI on a GPIO or an eSPI virtual wire.
+pub async fn host_event_task(mut doorbell: impl Doorbell) -> ! {
+    let espi_service = ESPI_SERVICE.get().await;
+
+    'events: loop {
+        let host_event = event::receive().await;
+
+        loop {
+            // Clear a stale signal first so a consumer write after the check isn't missed
+            espi_service.consumer_moved.reset();
+            match espi_service.memory_map.borrow_mut().push_event(host_event) {
+                Ok(()) => break,
+                Err(ec_type::Error::QueueFull) => (),
+                Err(_) => {
+                    error!("Host event queue is not enabled in the memory map, dropping event");
+                    continue 'events;
+                }
+            }
 
-    if size_of::<ec_type::structure::ECMemory>() > memory_map_buffer.len() {
-        panic!("eSPI MemoryMap is too big for reserved memory buffer!!!");
-    }
+            espi_service.consumer_moved.wait().await;
+        }
 
-    memory_map_buffer.fill(0);
+        if doorbell.ring().await.is_err() {
+            error!("Failed to notify host of pending events");
+        }
+    }
+}
 
-    let memory_map: &mut ec_type::structure::ECMemory =
-        unsafe { &mut *(memory_map_buffer.as_mut_ptr() as *mut ec_type::structure::ECMemory) };
+#[embassy_executor::task]
+pub async fn espi_service(
+    mut espi: espi::Espi<'static>,
+    memory_map_buffer: &'static mut [u8],
+    layout: ec_type::Layout,
+) {
+    info!("Reserved eSPI memory map buffer size: {}", memory_map_buffer.len());
+    info!("eSPI MemoryMap size: {}", layout.size());
 
     espi.wait_for_plat_reset().await;
 
     info!("Initializing memory map");
-    memory_map.ver.major = ec_type::structure::EC_MEMMAP_VERSION.major;
-    memory_map.ver.minor = ec_type::structure::EC_MEMMAP_VERSION.minor;
-    memory_map.ver.spin = ec_type::structure::EC_MEMMAP_VERSION.spin;
-    memory_map.ver.res0 = ec_type::structure::EC_MEMMAP_VERSION.res0;
+    let Ok(memory_map) = ec_type::MemoryMap::new(layout, memory_map_buffer) else {
+        panic!("eSPI MemoryMap is too big for reserved memory buffer!!!");
+    };
 
     let espi_service = ESPI_SERVICE.get_or_init(|| Service::new(memory_map));
     comms::register_endpoint(espi_service, &espi_service.endpoint)
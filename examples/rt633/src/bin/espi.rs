@@ -11,6 +11,8 @@ use embassy_imxrt::bind_interrupts;
 use embassy_imxrt::espi::BaseOrAsz;
 use embassy_imxrt::espi::{Base, Capabilities, Config, Direction, Espi, InterruptHandler, Len, Maxspd, PortConfig};
 use embassy_imxrt::peripherals::ESPI;
+use embedded_services::ec_type::event::{self, Doorbell, HostEvent};
+use embedded_services::ec_type::SectionId;
 use {defmt_rtt as _, panic_probe as _};
 
 // Mock battery service
@@ -74,7 +76,7 @@ mod battery_service {
                 .endpoint
                 .send(
                     EndpointID::External(External::Host),
-                    &ec_type::message::BatteryMessage::RemainCap(battery_remain_cap),
+                    &ec_type::message::BatteryMessage::RemainCap(ec_type::units::MilliWattHours(battery_remain_cap)),
                 )
                 .await
                 .unwrap();
@@ -86,6 +88,23 @@ mod battery_service {
     }
 }
 
+// Mock doorbell, a board would raise an SCI or an eSPI virtual wire
+struct LogDoorbell;
+
+impl Doorbell for LogDoorbell {
+    type Error = core::convert::Infallible;
+
+    async fn ring(&mut self) -> Result<(), Self::Error> {
+        info!("Host events pending");
+        Ok(())
+    }
+}
+
+#[embassy_executor::task]
+async fn host_event_task() {
+    espi_service::host_event_task(LogDoorbell).await
+}
+
 bind_interrupts!(struct Irqs {
     ESPI => InterruptHandler<ESPI>;
 });
@@ -100,6 +119,7 @@ async fn main(spawner: Spawner) {
     let p = embassy_imxrt::init(Default::default());
 
     embedded_services::init().await;
+    embedded_services::comms::set_routing_policy(espi_service::ROUTING_POLICY).unwrap();
 
     let espi = Espi::new(
         p.ESPI,
@@ -147,7 +167,19 @@ async fn main(spawner: Spawner) {
         slice::from_raw_parts_mut(start_espi_data, espi_data_len)
     };
 
-    spawner.must_spawn(espi_service::espi_service(espi, memory_map_buffer));
+    let layout = embedded_services::ec_type::Layout::with_instances(&[
+        (SectionId::Capabilities, 1),
+        (SectionId::TimeAlarm, 1),
+        (SectionId::Battery, 1),
+        (SectionId::Thermal, 1),
+        (SectionId::Fan, 1),
+        (SectionId::Sensor, 1),
+        (SectionId::EventQueue, 1),
+        (SectionId::EventEntry, 16),
+    ])
+    .unwrap();
+    spawner.must_spawn(espi_service::espi_service(espi, memory_map_buffer, layout));
+    spawner.must_spawn(host_event_task());
 
     battery_service::init().await;
 
@@ -157,6 +189,15 @@ async fn main(spawner: Spawner) {
         embassy_time::Timer::after_secs(10).await;
         info!("The uptime is {} secs", embassy_time::Instant::now().as_secs());
 
+        // Mock host event
+        let uptime = embassy_time::Instant::now().as_secs() as u32;
+        event::post(HostEvent {
+            service: 0,
+            event: 1,
+            payload: uptime,
+        })
+        .await;
+
         let data = unsafe {
             let start_espi_data = &__start_espi_data as *const u8 as *mut u8;
             let end_espi_data = &__end_espi_data as *const u8 as *mut u8;
@@ -218,13 +218,31 @@ async fn battery_publish_task(fg_device: &'static Device) {
         // Get dynamic cache
         let cache = fg_device.get_dynamic_battery_cache();
 
-        // Send cache data to eSpi service
+        // Send cache data to eSpi service, alarms go ahead of other traffic
+        let priority = if cache.is_critical() {
+            embedded_services::comms::Priority::Critical
+        } else {
+            embedded_services::comms::Priority::Normal
+        };
+        battery_service::comms_send_with_priority(
+            embedded_services::comms::EndpointID::External(embedded_services::comms::External::Host),
+            priority,
+            &embedded_services::ec_type::message::BatteryMessage::Status(cache.battery_status.into()),
+        )
+        .await
+        .unwrap();
         battery_service::comms_send(
             embedded_services::comms::EndpointID::External(embedded_services::comms::External::Host),
             &embedded_services::ec_type::message::BatteryMessage::CycleCount(cache.cycle_count.into()),
         )
         .await
         .unwrap();
+        battery_service::comms_send(
+            embedded_services::comms::EndpointID::External(embedded_services::comms::External::Host),
+            &embedded_services::ec_type::message::BatteryMessage::RemainCap(cache.remaining_capacity_mwh),
+        )
+        .await
+        .unwrap();
     }
 }
 
@@ -241,6 +259,7 @@ async fn main(spawner: Spawner) {
     let p = embassy_imxrt::init(Default::default());
 
     embedded_services::init().await;
+    embedded_services::comms::set_routing_policy(espi_service::ROUTING_POLICY).unwrap();
 
     let espi = Espi::new(
         p.ESPI,
@@ -288,7 +307,13 @@ async fn main(spawner: Spawner) {
         slice::from_raw_parts_mut(start_espi_data, espi_data_len)
     };
 
-    spawner.must_spawn(espi_service::espi_service(espi, memory_map_buffer));
+    // This board only reports battery data to the host
+    let layout = embedded_services::ec_type::Layout::new(&[
+        embedded_services::ec_type::SectionId::Capabilities,
+        embedded_services::ec_type::SectionId::Battery,
+    ])
+    .unwrap();
+    spawner.must_spawn(espi_service::espi_service(espi, memory_map_buffer, layout));
 
     let config = embassy_imxrt::i2c::master::Config {
         speed: embassy_imxrt::i2c::master::Speed::Standard,
@@ -23,6 +23,7 @@ mod activity_example {
         enum Actions {
             TurnOnBacklight,
             TurnOffBacklight(bool),
+            DimBacklight,
         }
 
         pub struct BacklightContext {
@@ -42,6 +43,14 @@ mod activity_example {
                     });
                 }
             }
+
+            fn system_activity_update(&self, activity: activity::SystemActivity) {
+                self.action_queue.signal(match activity {
+                    activity::SystemActivity::Active => Actions::TurnOnBacklight,
+                    activity::SystemActivity::Idle => Actions::DimBacklight,
+                    activity::SystemActivity::Standby => Actions::TurnOffBacklight(true),
+                });
+            }
         }
 
         impl BacklightContext {
@@ -68,6 +77,11 @@ mod activity_example {
                 embassy_time::Timer::after_millis(200).await;
             }
 
+            async fn dim(&self) {
+                info!("Backlight dimmed!");
+                embassy_time::Timer::after_millis(500).await;
+            }
+
             async fn fade_off(&self) {
                 info!("Backlight fading off!");
                 embassy_time::Timer::after_millis(2000).await;
@@ -79,6 +93,7 @@ mod activity_example {
 
                     match event {
                         Actions::TurnOnBacklight => self.turn_on().await,
+                        Actions::DimBacklight => self.dim().await,
                         Actions::TurnOffBacklight(fade) => {
                             if fade {
                                 self.fade_off().await;
@@ -141,6 +156,11 @@ mod activity_example {
     }
 }
 
+#[embassy_executor::task]
+async fn activity_task() {
+    embedded_services::activity::task().await;
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     let _p = embassy_imxrt::init(Default::default());
@@ -151,6 +171,13 @@ async fn main(spawner: Spawner) {
 
     info!("Service initialization complete...");
 
+    // dim the backlight after 10 s without activity, and turn it off after 30 s
+    embedded_services::activity::configure_system(embedded_services::activity::SystemConfig {
+        idle_timeout: embassy_time::Duration::from_secs(10),
+        standby_timeout: embassy_time::Duration::from_secs(30),
+    });
+    spawner.spawn(activity_task()).unwrap();
+
     // create an activity service subscriber
     spawner.spawn(activity_example::backlight::task()).unwrap();
 
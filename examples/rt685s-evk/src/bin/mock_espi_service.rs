@@ -19,7 +19,6 @@ enum RxMessage {
 // Mock eSPI transport service
 mod espi_service {
     use crate::{RxMessage, TxMessage};
-    use core::convert::Infallible;
     use defmt::info;
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
     use embassy_sync::once_lock::OnceLock;
@@ -67,7 +66,7 @@ mod espi_service {
     }
 
     // Funtion to forward a battery_charge_message to the battery service
-    pub async fn forward_set_battery_charge_message(battery_charge: u32) -> Result<(), Infallible> {
+    pub async fn forward_set_battery_charge_message(battery_charge: u32) -> Result<(), comms::SendError> {
         let espi_service = ESPI_SERVICE.get().await;
 
         espi_service
@@ -34,7 +34,7 @@ mod sender {
 
         pub async fn send(&self, message: Message) {
             self.tp
-                .send(EndpointID::Internal(Internal::Power), &message)
+                .send_with_priority(EndpointID::Internal(Internal::Power), comms::Priority::High, &message)
                 .await
                 .unwrap();
         }
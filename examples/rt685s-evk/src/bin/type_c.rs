@@ -14,7 +14,7 @@ use embassy_sync::mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
 use embassy_time::{self as _, Delay};
 use embedded_services::comms;
-use embedded_services::power::policy::DeviceId as PowerId;
+use embedded_services::power::policy::{self, DeviceId as PowerId};
 use embedded_services::type_c::{self, ControllerId};
 use embedded_usb_pd::GlobalPortId;
 use static_cell::StaticCell;
@@ -187,12 +187,22 @@ async fn main(spawner: Spawner) {
 
     comms::register_endpoint(battery, &battery.tp).await.unwrap();
 
+    static BATTERY_SUBSCRIPTION: OnceLock<comms::Subscription> = OnceLock::new();
+    let battery_subscription =
+        BATTERY_SUBSCRIPTION.get_or_init(|| comms::Subscription::new(comms::Topic::of::<policy::CommsMessage>()));
+    comms::subscribe(&battery.tp, battery_subscription).await.unwrap();
+
     static DEBUG_ACCESSORY: OnceLock<debug::Device> = OnceLock::new();
     let debug_accessory = DEBUG_ACCESSORY.get_or_init(|| debug::Device::new());
     comms::register_endpoint(debug_accessory, &debug_accessory.tp)
         .await
         .unwrap();
 
+    static DEBUG_SUBSCRIPTION: OnceLock<comms::Subscription> = OnceLock::new();
+    let debug_subscription = DEBUG_SUBSCRIPTION
+        .get_or_init(|| comms::Subscription::new(comms::Topic::of::<type_c::comms::DebugAccessoryMessage>()));
+    comms::subscribe(&debug_accessory.tp, debug_subscription).await.unwrap();
+
     embassy_time::Timer::after_secs(10).await;
 
     let status = type_c::external::get_controller_status(CONTROLLER0_ID).await.unwrap();
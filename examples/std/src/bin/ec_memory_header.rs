@@ -0,0 +1,8 @@
+//! Prints the C header describing the EC memory map
+use embedded_services::ec_type::header;
+
+fn main() {
+    let mut out = String::new();
+    header::write_c_header(&mut out).unwrap();
+    print!("{out}");
+}
@@ -1,22 +1,67 @@
 use embassy_executor::Executor;
-use embassy_time::{self as _, Timer};
-use embedded_services::init;
+use embassy_time::{Duration, Timer};
+use embedded_services::init::{self, Stage};
 use log::*;
 use static_cell::StaticCell;
 
+/// Sensor bus, comes up once the core interfaces are initialized
+static BUS: init::Service = init::Service::new("bus", &[&init::CORE]);
+/// Temperature sensor on the bus
+static SENSOR: init::Service = init::Service::new("sensor", &[&BUS]);
+/// Fan controlled from the sensor, its hardware never responds
+static FAN: init::Service = init::Service::new("fan", &[&SENSOR]);
+
+#[embassy_executor::task]
+async fn bus_task() {
+    if BUS.wait_dependencies(Stage::HardwareReady).await.is_err() {
+        BUS.fail();
+        return;
+    }
+
+    BUS.reached(Stage::StaticInit);
+    Timer::after_millis(100).await;
+    BUS.ready();
+    info!("Bus ready");
+}
+
 #[embassy_executor::task]
-async fn registration_waiter() {
-    info!("Waiting for registration");
-    init::wait_for_registration().await;
-    info!("Registration done");
+async fn sensor_task() {
+    if SENSOR.wait_dependencies(Stage::HardwareReady).await.is_err() {
+        SENSOR.fail();
+        return;
+    }
+
+    SENSOR.reached(Stage::Registered);
+    Timer::after_millis(100).await;
+    SENSOR.ready();
+    info!("Sensor ready");
 }
 
 #[embassy_executor::task]
-async fn registration_task() {
-    info!("Registration task started");
-    Timer::after(embassy_time::Duration::from_secs(1)).await;
-    init::registration_done();
-    info!("Registration task finished");
+async fn fan_task() {
+    if FAN.wait_dependencies(Stage::HardwareReady).await.is_err() {
+        FAN.fail();
+        return;
+    }
+
+    FAN.reached(Stage::Registered);
+    info!("Fan registered, waiting for hardware");
+}
+
+#[embassy_executor::task]
+async fn boot_task() {
+    embedded_services::init().await;
+
+    // the fan and its dependencies are in the report even before they report a stage
+    FAN.register();
+    let report = init::wait_for_boot(Duration::from_secs(1)).await;
+    if report.is_ok() {
+        info!("Boot complete");
+    } else {
+        for service in report.failed() {
+            error!("{} did not come up", service.name());
+        }
+    }
 }
 
 fn main() {
@@ -25,7 +70,9 @@ fn main() {
     static EXECUTOR: StaticCell<Executor> = StaticCell::new();
     let executor = EXECUTOR.init(Executor::new());
     executor.run(|spawner| {
-        spawner.must_spawn(registration_waiter());
-        spawner.must_spawn(registration_task());
+        spawner.must_spawn(bus_task());
+        spawner.must_spawn(sensor_task());
+        spawner.must_spawn(fan_task());
+        spawner.must_spawn(boot_task());
     });
 }
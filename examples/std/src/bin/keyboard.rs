@@ -2,7 +2,7 @@ use embassy_executor::{Executor, Spawner};
 use embassy_sync::once_lock::OnceLock;
 use embassy_time::Timer;
 use embedded_services::keyboard::{self, DeviceId, Key, KeyEvent};
-use embedded_services::{comms, define_static_buffer};
+use embedded_services::{comms, define_static_buffer_pool};
 use log::info;
 use static_cell::StaticCell;
 
@@ -10,47 +10,45 @@ use static_cell::StaticCell;
 mod device {
     use std::borrow::BorrowMut;
 
-    use embedded_services::buffer::OwnedRef;
+    use embedded_services::buffer::BufferPool;
     use embedded_services::keyboard::{self, DeviceId, Event, Key, KeyEvent, MessageData};
+    use log::error;
+
+    /// Pool of single event blocks, a block is returned once the event has been delivered
+    pub type EventPool = BufferPool<'static, KeyEvent, 1, 8>;
 
     pub struct Device {
         id: DeviceId,
-        event_buffer: OwnedRef<'static, KeyEvent>,
+        events: &'static EventPool,
     }
 
     impl Device {
-        pub fn new(id: DeviceId, event_buffer: OwnedRef<'static, KeyEvent>) -> Self {
-            Self { id, event_buffer }
+        pub fn new(id: DeviceId, events: &'static EventPool) -> Self {
+            Self { id, events }
         }
 
-        pub async fn key_down(&self, key: Key) {
+        async fn send_event(&self, event: KeyEvent) {
+            let Some(block) = self.events.alloc() else {
+                error!("Event pool exhausted, dropping {:?}", event);
+                return;
+            };
+
             {
-                let mut borrow = self.event_buffer.borrow_mut();
+                let mut borrow = block.borrow_mut();
                 let buf: &mut [KeyEvent] = borrow.borrow_mut();
 
-                buf[0] = KeyEvent::Make(key);
+                buf[0] = event;
             }
 
-            keyboard::broadcast_message(
-                self.id,
-                MessageData::Event(Event::KeyEvent(self.id, self.event_buffer.reference().slice(0..1))),
-            )
-            .await;
+            keyboard::broadcast_message(self.id, MessageData::Event(Event::KeyEvent(self.id, block.reference()))).await;
         }
 
-        pub async fn key_up(&self, key: Key) {
-            {
-                let mut borrow = self.event_buffer.borrow_mut();
-                let buf: &mut [KeyEvent] = borrow.borrow_mut();
-
-                buf[0] = KeyEvent::Break(key);
-            }
+        pub async fn key_down(&self, key: Key) {
+            self.send_event(KeyEvent::Make(key)).await;
+        }
 
-            keyboard::broadcast_message(
-                self.id,
-                MessageData::Event(Event::KeyEvent(self.id, self.event_buffer.reference().slice(0..1))),
-            )
-            .await;
+        pub async fn key_up(&self, key: Key) {
+            self.send_event(KeyEvent::Break(key)).await;
         }
     }
 }
@@ -93,6 +91,10 @@ mod host {
 
                     Ok(())
                 }
+                MessageData::Event(Event::Hotkey(id, event)) => {
+                    info!("Host received hotkey from device {}: {:?}", id.0, event);
+                    Ok(())
+                }
             }
         }
     }
@@ -102,11 +104,11 @@ const DEVICE0_ID: DeviceId = DeviceId(0);
 
 #[embassy_executor::task]
 async fn device() {
-    define_static_buffer!(buffer, KeyEvent, [KeyEvent::Break(Key(0)); 8]);
+    define_static_buffer_pool!(events, KeyEvent, [KeyEvent::Break(Key(0)); 1], 8);
 
     info!("Device task");
     static DEVICE0: OnceLock<device::Device> = OnceLock::new();
-    let this = DEVICE0.get_or_init(|| device::Device::new(DEVICE0_ID, buffer::get_mut().unwrap()));
+    let this = DEVICE0.get_or_init(|| device::Device::new(DEVICE0_ID, events::get()));
     info!("Registering device 0 endpoint");
 
     loop {
@@ -6,7 +6,7 @@ use embassy_sync::once_lock::OnceLock;
 use embassy_time::Timer;
 use embedded_services::comms;
 use embedded_services::power::{self, policy};
-use embedded_services::type_c::{controller, ControllerId};
+use embedded_services::type_c::{self, controller, ControllerId};
 use embedded_usb_pd::type_c::Current;
 use embedded_usb_pd::type_c::Current as TypecCurrent;
 use embedded_usb_pd::Error;
@@ -254,6 +254,10 @@ async fn task(spawner: Spawner) {
     static LISTENER: OnceLock<debug::Listener> = OnceLock::new();
     let listener = LISTENER.get_or_init(debug::Listener::new);
     comms::register_endpoint(listener, &listener.tp).await.unwrap();
+    static SUBSCRIPTION: OnceLock<comms::Subscription> = OnceLock::new();
+    let subscription = SUBSCRIPTION
+        .get_or_init(|| comms::Subscription::new(comms::Topic::of::<type_c::comms::DebugAccessoryMessage>()));
+    comms::subscribe(&listener.tp, subscription).await.unwrap();
 
     static STATE: OnceLock<test_controller::ControllerState> = OnceLock::new();
     let state = STATE.get_or_init(test_controller::ControllerState::new);
@@ -6,7 +6,7 @@ use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::signal::Signal;
 use embassy_time::{with_timeout, Duration};
 use embedded_services::buffer::OwnedRef;
-use embedded_services::comms::{self, Endpoint, EndpointID, External, MailboxDelegate};
+use embedded_services::comms::{self, Endpoint, EndpointID, External};
 use embedded_services::hid::{self, DeviceId, Opcode};
 use embedded_services::{error, trace};
 
@@ -124,7 +124,8 @@ impl<B: I2cSlaveAsync> Host<B> {
 
             if opcode.requires_host_data() {
                 trace!("Waiting for data");
-                let mut borrow = self.buffer.borrow_mut();
+                // The device may still be reading the data from the previous command
+                let mut borrow = self.buffer.borrow_mut_async().await;
                 let buffer: &mut [u8] = borrow.borrow_mut();
 
                 self.read_bus(DATA_READ_TIMEOUT_MS, &mut buffer[0..2]).await?;
@@ -293,13 +294,12 @@ impl<B: I2cSlaveAsync> Host<B> {
     }
 }
 
-impl<B: I2cSlaveAsync> MailboxDelegate for Host<B> {
-    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
-        let hid_msg = message
-            .data
-            .get::<hid::Message>()
-            .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
-
+impl<B: I2cSlaveAsync> comms::Handler<hid::Message<'static>> for Host<B> {
+    fn handle(
+        &self,
+        message: &comms::Message,
+        hid_msg: &hid::Message<'static>,
+    ) -> Result<(), comms::MailboxDelegateError> {
         match hid_msg.data {
             hid::MessageData::Response(ref response) => {
                 self.response.signal(response.clone());
@@ -313,3 +313,5 @@ impl<B: I2cSlaveAsync> MailboxDelegate for Host<B> {
         }
     }
 }
+
+embedded_services::impl_mailbox_delegate!(impl[B: I2cSlaveAsync] for Host<B> { hid::Message<'static> });
@@ -0,0 +1,641 @@
+//! HID keyboard built from keyboard service events
+//!
+//! Key events broadcast to the HID endpoint with [`keyboard::enable_broadcast_hid`] are collected into the pressed-key
+//! state and served to the host as boot or report protocol input reports through a [`hid::Device`].
+use core::borrow::{Borrow, BorrowMut};
+use core::cell::{Cell, RefCell};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+use embedded_services::buffer::{OwnedRef, SharedRef};
+use embedded_services::comms::{self, Endpoint, EndpointID, Internal};
+use embedded_services::hid::{self, CommandResponse, DeviceContainer, Protocol, ReportFreq, ReportType, Response};
+use embedded_services::keyboard::{self, Event, KeyEvent, MessageData};
+use embedded_services::{error, info, trace, warn};
+
+mod report;
+pub use report::*;
+
+/// Number of input reports queued for the host, the oldest report is dropped once full
+const REPORT_QUEUE_LEN: usize = 8;
+/// Length of the length prefix of input and feature reports
+const LENGTH_LEN: usize = 2;
+/// HID over I2C version
+const BCD_VERSION: u16 = 0x0100;
+
+/// HID keyboard configuration
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Key rollover used in report protocol
+    pub rollover: Rollover,
+    /// Vendor ID reported in the HID descriptor
+    pub vendor_id: u16,
+    /// Product ID reported in the HID descriptor
+    pub product_id: u16,
+    /// Version reported in the HID descriptor
+    pub version_id: u16,
+}
+
+struct State {
+    keys: KeyState,
+    protocol: Protocol,
+    idle: ReportFreq,
+    /// Key states not yet read by the host
+    pending: heapless::Deque<KeyState, REPORT_QUEUE_LEN>,
+    /// Time the host last read an input report
+    last_report: Instant,
+}
+
+/// HID keyboard device
+///
+/// The interrupt line is asserted low while input reports are waiting to be read by the host.
+pub struct Keyboard<INT: OutputPin> {
+    device: hid::Device,
+    /// Receives keyboard service events sent to the HID endpoint
+    tp: Endpoint,
+    config: Config,
+    state: RefCell<State>,
+    leds: Cell<Leds>,
+    leds_changed: Signal<NoopRawMutex, Leds>,
+    interrupt: RefCell<INT>,
+    buffer: OwnedRef<'static, u8>,
+}
+
+impl<INT: OutputPin> Keyboard<INT> {
+    /// Create a new keyboard, `buffer` must fit the HID and report descriptors
+    pub fn new(
+        id: hid::DeviceId,
+        regs: hid::RegisterFile,
+        config: Config,
+        interrupt: INT,
+        buffer: OwnedRef<'static, u8>,
+    ) -> Self {
+        Self {
+            device: hid::Device::new(id, regs),
+            tp: Endpoint::uninit(EndpointID::Internal(Internal::Hid)),
+            config,
+            state: RefCell::new(State {
+                keys: KeyState::default(),
+                protocol: Protocol::Report,
+                idle: ReportFreq::Infinite,
+                pending: heapless::Deque::new(),
+                last_report: Instant::MIN,
+            }),
+            leds: Cell::new(Leds::default()),
+            leds_changed: Signal::new(),
+            interrupt: RefCell::new(interrupt),
+            buffer,
+        }
+    }
+
+    /// Register the HID device and the endpoint receiving keyboard events
+    pub async fn register(&'static self) -> Result<(), comms::RegistrationError> {
+        hid::register_device(self).await?;
+        comms::register_endpoint(self, &self.tp).await
+    }
+
+    /// Current LED state
+    pub fn leds(&self) -> Leds {
+        self.leds.get()
+    }
+
+    /// Wait for the host to change the LED state
+    pub async fn wait_leds(&self) -> Leds {
+        self.leds_changed.wait().await
+    }
+
+    fn set_interrupt(&self, asserted: bool) {
+        let mut interrupt = self.interrupt.borrow_mut();
+        let result = if asserted {
+            interrupt.set_low()
+        } else {
+            interrupt.set_high()
+        };
+
+        if result.is_err() {
+            error!("Failed to drive interrupt");
+        }
+    }
+
+    /// Queue the current key state for the host
+    fn queue_report(&self, state: &mut State) {
+        if state.pending.is_full() {
+            warn!("Input report queue full, dropping oldest report");
+            state.pending.pop_front();
+        }
+
+        let _ = state.pending.push_back(state.keys);
+        self.set_interrupt(true);
+    }
+
+    /// Drop reports queued in a format the host no longer expects
+    fn clear_reports(&self, state: &mut State) {
+        state.pending.clear();
+        self.set_interrupt(false);
+    }
+
+    /// Deadline to repeat the current report, if the host set an idle rate and no report is waiting
+    fn idle_deadline(&self) -> Option<Instant> {
+        let state = self.state.borrow();
+        match state.idle {
+            ReportFreq::Msecs(ms) if state.pending.is_empty() => {
+                Some(state.last_report + Duration::from_millis(ms as u64))
+            }
+            _ => None,
+        }
+    }
+
+    fn hid_descriptor(&self) -> hid::Descriptor {
+        let regs = &self.device.regs;
+        hid::Descriptor {
+            w_hid_desc_length: hid::DESCRIPTOR_LEN as u16,
+            bcd_version: BCD_VERSION,
+            w_report_desc_length: self.config.rollover.report_descriptor().len() as u16,
+            w_report_desc_register: regs.report_desc_reg,
+            w_input_register: regs.input_reg,
+            w_max_input_length: (LENGTH_LEN + self.config.rollover.report_len().max(BOOT_REPORT_LEN)) as u16,
+            w_output_register: regs.output_reg,
+            w_max_output_length: (LENGTH_LEN + LED_REPORT_LEN) as u16,
+            w_command_register: regs.command_reg,
+            w_data_register: regs.data_reg,
+            w_vendor_id: self.config.vendor_id,
+            w_product_id: self.config.product_id,
+            w_version_id: self.config.version_id,
+        }
+    }
+
+    /// Fill the buffer and return a reference to the written bytes
+    async fn respond_with(
+        &self,
+        fill: impl FnOnce(&mut [u8]) -> Result<usize, hid::Error>,
+    ) -> Result<SharedRef<'static, u8>, hid::Error> {
+        let len = {
+            // the host may still be sending the previous response
+            let mut borrow = self.buffer.borrow_mut_async().await;
+            let buf: &mut [u8] = borrow.borrow_mut();
+            fill(buf)?
+        };
+
+        Ok(self.buffer.reference().slice(0..len))
+    }
+
+    /// Encode a length prefixed input report
+    fn encode_report(&self, keys: &KeyState, protocol: Protocol, buf: &mut [u8]) -> Result<usize, hid::Error> {
+        if buf.len() < LENGTH_LEN {
+            return Err(hid::Error::InvalidSize(LENGTH_LEN, buf.len()));
+        }
+        let len = LENGTH_LEN + keys.encode_into_slice(protocol, self.config.rollover, &mut buf[LENGTH_LEN..])?;
+
+        buf[..LENGTH_LEN].copy_from_slice(&(len as u16).to_le_bytes());
+        Ok(len)
+    }
+
+    async fn input_report(&self) -> Result<SharedRef<'static, u8>, hid::Error> {
+        let (keys, protocol) = {
+            let mut state = self.state.borrow_mut();
+            let keys = state.pending.pop_front().unwrap_or(state.keys);
+            if state.pending.is_empty() {
+                self.set_interrupt(false);
+            }
+
+            state.last_report = Instant::now();
+            (keys, state.protocol)
+        };
+
+        self.respond_with(|buf| self.encode_report(&keys, protocol, buf)).await
+    }
+
+    fn set_leds(&self, report: &SharedRef<'static, u8>) -> Result<(), hid::Error> {
+        let borrow = report.try_borrow().map_err(|_| hid::Error::InvalidData)?;
+        let report: &[u8] = borrow.borrow();
+
+        let leds = Leds::decode(self.state.borrow().protocol, report)?;
+        trace!("LEDs: {:#x}", leds.0);
+        if self.leds.replace(leds) != leds {
+            self.leds_changed.signal(leds);
+        }
+
+        Ok(())
+    }
+
+    async fn handle_command(&self, command: hid::Command<'static>) -> Result<Option<Response<'static>>, hid::Error> {
+        let response = match command {
+            hid::Command::Reset => {
+                let mut state = self.state.borrow_mut();
+                state.protocol = Protocol::Report;
+                state.idle = ReportFreq::Infinite;
+                self.clear_reports(&mut state);
+                None
+            }
+            hid::Command::GetReport(ReportType::Input, _) => {
+                let (keys, protocol) = {
+                    let state = self.state.borrow();
+                    (state.keys, state.protocol)
+                };
+                let report = self
+                    .respond_with(|buf| self.encode_report(&keys, protocol, buf))
+                    .await?;
+                Some(Response::FeatureReport(report))
+            }
+            hid::Command::SetReport(ReportType::Output, _, report) => {
+                self.set_leds(&report)?;
+                None
+            }
+            hid::Command::GetReport(..) | hid::Command::SetReport(..) => return Err(hid::Error::InvalidReportType),
+            hid::Command::GetIdle(_) => Some(Response::Command(CommandResponse::GetIdle(self.state.borrow().idle))),
+            hid::Command::SetIdle(_, idle) => {
+                self.state.borrow_mut().idle = idle;
+                None
+            }
+            hid::Command::GetProtocol => Some(Response::Command(CommandResponse::GetProtocol(
+                self.state.borrow().protocol,
+            ))),
+            hid::Command::SetProtocol(protocol) => {
+                info!("Keyboard protocol: {:?}", protocol);
+                let mut state = self.state.borrow_mut();
+                state.protocol = protocol;
+                self.clear_reports(&mut state);
+                None
+            }
+            hid::Command::SetPower(_) | hid::Command::Vendor => None,
+        };
+
+        Ok(response)
+    }
+
+    /// Process a request from the host, or repeat the current report once the idle rate elapses
+    pub async fn process_request(&self) -> Result<(), hid::Error> {
+        let request = match self.idle_deadline() {
+            Some(deadline) => match select(self.device.wait_request(), Timer::at(deadline)).await {
+                Either::First(request) => request,
+                Either::Second(_) => {
+                    // the deadline may have moved while waiting
+                    if self.idle_deadline().is_some_and(|deadline| deadline <= Instant::now()) {
+                        let mut state = self.state.borrow_mut();
+                        self.queue_report(&mut state);
+                    }
+                    return Ok(());
+                }
+            },
+            None => self.device.wait_request().await,
+        };
+
+        let response = match request {
+            hid::Request::Descriptor => {
+                let descriptor = self.hid_descriptor();
+                let descriptor = self.respond_with(|buf| descriptor.encode_into_slice(buf)).await?;
+                Some(Response::Descriptor(descriptor))
+            }
+            hid::Request::ReportDescriptor => {
+                let descriptor = self.config.rollover.report_descriptor();
+                let descriptor = self
+                    .respond_with(|buf| {
+                        if buf.len() < descriptor.len() {
+                            return Err(hid::Error::InvalidSize(descriptor.len(), buf.len()));
+                        }
+                        buf[..descriptor.len()].copy_from_slice(descriptor);
+                        Ok(descriptor.len())
+                    })
+                    .await?;
+                Some(Response::ReportDescriptor(descriptor))
+            }
+            hid::Request::InputReport => Some(Response::InputReport(self.input_report().await?)),
+            hid::Request::OutputReport(_, report) => {
+                self.set_leds(&report)?;
+                None
+            }
+            hid::Request::Command(command) => self.handle_command(command).await?,
+        };
+
+        self.device
+            .send_response(response)
+            .await
+            .map_err(|_| hid::Error::Transport)
+    }
+
+    /// Run the keyboard
+    pub async fn run(&self) -> ! {
+        loop {
+            if let Err(e) = self.process_request().await {
+                error!("Keyboard error: {:?}", e);
+            }
+        }
+    }
+}
+
+impl<INT: OutputPin> DeviceContainer for Keyboard<INT> {
+    fn get_hid_device(&self) -> &hid::Device {
+        &self.device
+    }
+}
+
+impl<INT: OutputPin> comms::Handler<keyboard::Message<'static>> for Keyboard<INT> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        message: &keyboard::Message<'static>,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        match &message.data {
+            MessageData::Event(Event::KeyEvent(_, events)) => {
+                let borrow = events.try_borrow().map_err(|_| comms::MailboxDelegateError::Other)?;
+                let events: &[KeyEvent] = borrow.borrow();
+
+                let mut state = self.state.borrow_mut();
+                let mut changed = false;
+                for event in events {
+                    changed |= state.keys.update(*event);
+                }
+                if changed {
+                    self.queue_report(&mut state);
+                }
+            }
+            // Hotkeys are handled by the EC and never reported to the host
+            MessageData::Event(Event::Hotkey(..)) => (),
+        }
+
+        Ok(())
+    }
+}
+
+embedded_services::impl_mailbox_delegate!(impl[INT: OutputPin] for Keyboard<INT> { keyboard::Message<'static> });
+
+#[cfg(test)]
+mod test {
+    use core::convert::Infallible;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use embassy_sync::once_lock::OnceLock;
+    use embedded_hal::digital::ErrorType;
+    use embedded_services::comms::External;
+    use embedded_services::define_static_buffer;
+    use embedded_services::hid::ReportId;
+    use embedded_services::keyboard::{DeviceId, Key};
+
+    use super::*;
+
+    const ID: hid::DeviceId = hid::DeviceId(0);
+    const CONFIG: Config = Config {
+        rollover: Rollover::SixKey,
+        vendor_id: 0x045e,
+        product_id: 0x0001,
+        version_id: 0x0100,
+    };
+    const A: Key = Key(0x04);
+
+    /// Interrupt pin recording its level, high while deasserted
+    struct MockPin(&'static AtomicBool);
+
+    impl MockPin {
+        fn asserted(level: &AtomicBool) -> bool {
+            !level.load(Ordering::Relaxed)
+        }
+    }
+
+    impl ErrorType for MockPin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for MockPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.store(false, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    /// Host side of the HID transport, records responses sent by the keyboard
+    struct Host {
+        tp: Endpoint,
+        response: Signal<NoopRawMutex, Option<Response<'static>>>,
+    }
+
+    impl comms::Handler<hid::Message<'static>> for Host {
+        fn handle(
+            &self,
+            _message: &comms::Message,
+            message: &hid::Message<'static>,
+        ) -> Result<(), comms::MailboxDelegateError> {
+            match &message.data {
+                hid::MessageData::Response(response) => {
+                    self.response.signal(response.clone());
+                    Ok(())
+                }
+                hid::MessageData::Request(_) => Err(comms::MailboxDelegateError::InvalidData),
+            }
+        }
+    }
+
+    embedded_services::impl_mailbox_delegate!(impl for Host { hid::Message<'static> });
+
+    fn assert_report(report: SharedRef<'static, u8>, expected: &[u8]) {
+        let borrow = report.try_borrow().unwrap();
+        let report: &[u8] = borrow.borrow();
+        assert_eq!(report, expected);
+    }
+
+    fn press(keyboard: &Keyboard<MockPin>, key: Key) {
+        let mut state = keyboard.state.borrow_mut();
+        state.keys.update(KeyEvent::Make(key));
+        keyboard.queue_report(&mut state);
+    }
+
+    #[test]
+    fn test_handle_command() {
+        static LEVEL: AtomicBool = AtomicBool::new(true);
+        define_static_buffer!(buffer, u8, [0; 64]);
+        define_static_buffer!(led_report, u8, [REPORT_ID.0, 0x02]);
+
+        let keyboard = Keyboard::new(
+            ID,
+            hid::RegisterFile::default(),
+            CONFIG,
+            MockPin(&LEVEL),
+            buffer::get_mut().unwrap(),
+        );
+
+        embassy_futures::block_on(async {
+            press(&keyboard, A);
+            press(&keyboard, A);
+            assert!(MockPin::asserted(&LEVEL));
+            assert_eq!(keyboard.state.borrow().pending.len(), 2);
+
+            // switching protocol drops reports queued in the old format
+            assert!(keyboard
+                .handle_command(hid::Command::SetProtocol(Protocol::Boot))
+                .await
+                .unwrap()
+                .is_none());
+            assert!(keyboard.state.borrow().pending.is_empty());
+            assert!(!MockPin::asserted(&LEVEL));
+            assert!(matches!(
+                keyboard.handle_command(hid::Command::GetProtocol).await,
+                Ok(Some(Response::Command(CommandResponse::GetProtocol(Protocol::Boot))))
+            ));
+
+            let Ok(Some(Response::FeatureReport(report))) = keyboard
+                .handle_command(hid::Command::GetReport(ReportType::Input, REPORT_ID))
+                .await
+            else {
+                panic!("Expected feature report");
+            };
+            assert_report(report, &[10, 0, 0, 0, A.0, 0, 0, 0, 0, 0]);
+
+            keyboard
+                .handle_command(hid::Command::SetProtocol(Protocol::Report))
+                .await
+                .unwrap();
+            assert!(keyboard
+                .handle_command(hid::Command::SetReport(
+                    ReportType::Output,
+                    REPORT_ID,
+                    led_report::get()
+                ))
+                .await
+                .unwrap()
+                .is_none());
+            assert_eq!(keyboard.leds(), Leds::CAPS_LOCK);
+            assert_eq!(keyboard.wait_leds().await, Leds::CAPS_LOCK);
+            assert!(matches!(
+                keyboard
+                    .handle_command(hid::Command::SetReport(
+                        ReportType::Feature,
+                        REPORT_ID,
+                        led_report::get()
+                    ))
+                    .await,
+                Err(hid::Error::InvalidReportType)
+            ));
+
+            keyboard
+                .handle_command(hid::Command::SetIdle(ReportId(0), ReportFreq::Msecs(4)))
+                .await
+                .unwrap();
+            assert!(matches!(
+                keyboard.handle_command(hid::Command::GetIdle(ReportId(0))).await,
+                Ok(Some(Response::Command(CommandResponse::GetIdle(ReportFreq::Msecs(4)))))
+            ));
+
+            // reset restores the defaults
+            press(&keyboard, Key(0x05));
+            keyboard.handle_command(hid::Command::Reset).await.unwrap();
+            let state = keyboard.state.borrow();
+            assert_eq!(state.protocol, Protocol::Report);
+            assert_eq!(state.idle, ReportFreq::Infinite);
+            assert!(state.pending.is_empty());
+            assert!(!MockPin::asserted(&LEVEL));
+        });
+    }
+
+    #[test]
+    fn test_process_request() {
+        static LEVEL: AtomicBool = AtomicBool::new(true);
+        static KEYBOARD: OnceLock<Keyboard<MockPin>> = OnceLock::new();
+        static HOST: OnceLock<Host> = OnceLock::new();
+        define_static_buffer!(buffer, u8, [0; 64]);
+        define_static_buffer!(events, KeyEvent, [KeyEvent::Make(A)]);
+        define_static_buffer!(led_report, u8, [REPORT_ID.0, 0x01]);
+
+        let keyboard = KEYBOARD.get_or_init(|| {
+            Keyboard::new(
+                ID,
+                hid::RegisterFile::default(),
+                CONFIG,
+                MockPin(&LEVEL),
+                buffer::get_mut().unwrap(),
+            )
+        });
+        let host = HOST.get_or_init(|| Host {
+            tp: Endpoint::uninit(EndpointID::External(External::Host)),
+            response: Signal::new(),
+        });
+
+        embassy_futures::block_on(async {
+            embedded_services::init().await;
+            keyboard.register().await.unwrap();
+            comms::register_endpoint(host, &host.tp).await.unwrap();
+
+            // key events reach the keyboard, HID requests on the same endpoint ID reach the device
+            comms::send(
+                EndpointID::Internal(Internal::Keyboard),
+                EndpointID::Internal(Internal::Hid),
+                &keyboard::Message {
+                    device_id: DeviceId(0),
+                    data: MessageData::Event(Event::KeyEvent(DeviceId(0), events::get())),
+                },
+            )
+            .await
+            .unwrap();
+            assert!(MockPin::asserted(&LEVEL));
+
+            hid::send_request(&host.tp, ID, hid::Request::InputReport)
+                .await
+                .unwrap();
+            keyboard.process_request().await.unwrap();
+            let Some(Some(Response::InputReport(report))) = host.response.try_take() else {
+                panic!("Expected input report");
+            };
+            assert_report(report, &[11, 0, REPORT_ID.0, 0, 0, A.0, 0, 0, 0, 0, 0]);
+            assert!(!MockPin::asserted(&LEVEL));
+
+            // the current report repeats once the idle rate elapses without a read
+            hid::send_request(
+                &host.tp,
+                ID,
+                hid::Request::Command(hid::Command::SetIdle(ReportId(0), ReportFreq::Msecs(1))),
+            )
+            .await
+            .unwrap();
+            keyboard.process_request().await.unwrap();
+            assert!(matches!(host.response.try_take(), Some(None)));
+
+            keyboard.process_request().await.unwrap();
+            assert!(host.response.try_take().is_none());
+            assert_eq!(keyboard.state.borrow().pending.len(), 1);
+            assert!(MockPin::asserted(&LEVEL));
+
+            // no repeat is queued while a report is waiting
+            assert!(keyboard.idle_deadline().is_none());
+
+            hid::send_request(&host.tp, ID, hid::Request::InputReport)
+                .await
+                .unwrap();
+            keyboard.process_request().await.unwrap();
+            let Some(Some(Response::InputReport(report))) = host.response.try_take() else {
+                panic!("Expected input report");
+            };
+            assert_report(report, &[11, 0, REPORT_ID.0, 0, 0, A.0, 0, 0, 0, 0, 0]);
+            assert!(!MockPin::asserted(&LEVEL));
+
+            hid::send_request(
+                &host.tp,
+                ID,
+                hid::Request::Command(hid::Command::SetIdle(ReportId(0), ReportFreq::Infinite)),
+            )
+            .await
+            .unwrap();
+            keyboard.process_request().await.unwrap();
+            assert!(matches!(host.response.try_take(), Some(None)));
+            assert!(keyboard.idle_deadline().is_none());
+
+            // output reports set the LEDs
+            hid::send_request(
+                &host.tp,
+                ID,
+                hid::Request::OutputReport(Some(REPORT_ID), led_report::get()),
+            )
+            .await
+            .unwrap();
+            keyboard.process_request().await.unwrap();
+            assert!(matches!(host.response.try_take(), Some(None)));
+            assert_eq!(keyboard.leds(), Leds::NUM_LOCK);
+        });
+    }
+}
@@ -0,0 +1,344 @@
+//! Keyboard key state, report descriptors and report encoding
+use embedded_services::hid::{Error, Protocol, ReportId};
+use embedded_services::keyboard::KeyEvent;
+
+/// Report ID used for keyboard input and LED output reports in report protocol
+pub const REPORT_ID: ReportId = ReportId(1);
+
+/// Length of a boot protocol report: modifiers, reserved byte and six keys
+pub const BOOT_REPORT_LEN: usize = 8;
+/// Number of keys in a boot or 6KRO report
+const ROLLOVER_KEYS: usize = 6;
+/// Usages reported in the NKRO bitmap, everything below the modifiers
+const NKRO_USAGES: usize = 0xe0;
+/// Length of the NKRO bitmap
+const NKRO_BITMAP_LEN: usize = NKRO_USAGES / 8;
+/// Length of a 6KRO report protocol report, including the report ID
+pub const SIX_KEY_REPORT_LEN: usize = 1 + BOOT_REPORT_LEN;
+/// Length of an NKRO report protocol report, including the report ID
+pub const NKRO_REPORT_LEN: usize = 2 + NKRO_BITMAP_LEN;
+/// Maximum length of any input report
+pub const MAX_REPORT_LEN: usize = NKRO_REPORT_LEN;
+/// Length of the LED output report, including the report ID
+pub const LED_REPORT_LEN: usize = 2;
+
+/// First modifier usage, left control
+const MODIFIER_FIRST: u8 = 0xe0;
+/// Last modifier usage, right GUI
+const MODIFIER_LAST: u8 = 0xe7;
+/// Usage reported in every key slot when more keys are pressed than a report can hold
+const ERROR_ROLLOVER: u8 = 0x01;
+
+/// Report descriptor for report protocol with six key rollover
+#[rustfmt::skip]
+pub const SIX_KEY_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,         // Usage Page (Generic Desktop)
+    0x09, 0x06,         // Usage (Keyboard)
+    0xa1, 0x01,         // Collection (Application)
+    0x85, REPORT_ID.0,  //   Report ID
+    0x05, 0x07,         //   Usage Page (Keyboard/Keypad)
+    0x19, 0xe0,         //   Usage Minimum (Left Control)
+    0x29, 0xe7,         //   Usage Maximum (Right GUI)
+    0x15, 0x00,         //   Logical Minimum (0)
+    0x25, 0x01,         //   Logical Maximum (1)
+    0x75, 0x01,         //   Report Size (1)
+    0x95, 0x08,         //   Report Count (8)
+    0x81, 0x02,         //   Input (Data, Variable, Absolute)
+    0x75, 0x08,         //   Report Size (8)
+    0x95, 0x01,         //   Report Count (1)
+    0x81, 0x01,         //   Input (Constant)
+    0x05, 0x08,         //   Usage Page (LEDs)
+    0x19, 0x01,         //   Usage Minimum (Num Lock)
+    0x29, 0x05,         //   Usage Maximum (Kana)
+    0x75, 0x01,         //   Report Size (1)
+    0x95, 0x05,         //   Report Count (5)
+    0x91, 0x02,         //   Output (Data, Variable, Absolute)
+    0x75, 0x03,         //   Report Size (3)
+    0x95, 0x01,         //   Report Count (1)
+    0x91, 0x01,         //   Output (Constant)
+    0x05, 0x07,         //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00,         //   Usage Minimum (0)
+    0x2a, 0xff, 0x00,   //   Usage Maximum (255)
+    0x15, 0x00,         //   Logical Minimum (0)
+    0x26, 0xff, 0x00,   //   Logical Maximum (255)
+    0x75, 0x08,         //   Report Size (8)
+    0x95, 0x06,         //   Report Count (6)
+    0x81, 0x00,         //   Input (Data, Array, Absolute)
+    0xc0,               // End Collection
+];
+
+/// Report descriptor for report protocol with n-key rollover
+#[rustfmt::skip]
+pub const NKRO_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,         // Usage Page (Generic Desktop)
+    0x09, 0x06,         // Usage (Keyboard)
+    0xa1, 0x01,         // Collection (Application)
+    0x85, REPORT_ID.0,  //   Report ID
+    0x05, 0x07,         //   Usage Page (Keyboard/Keypad)
+    0x19, 0xe0,         //   Usage Minimum (Left Control)
+    0x29, 0xe7,         //   Usage Maximum (Right GUI)
+    0x15, 0x00,         //   Logical Minimum (0)
+    0x25, 0x01,         //   Logical Maximum (1)
+    0x75, 0x01,         //   Report Size (1)
+    0x95, 0x08,         //   Report Count (8)
+    0x81, 0x02,         //   Input (Data, Variable, Absolute)
+    0x19, 0x00,         //   Usage Minimum (0)
+    0x29, 0xdf,         //   Usage Maximum (0xdf)
+    0x95, 0xe0,         //   Report Count (224)
+    0x81, 0x02,         //   Input (Data, Variable, Absolute)
+    0x05, 0x08,         //   Usage Page (LEDs)
+    0x19, 0x01,         //   Usage Minimum (Num Lock)
+    0x29, 0x05,         //   Usage Maximum (Kana)
+    0x95, 0x05,         //   Report Count (5)
+    0x91, 0x02,         //   Output (Data, Variable, Absolute)
+    0x75, 0x03,         //   Report Size (3)
+    0x95, 0x01,         //   Report Count (1)
+    0x91, 0x01,         //   Output (Constant)
+    0xc0,               // End Collection
+];
+
+/// Key rollover reported in report protocol, boot protocol always uses six key rollover
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rollover {
+    /// Modifiers plus up to six keys
+    SixKey,
+    /// Modifiers plus a bitmap of all keys
+    NKey,
+}
+
+impl Rollover {
+    /// Report descriptor matching this rollover
+    pub fn report_descriptor(&self) -> &'static [u8] {
+        match self {
+            Rollover::SixKey => SIX_KEY_REPORT_DESCRIPTOR,
+            Rollover::NKey => NKRO_REPORT_DESCRIPTOR,
+        }
+    }
+
+    /// Length of a report protocol input report, including the report ID
+    pub fn report_len(&self) -> usize {
+        match self {
+            Rollover::SixKey => SIX_KEY_REPORT_LEN,
+            Rollover::NKey => NKRO_REPORT_LEN,
+        }
+    }
+}
+
+/// Keyboard LED state from output reports
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Leds(pub u8);
+
+impl Leds {
+    /// Num lock
+    pub const NUM_LOCK: Leds = Leds(1 << 0);
+    /// Caps lock
+    pub const CAPS_LOCK: Leds = Leds(1 << 1);
+    /// Scroll lock
+    pub const SCROLL_LOCK: Leds = Leds(1 << 2);
+    /// Compose
+    pub const COMPOSE: Leds = Leds(1 << 3);
+    /// Kana
+    pub const KANA: Leds = Leds(1 << 4);
+
+    /// Returns true if all LEDs in `leds` are on
+    pub fn contains(&self, leds: Leds) -> bool {
+        self.0 & leds.0 == leds.0
+    }
+
+    /// Decode an LED output report, the LED byte follows the report ID in report protocol
+    pub fn decode(protocol: Protocol, report: &[u8]) -> Result<Self, Error> {
+        let leds = match protocol {
+            Protocol::Boot => report.first(),
+            Protocol::Report => match report {
+                [id, leds, ..] if *id == REPORT_ID.0 => Some(leds),
+                [_, _, ..] => return Err(Error::InvalidData),
+                _ => None,
+            },
+        };
+
+        leds.map(|leds| Leds(leds & 0x1f))
+            .ok_or(Error::InvalidSize(LED_REPORT_LEN, report.len()))
+    }
+}
+
+/// Pressed keys and modifiers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyState {
+    modifiers: u8,
+    /// Bitmap of pressed non-modifier keys, indexed by usage
+    keys: [u8; 32],
+}
+
+impl KeyState {
+    /// Apply a key event, returns true if the state changed
+    pub fn update(&mut self, event: KeyEvent) -> bool {
+        let (key, pressed) = match event {
+            KeyEvent::Make(key) => (key.0, true),
+            KeyEvent::Break(key) => (key.0, false),
+        };
+
+        let (byte, mask) = if (MODIFIER_FIRST..=MODIFIER_LAST).contains(&key) {
+            (&mut self.modifiers, 1 << (key - MODIFIER_FIRST))
+        } else {
+            (&mut self.keys[key as usize / 8], 1 << (key % 8))
+        };
+
+        let previous = *byte;
+        if pressed {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+
+        previous != *byte
+    }
+
+    /// Returns true if a key or modifier is pressed
+    pub fn is_pressed(&self, key: u8) -> bool {
+        if (MODIFIER_FIRST..=MODIFIER_LAST).contains(&key) {
+            self.modifiers & (1 << (key - MODIFIER_FIRST)) != 0
+        } else {
+            self.keys[key as usize / 8] & (1 << (key % 8)) != 0
+        }
+    }
+
+    /// Pressed non-modifier keys in usage order
+    fn pressed_keys(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX)
+            .filter(|key| !(MODIFIER_FIRST..=MODIFIER_LAST).contains(key))
+            .filter(|key| self.is_pressed(*key))
+    }
+
+    /// Encode modifiers, reserved byte and up to six keys, all keys report rollover errors if more are pressed
+    fn encode_six_key(&self, buf: &mut [u8]) {
+        buf[0] = self.modifiers;
+        buf[1] = 0;
+
+        let slots = &mut buf[2..BOOT_REPORT_LEN];
+        if self.pressed_keys().count() > ROLLOVER_KEYS {
+            slots.fill(ERROR_ROLLOVER);
+        } else {
+            slots.fill(0);
+            for (slot, key) in slots.iter_mut().zip(self.pressed_keys()) {
+                *slot = key;
+            }
+        }
+    }
+
+    /// Encode an input report for the protocol into the slice, returns the number of bytes written
+    pub fn encode_into_slice(&self, protocol: Protocol, rollover: Rollover, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = match protocol {
+            Protocol::Boot => BOOT_REPORT_LEN,
+            Protocol::Report => rollover.report_len(),
+        };
+        if buf.len() < len {
+            return Err(Error::InvalidSize(len, buf.len()));
+        }
+
+        match (protocol, rollover) {
+            (Protocol::Boot, _) => self.encode_six_key(buf),
+            (Protocol::Report, Rollover::SixKey) => {
+                buf[0] = REPORT_ID.0;
+                self.encode_six_key(&mut buf[1..]);
+            }
+            (Protocol::Report, Rollover::NKey) => {
+                buf[0] = REPORT_ID.0;
+                buf[1] = self.modifiers;
+                buf[2..len].copy_from_slice(&self.keys[..NKRO_BITMAP_LEN]);
+            }
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embedded_services::keyboard::Key;
+
+    use super::*;
+
+    const A: Key = Key(0x04);
+    const B: Key = Key(0x05);
+    const LEFT_SHIFT: Key = Key(0xe1);
+
+    #[test]
+    fn test_reports() {
+        let mut state = KeyState::default();
+        let mut buf = [0u8; MAX_REPORT_LEN];
+
+        assert!(state.update(KeyEvent::Make(B)));
+        assert!(state.update(KeyEvent::Make(LEFT_SHIFT)));
+        assert!(state.update(KeyEvent::Make(A)));
+        assert!(!state.update(KeyEvent::Make(A)));
+
+        assert_eq!(
+            state.encode_into_slice(Protocol::Boot, Rollover::NKey, &mut buf),
+            Ok(BOOT_REPORT_LEN)
+        );
+        assert_eq!(buf[..BOOT_REPORT_LEN], [0x02, 0, 0x04, 0x05, 0, 0, 0, 0]);
+
+        assert_eq!(
+            state.encode_into_slice(Protocol::Report, Rollover::SixKey, &mut buf),
+            Ok(SIX_KEY_REPORT_LEN)
+        );
+        assert_eq!(
+            buf[..SIX_KEY_REPORT_LEN],
+            [REPORT_ID.0, 0x02, 0, 0x04, 0x05, 0, 0, 0, 0]
+        );
+
+        assert_eq!(
+            state.encode_into_slice(Protocol::Report, Rollover::NKey, &mut buf),
+            Ok(NKRO_REPORT_LEN)
+        );
+        assert_eq!(buf[..3], [REPORT_ID.0, 0x02, 0x30]);
+        assert!(buf[3..NKRO_REPORT_LEN].iter().all(|byte| *byte == 0));
+
+        assert!(state.update(KeyEvent::Break(A)));
+        assert!(!state.update(KeyEvent::Break(A)));
+        assert!(!state.is_pressed(A.0));
+        assert!(state.is_pressed(LEFT_SHIFT.0));
+    }
+
+    #[test]
+    fn test_rollover_error() {
+        let mut state = KeyState::default();
+        let mut buf = [0u8; MAX_REPORT_LEN];
+
+        for key in 0x04..0x0b {
+            state.update(KeyEvent::Make(Key(key)));
+        }
+
+        // seven keys overflow six key reports, but fit in the NKRO bitmap
+        state
+            .encode_into_slice(Protocol::Boot, Rollover::SixKey, &mut buf)
+            .unwrap();
+        assert_eq!(buf[..BOOT_REPORT_LEN], [0, 0, 1, 1, 1, 1, 1, 1]);
+
+        state
+            .encode_into_slice(Protocol::Report, Rollover::NKey, &mut buf)
+            .unwrap();
+        assert_eq!(buf[..4], [REPORT_ID.0, 0, 0xf0, 0x07]);
+
+        assert_eq!(
+            state.encode_into_slice(Protocol::Report, Rollover::NKey, &mut buf[..8]),
+            Err(Error::InvalidSize(NKRO_REPORT_LEN, 8))
+        );
+    }
+
+    #[test]
+    fn test_leds() {
+        let leds = Leds::decode(Protocol::Report, &[REPORT_ID.0, 0x02]).unwrap();
+        assert!(leds.contains(Leds::CAPS_LOCK));
+        assert!(!leds.contains(Leds::NUM_LOCK));
+
+        assert_eq!(Leds::decode(Protocol::Boot, &[0xff]), Ok(Leds(0x1f)));
+        assert_eq!(Leds::decode(Protocol::Report, &[2, 0x01]), Err(Error::InvalidData));
+        assert_eq!(
+            Leds::decode(Protocol::Report, &[REPORT_ID.0]),
+            Err(Error::InvalidSize(LED_REPORT_LEN, 1))
+        );
+    }
+}
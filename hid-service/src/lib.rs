@@ -3,6 +3,7 @@
 use embedded_services::hid;
 
 pub mod i2c;
+pub mod keyboard;
 
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
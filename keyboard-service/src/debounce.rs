@@ -0,0 +1,76 @@
+//! Per-key debouncing
+use crate::matrix::KeyMatrix;
+
+/// Per-key debouncer, a key changes state once its raw state has been stable for a number of scans
+pub struct Debouncer<const ROWS: usize, const COLS: usize> {
+    /// Number of consecutive scans a raw state must be seen before it is accepted
+    scans: u8,
+    /// Consecutive scans each key has differed from its debounced state
+    counters: [[u8; COLS]; ROWS],
+    /// Debounced key states
+    state: KeyMatrix<ROWS, COLS>,
+}
+
+impl<const ROWS: usize, const COLS: usize> Debouncer<ROWS, COLS> {
+    /// Create a new debouncer, `scans` of 0 or 1 disables debouncing
+    pub const fn new(scans: u8) -> Self {
+        Self {
+            scans,
+            counters: [[0; COLS]; ROWS],
+            state: [[false; COLS]; ROWS],
+        }
+    }
+
+    /// Update with the raw state of a scan and return the debounced state
+    pub fn update(&mut self, raw: &KeyMatrix<ROWS, COLS>) -> &KeyMatrix<ROWS, COLS> {
+        for (row, raw_row) in raw.iter().enumerate() {
+            for (col, pressed) in raw_row.iter().enumerate() {
+                let counter = &mut self.counters[row][col];
+                if *pressed == self.state[row][col] {
+                    *counter = 0;
+                    continue;
+                }
+
+                *counter += 1;
+                if *counter >= self.scans {
+                    self.state[row][col] = *pressed;
+                    *counter = 0;
+                }
+            }
+        }
+
+        &self.state
+    }
+
+    /// Current debounced key states
+    pub fn state(&self) -> &KeyMatrix<ROWS, COLS> {
+        &self.state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let mut debouncer: Debouncer<1, 2> = Debouncer::new(3);
+
+        // a press is accepted after three stable scans
+        assert_eq!(debouncer.update(&[[true, false]]), &[[false, false]]);
+        assert_eq!(debouncer.update(&[[true, false]]), &[[false, false]]);
+        assert_eq!(debouncer.update(&[[true, false]]), &[[true, false]]);
+
+        // bounces reset the count
+        assert_eq!(debouncer.update(&[[false, false]]), &[[true, false]]);
+        assert_eq!(debouncer.update(&[[true, false]]), &[[true, false]]);
+        assert_eq!(debouncer.update(&[[false, true]]), &[[true, false]]);
+        assert_eq!(debouncer.update(&[[false, true]]), &[[true, false]]);
+        assert_eq!(debouncer.update(&[[false, true]]), &[[false, true]]);
+
+        // debouncing disabled
+        let mut debouncer: Debouncer<1, 1> = Debouncer::new(0);
+        assert_eq!(debouncer.update(&[[true]]), &[[true]]);
+        assert_eq!(debouncer.update(&[[false]]), &[[false]]);
+    }
+}
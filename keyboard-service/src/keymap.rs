@@ -0,0 +1,93 @@
+//! Layered keymaps
+use embedded_services::keyboard::{Hotkey, Key};
+
+/// Maximum number of keymap layers
+pub const MAX_LAYERS: usize = 32;
+
+/// Action assigned to a key position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// No action
+    None,
+    /// Use the action of the next lower active layer
+    Transparent,
+    /// Report a key
+    Key(Key),
+    /// Activate a layer while held, e.g. the Fn layer, must be one of the keymap's layers
+    Layer(u8),
+    /// OEM hotkey handled by the EC
+    Hotkey(Hotkey),
+}
+
+/// Keymap with `LAYERS` layers, layer 0 is the base layer
+pub struct Keymap<const ROWS: usize, const COLS: usize, const LAYERS: usize> {
+    layers: [[[Action; COLS]; ROWS]; LAYERS],
+}
+
+impl<const ROWS: usize, const COLS: usize, const LAYERS: usize> Keymap<ROWS, COLS, LAYERS> {
+    /// Create a new keymap, panics if an [`Action::Layer`] refers to a layer the keymap doesn't have
+    pub const fn new(layers: [[[Action; COLS]; ROWS]; LAYERS]) -> Self {
+        assert!(LAYERS > 0 && LAYERS <= MAX_LAYERS);
+
+        let mut layer = 0;
+        while layer < LAYERS {
+            let mut row = 0;
+            while row < ROWS {
+                let mut col = 0;
+                while col < COLS {
+                    if let Action::Layer(target) = layers[layer][row][col] {
+                        assert!((target as usize) < LAYERS, "Action::Layer out of range");
+                    }
+                    col += 1;
+                }
+                row += 1;
+            }
+            layer += 1;
+        }
+
+        Self { layers }
+    }
+
+    /// Resolve the action for a key position, `layers` is a bitmask of active layers.
+    ///
+    /// The highest active layer takes priority, [`Action::Transparent`] falls through to lower active layers and the
+    /// base layer is always active
+    pub fn resolve(&self, row: usize, col: usize, layers: u32) -> Action {
+        let layers = layers | 1;
+        (0..LAYERS)
+            .rev()
+            .filter(|layer| layers & (1 << layer) != 0)
+            .map(|layer| self.layers[layer][row][col])
+            .find(|action| *action != Action::Transparent)
+            .unwrap_or(Action::None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        const A: Action = Action::Key(Key(0x04));
+        const B: Action = Action::Key(Key(0x05));
+        const FN: Action = Action::Layer(1);
+        const MUTE: Action = Action::Hotkey(Hotkey(1));
+        const T: Action = Action::Transparent;
+
+        let keymap = Keymap::new([[[A, B, FN]], [[MUTE, T, T]], [[T, T, A]]]);
+
+        assert_eq!(keymap.resolve(0, 0, 0), A);
+        assert_eq!(keymap.resolve(0, 0, 1 << 1), MUTE);
+        assert_eq!(keymap.resolve(0, 1, 1 << 1), B);
+        assert_eq!(keymap.resolve(0, 2, 1 << 2), A);
+        assert_eq!(keymap.resolve(0, 0, 1 << 1 | 1 << 2), MUTE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Action::Layer out of range")]
+    fn test_layer_out_of_range() {
+        Keymap::new([[[Action::Layer(1)]]]);
+    }
+}
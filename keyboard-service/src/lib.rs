@@ -0,0 +1,538 @@
+//! Keyboard matrix scanning service
+//!
+//! Scans a key matrix, debounces each key, rejects ghost keys and maps key positions through a layered keymap.
+//! Key events are broadcast as [`keyboard::Event::KeyEvent`] and OEM hotkeys as [`keyboard::Event::Hotkey`].
+//! Legacy hosts are served scancodes translated from the broadcast key events by [`scancode::Host`].
+#![no_std]
+
+use core::borrow::BorrowMut;
+
+use embassy_time::{Duration, Timer};
+use embedded_services::buffer::OwnedRef;
+use embedded_services::error;
+use embedded_services::keyboard::{self, DeviceId, Event, HotkeyEvent, KeyEvent, MessageData};
+
+pub mod debounce;
+pub mod keymap;
+pub mod matrix;
+pub mod scancode;
+
+use debounce::Debouncer;
+use keymap::{Action, Keymap};
+use matrix::{KeyMatrix, Matrix};
+
+/// Maximum number of hotkey events broadcast per scan, further events are broadcast after the first batch
+const MAX_HOTKEY_EVENTS: usize = 4;
+
+/// Keyboard service error
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error from the underlying matrix
+    Matrix(E),
+}
+
+/// Keyboard configuration
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Time between matrix scans
+    pub scan_interval: Duration,
+    /// Number of consecutive scans a key must be stable before a change is accepted
+    pub debounce_scans: u8,
+    /// Reject ghost keys, only needed for matrices without per-key diodes
+    pub ghost_detection: bool,
+    /// Maximum number of keys reported as pressed at once, further presses are ignored until a key is released
+    pub rollover: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scan_interval: Duration::from_millis(5),
+            debounce_scans: 4,
+            ghost_detection: true,
+            rollover: usize::MAX,
+        }
+    }
+}
+
+/// Output of a processed scan
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Output {
+    /// Key event
+    Key(KeyEvent),
+    /// OEM hotkey event
+    Hotkey(HotkeyEvent),
+}
+
+/// Returns the rows that form a ghost rectangle with another row.
+///
+/// Without per-key diodes, three keys pressed on the corners of a rectangle make the fourth corner read as pressed,
+/// which shows up as two rows sharing two or more pressed columns
+fn ghost_rows<const ROWS: usize, const COLS: usize>(state: &KeyMatrix<ROWS, COLS>) -> [bool; ROWS] {
+    let mut ghosted = [false; ROWS];
+
+    for (row, keys) in state.iter().enumerate() {
+        for (other, other_keys) in state.iter().enumerate().skip(row + 1) {
+            let shared = keys.iter().zip(other_keys).filter(|(a, b)| **a && **b).count();
+            if shared >= 2 {
+                ghosted[row] = true;
+                ghosted[other] = true;
+            }
+        }
+    }
+
+    ghosted
+}
+
+/// Turns raw matrix scans into key and hotkey events
+pub struct Scanner<const ROWS: usize, const COLS: usize, const LAYERS: usize> {
+    keymap: &'static Keymap<ROWS, COLS, LAYERS>,
+    config: Config,
+    debouncer: Debouncer<ROWS, COLS>,
+    /// Actions of the keys currently reported as pressed, resolved when the key was pressed
+    pressed: [[Option<Action>; COLS]; ROWS],
+    /// Number of keys currently reported
+    reported_keys: usize,
+    /// Bitmask of held layers
+    layers: u32,
+}
+
+impl<const ROWS: usize, const COLS: usize, const LAYERS: usize> Scanner<ROWS, COLS, LAYERS> {
+    /// Create a new scanner
+    pub fn new(keymap: &'static Keymap<ROWS, COLS, LAYERS>, config: Config) -> Self {
+        Self {
+            keymap,
+            config,
+            debouncer: Debouncer::new(config.debounce_scans),
+            pressed: [[None; COLS]; ROWS],
+            reported_keys: 0,
+            layers: 0,
+        }
+    }
+
+    /// Scanner configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Process a raw scan, `emit` returns false if it cannot accept further outputs.
+    ///
+    /// Returns true if all changes were emitted, otherwise call [`Scanner::resume`] to emit the remaining changes
+    pub fn process(&mut self, raw: &KeyMatrix<ROWS, COLS>, emit: impl FnMut(Output) -> bool) -> bool {
+        self.debouncer.update(raw);
+        self.resume(emit)
+    }
+
+    /// Emit changes left over from the last call to [`Scanner::process`], returns true once all changes are emitted
+    pub fn resume(&mut self, mut emit: impl FnMut(Output) -> bool) -> bool {
+        let state = *self.debouncer.state();
+
+        // releases first, so that keys released in this scan free up rollover for new presses
+        for (row, keys) in state.iter().enumerate() {
+            for (col, _) in keys.iter().enumerate().filter(|(_, pressed)| !**pressed) {
+                if let Some(action) = self.pressed[row][col] {
+                    if !self.release(action, &mut emit) {
+                        return false;
+                    }
+                    self.pressed[row][col] = None;
+                }
+            }
+        }
+
+        let ghosted = if self.config.ghost_detection {
+            ghost_rows(&state)
+        } else {
+            [false; ROWS]
+        };
+
+        for (row, keys) in state.iter().enumerate() {
+            // new presses in ghosted rows are ignored until the ghost condition clears
+            if ghosted[row] {
+                continue;
+            }
+
+            for (col, _) in keys.iter().enumerate().filter(|(_, pressed)| **pressed) {
+                if self.pressed[row][col].is_some() {
+                    continue;
+                }
+
+                let action = self.keymap.resolve(row, col, self.layers);
+                if matches!(action, Action::Key(_)) && self.reported_keys >= self.config.rollover {
+                    continue;
+                }
+
+                if !self.press(action, &mut emit) {
+                    return false;
+                }
+                self.pressed[row][col] = Some(action);
+            }
+        }
+
+        true
+    }
+
+    fn press(&mut self, action: Action, emit: &mut impl FnMut(Output) -> bool) -> bool {
+        match action {
+            Action::Key(key) => {
+                if !emit(Output::Key(KeyEvent::Make(key))) {
+                    return false;
+                }
+                self.reported_keys += 1;
+            }
+            Action::Hotkey(hotkey) => return emit(Output::Hotkey(HotkeyEvent::Make(hotkey))),
+            Action::Layer(layer) => self.layers |= 1 << layer,
+            Action::None | Action::Transparent => (),
+        }
+
+        true
+    }
+
+    fn release(&mut self, action: Action, emit: &mut impl FnMut(Output) -> bool) -> bool {
+        match action {
+            Action::Key(key) => {
+                if !emit(Output::Key(KeyEvent::Break(key))) {
+                    return false;
+                }
+                self.reported_keys -= 1;
+            }
+            Action::Hotkey(hotkey) => return emit(Output::Hotkey(HotkeyEvent::Break(hotkey))),
+            Action::Layer(layer) => self.layers &= !(1 << layer),
+            Action::None | Action::Transparent => (),
+        }
+
+        true
+    }
+}
+
+/// Keyboard service, scans a matrix and broadcasts the resulting events
+pub struct Keyboard<M: Matrix<ROWS, COLS>, const ROWS: usize, const COLS: usize, const LAYERS: usize> {
+    id: DeviceId,
+    matrix: M,
+    scanner: Scanner<ROWS, COLS, LAYERS>,
+    /// Buffer for key events, events of a scan are broadcast in batches of the buffer length
+    events: OwnedRef<'static, KeyEvent>,
+}
+
+impl<M: Matrix<ROWS, COLS>, const ROWS: usize, const COLS: usize, const LAYERS: usize> Keyboard<M, ROWS, COLS, LAYERS> {
+    /// Create a new keyboard service
+    pub fn new(
+        id: DeviceId,
+        matrix: M,
+        keymap: &'static Keymap<ROWS, COLS, LAYERS>,
+        config: Config,
+        events: OwnedRef<'static, KeyEvent>,
+    ) -> Self {
+        Self {
+            id,
+            matrix,
+            scanner: Scanner::new(keymap, config),
+            events,
+        }
+    }
+
+    /// Scan the matrix once and broadcast any resulting events
+    pub async fn scan(&mut self) -> Result<(), Error<M::Error>> {
+        let raw = self.matrix.scan().await.map_err(Error::Matrix)?;
+
+        let mut first = true;
+        loop {
+            let mut count = 0;
+            let mut hotkeys = heapless::Vec::<HotkeyEvent, MAX_HOTKEY_EVENTS>::new();
+
+            let complete = {
+                // wait for receivers to finish with the previous events
+                let mut access = self.events.borrow_mut_async().await;
+                let buffer: &mut [KeyEvent] = access.borrow_mut();
+
+                let emit = |output| match output {
+                    Output::Key(event) => match buffer.get_mut(count) {
+                        Some(slot) => {
+                            *slot = event;
+                            count += 1;
+                            true
+                        }
+                        None => false,
+                    },
+                    Output::Hotkey(event) => hotkeys.push(event).is_ok(),
+                };
+
+                if first {
+                    self.scanner.process(&raw, emit)
+                } else {
+                    self.scanner.resume(emit)
+                }
+            };
+            first = false;
+
+            if count > 0 {
+                let events = self.events.reference().slice(0..count);
+                keyboard::broadcast_message(self.id, MessageData::Event(Event::KeyEvent(self.id, events))).await;
+            }
+
+            for event in hotkeys {
+                keyboard::broadcast_message(self.id, MessageData::Event(Event::Hotkey(self.id, event))).await;
+            }
+
+            if complete {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run the keyboard service
+    pub async fn run(&mut self) -> ! {
+        loop {
+            if self.scan().await.is_err() {
+                error!("Keyboard {}: matrix scan failed", self.id.0);
+            }
+
+            Timer::after(self.scanner.config().scan_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use core::borrow::Borrow;
+    use std::vec::Vec;
+
+    use embassy_sync::once_lock::OnceLock;
+    use embedded_services::comms::{self, Endpoint, EndpointID, External, MailboxDelegate, MailboxDelegateError};
+    use embedded_services::define_static_buffer;
+    use embedded_services::keyboard::{Hotkey, Key, Message};
+
+    use super::*;
+
+    const A: Action = Action::Key(Key(0x04));
+    const B: Action = Action::Key(Key(0x05));
+    const C: Action = Action::Key(Key(0x06));
+    const D: Action = Action::Key(Key(0x07));
+    const FN: Action = Action::Layer(1);
+    const MUTE: Action = Action::Hotkey(Hotkey(1));
+    const T: Action = Action::Transparent;
+
+    static KEYMAP: Keymap<2, 3, 2> = Keymap::new([[[A, B, FN], [C, D, T]], [[MUTE, T, T], [T, T, T]]]);
+
+    const NO_DEBOUNCE: Config = Config {
+        scan_interval: Duration::from_millis(5),
+        debounce_scans: 0,
+        ghost_detection: true,
+        rollover: usize::MAX,
+    };
+
+    fn collect(scanner: &mut Scanner<2, 3, 2>, raw: KeyMatrix<2, 3>) -> Vec<Output> {
+        let mut outputs = Vec::new();
+        assert!(scanner.process(&raw, |output| {
+            outputs.push(output);
+            true
+        }));
+        outputs
+    }
+
+    fn make(action: Action) -> Output {
+        match action {
+            Action::Key(key) => Output::Key(KeyEvent::Make(key)),
+            Action::Hotkey(hotkey) => Output::Hotkey(HotkeyEvent::Make(hotkey)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn brk(action: Action) -> Output {
+        match action {
+            Action::Key(key) => Output::Key(KeyEvent::Break(key)),
+            Action::Hotkey(hotkey) => Output::Hotkey(HotkeyEvent::Break(hotkey)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut scanner = Scanner::new(&KEYMAP, NO_DEBOUNCE);
+
+        assert_eq!(collect(&mut scanner, [[true, false, false], [false; 3]]), [make(A)]);
+        assert_eq!(collect(&mut scanner, [[false; 3], [false; 3]]), [brk(A)]);
+
+        // Fn remaps the key to a hotkey and falls through on transparent keys
+        assert!(collect(&mut scanner, [[false, false, true], [false; 3]]).is_empty());
+        assert_eq!(
+            collect(&mut scanner, [[true, true, true], [false; 3]]),
+            [make(MUTE), make(B)]
+        );
+
+        // keys keep their action when the layer is released
+        assert_eq!(collect(&mut scanner, [[true, true, false], [false; 3]]), []);
+        assert_eq!(collect(&mut scanner, [[false; 3], [false; 3]]), [brk(MUTE), brk(B)]);
+    }
+
+    #[test]
+    fn test_ghosting() {
+        let mut scanner = Scanner::new(&KEYMAP, NO_DEBOUNCE);
+
+        assert_eq!(
+            collect(&mut scanner, [[true, true, false], [false; 3]]),
+            [make(A), make(B)]
+        );
+
+        // pressing C makes D appear, both are ignored while the rectangle exists
+        assert!(collect(&mut scanner, [[true, true, false], [true, true, false]]).is_empty());
+        assert_eq!(
+            collect(&mut scanner, [[false, true, false], [true, true, false]]),
+            [brk(A), make(C), make(D)]
+        );
+
+        // without ghost detection every key is reported
+        let mut scanner = Scanner::new(
+            &KEYMAP,
+            Config {
+                ghost_detection: false,
+                ..NO_DEBOUNCE
+            },
+        );
+        assert_eq!(
+            collect(&mut scanner, [[true, true, false], [true, true, false]]),
+            [make(A), make(B), make(C), make(D)]
+        );
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut scanner = Scanner::new(
+            &KEYMAP,
+            Config {
+                rollover: 2,
+                ghost_detection: false,
+                ..NO_DEBOUNCE
+            },
+        );
+
+        assert_eq!(
+            collect(&mut scanner, [[true, true, false], [true, false, false]]),
+            [make(A), make(B)]
+        );
+
+        // releasing a key frees a slot for a held key
+        assert_eq!(
+            collect(&mut scanner, [[false, true, false], [true, false, false]]),
+            [brk(A), make(C)]
+        );
+    }
+
+    /// Emitter that accepts a single output
+    fn emit_one(outputs: &mut Vec<Output>) -> impl FnMut(Output) -> bool + '_ {
+        let mut accepted = false;
+        move |output| {
+            if accepted {
+                return false;
+            }
+            accepted = true;
+            outputs.push(output);
+            true
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut scanner = Scanner::new(&KEYMAP, NO_DEBOUNCE);
+
+        let mut outputs = Vec::new();
+        assert!(!scanner.process(&[[true, true, false], [false; 3]], emit_one(&mut outputs)));
+        assert!(scanner.resume(emit_one(&mut outputs)));
+        assert_eq!(outputs, [make(A), make(B)]);
+    }
+
+    struct SimMatrix {
+        scans: Vec<KeyMatrix<2, 3>>,
+    }
+
+    impl Matrix<2, 3> for SimMatrix {
+        type Error = ();
+
+        async fn scan(&mut self) -> Result<KeyMatrix<2, 3>, Self::Error> {
+            if self.scans.is_empty() {
+                Err(())
+            } else {
+                Ok(self.scans.remove(0))
+            }
+        }
+    }
+
+    struct Host {
+        tp: Endpoint,
+        received: std::sync::Mutex<Vec<Output>>,
+    }
+
+    impl MailboxDelegate for Host {
+        fn receive(&self, message: &comms::Message) -> Result<(), MailboxDelegateError> {
+            let message = message
+                .data
+                .get::<Message>()
+                .ok_or(MailboxDelegateError::MessageNotFound)?;
+
+            let mut received = self.received.lock().unwrap();
+            match &message.data {
+                MessageData::Event(Event::KeyEvent(_, events)) => {
+                    let events = events.borrow();
+                    let events: &[KeyEvent] = events.borrow();
+                    received.extend(events.iter().map(|event| Output::Key(*event)));
+                }
+                MessageData::Event(Event::Hotkey(_, event)) => received.push(Output::Hotkey(*event)),
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_keyboard() {
+        define_static_buffer!(events, KeyEvent, [KeyEvent::Break(Key(0)); 1]);
+        static HOST: OnceLock<Host> = OnceLock::new();
+
+        let host = HOST.get_or_init(|| Host {
+            tp: Endpoint::uninit(EndpointID::External(External::Host)),
+            received: std::sync::Mutex::new(Vec::new()),
+        });
+
+        embassy_futures::block_on(async {
+            embedded_services::init().await;
+            keyboard::enable_broadcast_host().await;
+            comms::register_endpoint(host, &host.tp).await.unwrap();
+
+            let matrix = SimMatrix {
+                scans: Vec::from([
+                    [[false, false, true], [false; 3]],
+                    [[false, false, true], [false; 3]],
+                    [[true, true, true], [true, false, false]],
+                    [[true, true, true], [true, false, false]],
+                    [[false; 3], [false; 3]],
+                    [[false; 3], [false; 3]],
+                ]),
+            };
+            let mut keyboard = Keyboard::new(
+                DeviceId(0),
+                matrix,
+                &KEYMAP,
+                Config {
+                    debounce_scans: 2,
+                    ..NO_DEBOUNCE
+                },
+                events::get_mut().unwrap(),
+            );
+
+            // key events are sent in batches of the buffer length, hotkeys after the batch they were scanned in
+            for _ in 0..6 {
+                keyboard.scan().await.unwrap();
+            }
+            assert!(matches!(keyboard.scan().await, Err(Error::Matrix(()))));
+        });
+
+        assert_eq!(
+            *host.received.lock().unwrap(),
+            [make(B), make(MUTE), make(C), brk(B), brk(MUTE), brk(C)]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Keyboard matrix scanning
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal_async::delay::DelayNs;
+
+/// Raw key states of a matrix, `true` if the key at `[row][col]` is pressed
+pub type KeyMatrix<const ROWS: usize, const COLS: usize> = [[bool; COLS]; ROWS];
+
+/// A keyboard matrix that can be scanned
+#[allow(async_fn_in_trait)]
+pub trait Matrix<const ROWS: usize, const COLS: usize> {
+    /// Type of error returned by the matrix
+    type Error;
+
+    /// Scan the matrix and return the raw key states
+    async fn scan(&mut self) -> Result<KeyMatrix<ROWS, COLS>, Self::Error>;
+}
+
+/// Pin error
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinError<I, O> {
+    /// Error reading a row pin
+    Input(I),
+    /// Error driving a column pin
+    Output(O),
+}
+
+/// Matrix scanned over GPIOs
+///
+/// Columns are driven low one at a time and rows are read back, rows must be pulled up so that a pressed key reads
+/// low.
+pub struct PinMatrix<I: InputPin, O: OutputPin, D: DelayNs, const ROWS: usize, const COLS: usize> {
+    rows: [I; ROWS],
+    cols: [O; COLS],
+    delay: D,
+    settle_us: u32,
+}
+
+impl<I: InputPin, O: OutputPin, D: DelayNs, const ROWS: usize, const COLS: usize> PinMatrix<I, O, D, ROWS, COLS> {
+    /// Create a new pin matrix, `settle_us` is the time to wait after driving a column before reading the rows
+    pub fn new(rows: [I; ROWS], cols: [O; COLS], delay: D, settle_us: u32) -> Self {
+        Self {
+            rows,
+            cols,
+            delay,
+            settle_us,
+        }
+    }
+}
+
+impl<I: InputPin, O: OutputPin, D: DelayNs, const ROWS: usize, const COLS: usize> Matrix<ROWS, COLS>
+    for PinMatrix<I, O, D, ROWS, COLS>
+{
+    type Error = PinError<I::Error, O::Error>;
+
+    async fn scan(&mut self) -> Result<KeyMatrix<ROWS, COLS>, Self::Error> {
+        let mut state = [[false; COLS]; ROWS];
+
+        for (col, pin) in self.cols.iter_mut().enumerate() {
+            pin.set_low().map_err(PinError::Output)?;
+            self.delay.delay_us(self.settle_us).await;
+
+            for (row, input) in self.rows.iter_mut().enumerate() {
+                state[row][col] = input.is_low().map_err(PinError::Input)?;
+            }
+
+            pin.set_high().map_err(PinError::Output)?;
+        }
+
+        Ok(state)
+    }
+}
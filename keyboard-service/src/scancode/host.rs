@@ -0,0 +1,256 @@
+//! Scancode byte stream for the host
+use core::borrow::Borrow;
+use core::cell::Cell;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
+use embedded_services::comms::{self, Endpoint, EndpointID, External};
+use embedded_services::keyboard::{self, Event, Key, KeyEvent, MessageData};
+use embedded_services::{trace, warn};
+
+use super::{is_make_only, translate, ScancodeSet, Typematic};
+
+/// Key being repeated and the time of its next repeat
+#[derive(Clone, Copy)]
+struct Repeat {
+    key: Key,
+    at: Instant,
+}
+
+/// Translates keyboard events broadcast to the host into a scancode byte stream
+///
+/// The eSPI or LPC 8042 emulation reads the stream with [`Host::read`]. Sequences that don't fit in the stream are
+/// dropped whole, so the host never sees a partial sequence. Only keyboard messages are accepted, other messages sent to
+/// the host endpoint are left to the receivers that accept them.
+pub struct Host<const N: usize> {
+    tp: Endpoint,
+    set: Cell<ScancodeSet>,
+    typematic: Cell<Typematic>,
+    repeat: Cell<Option<Repeat>>,
+    /// Signaled when the repeating key changes
+    repeat_changed: Signal<NoopRawMutex, ()>,
+    stream: Pipe<NoopRawMutex, N>,
+}
+
+impl<const N: usize> Host<N> {
+    /// Create a new scancode host
+    pub fn new(set: ScancodeSet, typematic: Typematic) -> Self {
+        Self {
+            tp: Endpoint::uninit(EndpointID::External(External::Host)),
+            set: Cell::new(set),
+            typematic: Cell::new(typematic),
+            repeat: Cell::new(None),
+            repeat_changed: Signal::new(),
+            stream: Pipe::new(),
+        }
+    }
+
+    /// Register the endpoint receiving keyboard events
+    pub async fn register(&'static self) -> Result<(), comms::RegistrationError> {
+        comms::register_endpoint(self, &self.tp).await
+    }
+
+    /// Current scancode set
+    pub fn scancode_set(&self) -> ScancodeSet {
+        self.set.get()
+    }
+
+    /// Select the scancode set, discards any bytes not yet read by the host
+    pub fn set_scancode_set(&self, set: ScancodeSet) {
+        self.set.set(set);
+        self.stream.clear();
+        self.stop_repeat();
+    }
+
+    /// Set the typematic repeat timing, applies from the next key press
+    pub fn set_typematic(&self, typematic: Typematic) {
+        self.typematic.set(typematic);
+    }
+
+    /// Read scancode bytes, waits until at least one byte is available
+    pub async fn read(&self, buf: &mut [u8]) -> usize {
+        self.stream.read(buf).await
+    }
+
+    /// Read scancode bytes without waiting, returns 0 if no bytes are available
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        self.stream.try_read(buf).unwrap_or(0)
+    }
+
+    /// Write the sequence for a key event to the stream
+    fn send(&self, event: KeyEvent) {
+        let Some(sequence) = translate(self.set.get(), event) else {
+            trace!("No scancode for key {:#x}", key_of(event).0);
+            return;
+        };
+
+        if sequence.len() > self.stream.free_capacity() {
+            warn!("Scancode stream full, dropping sequence");
+            return;
+        }
+
+        let _ = self.stream.try_write(&sequence);
+    }
+
+    fn stop_repeat(&self) {
+        self.repeat.set(None);
+        self.repeat_changed.signal(());
+    }
+
+    fn update_repeat(&self, event: KeyEvent) {
+        match event {
+            KeyEvent::Make(key) if !is_make_only(key) => {
+                // only the most recently pressed key repeats
+                self.repeat.set(Some(Repeat {
+                    key,
+                    at: Instant::now() + self.typematic.get().delay,
+                }));
+                self.repeat_changed.signal(());
+            }
+            KeyEvent::Break(key) if self.repeat.get().is_some_and(|repeat| repeat.key == key) => self.stop_repeat(),
+            _ => (),
+        }
+    }
+
+    /// Send the repeat of the held key if it is due at `now`, returns the time of the next repeat
+    fn send_repeat(&self, now: Instant) -> Option<Instant> {
+        let repeat = self.repeat.get()?;
+        if repeat.at > now {
+            return Some(repeat.at);
+        }
+
+        self.send(KeyEvent::Make(repeat.key));
+        let at = repeat.at + self.typematic.get().interval;
+        self.repeat.set(Some(Repeat { key: repeat.key, at }));
+        Some(at)
+    }
+
+    /// Run typematic repeat
+    pub async fn run(&self) -> ! {
+        loop {
+            let Some(repeat) = self.repeat.get() else {
+                self.repeat_changed.wait().await;
+                continue;
+            };
+
+            if let Either::First(_) = select(Timer::at(repeat.at), self.repeat_changed.wait()).await {
+                self.send_repeat(Instant::now());
+            }
+        }
+    }
+}
+
+fn key_of(event: KeyEvent) -> Key {
+    match event {
+        KeyEvent::Make(key) | KeyEvent::Break(key) => key,
+    }
+}
+
+impl<const N: usize> comms::Handler<keyboard::Message<'static>> for Host<N> {
+    fn handle(
+        &self,
+        _message: &comms::Message,
+        message: &keyboard::Message<'static>,
+    ) -> Result<(), comms::MailboxDelegateError> {
+        match &message.data {
+            MessageData::Event(Event::KeyEvent(_, events)) => {
+                let borrow = events.try_borrow().map_err(|_| comms::MailboxDelegateError::Other)?;
+                let events: &[KeyEvent] = borrow.borrow();
+
+                for event in events {
+                    self.send(*event);
+                    self.update_repeat(*event);
+                }
+            }
+            // Hotkeys are handled by the EC and have no scancode
+            MessageData::Event(Event::Hotkey(..)) => (),
+        }
+
+        Ok(())
+    }
+}
+
+embedded_services::impl_mailbox_delegate!(impl[const N: usize] for Host<N> { keyboard::Message<'static> });
+
+#[cfg(test)]
+mod test {
+    use embassy_time::Duration;
+    use embedded_services::comms::{Data, Internal, MailboxDelegate, Priority};
+    use embedded_services::define_static_buffer;
+    use embedded_services::keyboard::DeviceId;
+
+    use super::*;
+
+    const A: Key = Key(0x04);
+    const RIGHT_CTRL: Key = Key(0xe4);
+
+    fn deliver(host: &impl MailboxDelegate, events: embedded_services::buffer::SharedRef<'static, KeyEvent>) {
+        let message = keyboard::Message {
+            device_id: DeviceId(0),
+            data: MessageData::Event(Event::KeyEvent(DeviceId(0), events)),
+        };
+
+        host.receive(&comms::Message {
+            from: EndpointID::Internal(Internal::Keyboard),
+            to: EndpointID::External(External::Host),
+            priority: Priority::Normal,
+            data: Data::new(&message),
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_host() {
+        const DELAY: Duration = Duration::from_millis(20);
+        const INTERVAL: Duration = Duration::from_millis(10);
+
+        define_static_buffer!(press, KeyEvent, [KeyEvent::Make(A), KeyEvent::Break(RIGHT_CTRL)]);
+        define_static_buffer!(release, KeyEvent, [KeyEvent::Break(A)]);
+        let press = press::get_mut().unwrap();
+        let release = release::get_mut().unwrap();
+
+        let host: Host<16> = Host::new(
+            ScancodeSet::Set2,
+            Typematic {
+                delay: DELAY,
+                interval: INTERVAL,
+            },
+        );
+        let mut buf = [0u8; 16];
+
+        let before = Instant::now();
+        deliver(&host, press.reference());
+        let after = Instant::now();
+        assert_eq!(host.try_read(&mut buf), 4);
+        assert_eq!(buf[..4], [0x1c, 0xe0, 0xf0, 0x14]);
+
+        // the held key repeats once the delay elapses
+        let start = host.repeat.get().unwrap().at;
+        assert!(start >= before + DELAY && start <= after + DELAY);
+        assert_eq!(host.send_repeat(start - Duration::from_millis(1)), Some(start));
+        assert_eq!(host.try_read(&mut buf), 0);
+
+        // then every interval, catching up one repeat at a time
+        assert_eq!(host.send_repeat(start), Some(start + INTERVAL));
+        assert_eq!(host.send_repeat(start + INTERVAL * 2), Some(start + INTERVAL * 2));
+        assert_eq!(host.send_repeat(start + INTERVAL * 2), Some(start + INTERVAL * 3));
+        assert_eq!(host.send_repeat(start + INTERVAL * 2), Some(start + INTERVAL * 3));
+        assert_eq!(host.try_read(&mut buf), 3);
+        assert_eq!(buf[..3], [0x1c; 3]);
+
+        // releasing the key stops the repeat
+        deliver(&host, release.reference());
+        assert_eq!(host.send_repeat(start + INTERVAL * 10), None);
+        assert_eq!(host.try_read(&mut buf), 2);
+        assert_eq!(buf[..2], [0xf0, 0x1c]);
+
+        // switching sets discards unread bytes
+        deliver(&host, press.reference());
+        host.set_scancode_set(ScancodeSet::Set1);
+        assert_eq!(host.try_read(&mut buf), 0);
+        assert_eq!(host.send_repeat(start + INTERVAL * 10), None);
+    }
+}
@@ -0,0 +1,321 @@
+//! Scancode set 1 and set 2 translation for legacy 8042 hosts
+//!
+//! Keys are HID keyboard usages, they are translated to the make and break sequences a PS/2 keyboard would send.
+use embassy_time::Duration;
+use embedded_services::keyboard::{Key, KeyEvent};
+
+mod host;
+pub use host::*;
+
+/// Maximum length of a scancode sequence, the set 2 Pause make sequence
+pub const MAX_SEQUENCE_LEN: usize = 8;
+
+/// Scancode sequence for a single key event
+pub type Sequence = heapless::Vec<u8, MAX_SEQUENCE_LEN>;
+
+/// Prefix of extended keys
+const EXTENDED: u8 = 0xe0;
+/// Flag marking a table entry as extended
+const EXTENDED_FLAG: u16 = 0xe000;
+/// Set 1 break codes have this bit set
+const SET1_BREAK: u8 = 0x80;
+/// Set 2 break codes are prefixed with this byte
+const SET2_BREAK: u8 = 0xf0;
+
+/// HID usage of Print Screen
+const PRINT_SCREEN: u8 = 0x46;
+/// HID usage of Pause
+const PAUSE: u8 = 0x48;
+
+/// Scancode set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScancodeSet {
+    /// Set 1, also produced by 8042 translation of set 2
+    Set1,
+    /// Set 2, the default set of PS/2 keyboards
+    Set2,
+}
+
+/// Look up the set 1 and set 2 codes for a HID usage, extended codes have [`EXTENDED_FLAG`] set
+#[rustfmt::skip]
+fn lookup(usage: u8) -> Option<(u16, u16)> {
+    let codes = match usage {
+        0x04 => (0x1e, 0x1c),       // A
+        0x05 => (0x30, 0x32),       // B
+        0x06 => (0x2e, 0x21),       // C
+        0x07 => (0x20, 0x23),       // D
+        0x08 => (0x12, 0x24),       // E
+        0x09 => (0x21, 0x2b),       // F
+        0x0a => (0x22, 0x34),       // G
+        0x0b => (0x23, 0x33),       // H
+        0x0c => (0x17, 0x43),       // I
+        0x0d => (0x24, 0x3b),       // J
+        0x0e => (0x25, 0x42),       // K
+        0x0f => (0x26, 0x4b),       // L
+        0x10 => (0x32, 0x3a),       // M
+        0x11 => (0x31, 0x31),       // N
+        0x12 => (0x18, 0x44),       // O
+        0x13 => (0x19, 0x4d),       // P
+        0x14 => (0x10, 0x15),       // Q
+        0x15 => (0x13, 0x2d),       // R
+        0x16 => (0x1f, 0x1b),       // S
+        0x17 => (0x14, 0x2c),       // T
+        0x18 => (0x16, 0x3c),       // U
+        0x19 => (0x2f, 0x2a),       // V
+        0x1a => (0x11, 0x1d),       // W
+        0x1b => (0x2d, 0x22),       // X
+        0x1c => (0x15, 0x35),       // Y
+        0x1d => (0x2c, 0x1a),       // Z
+        0x1e => (0x02, 0x16),       // 1
+        0x1f => (0x03, 0x1e),       // 2
+        0x20 => (0x04, 0x26),       // 3
+        0x21 => (0x05, 0x25),       // 4
+        0x22 => (0x06, 0x2e),       // 5
+        0x23 => (0x07, 0x36),       // 6
+        0x24 => (0x08, 0x3d),       // 7
+        0x25 => (0x09, 0x3e),       // 8
+        0x26 => (0x0a, 0x46),       // 9
+        0x27 => (0x0b, 0x45),       // 0
+        0x28 => (0x1c, 0x5a),       // Enter
+        0x29 => (0x01, 0x76),       // Escape
+        0x2a => (0x0e, 0x66),       // Backspace
+        0x2b => (0x0f, 0x0d),       // Tab
+        0x2c => (0x39, 0x29),       // Space
+        0x2d => (0x0c, 0x4e),       // -
+        0x2e => (0x0d, 0x55),       // =
+        0x2f => (0x1a, 0x54),       // [
+        0x30 => (0x1b, 0x5b),       // ]
+        0x31 => (0x2b, 0x5d),       // \
+        0x32 => (0x2b, 0x5d),       // Non-US #
+        0x33 => (0x27, 0x4c),       // ;
+        0x34 => (0x28, 0x52),       // '
+        0x35 => (0x29, 0x0e),       // `
+        0x36 => (0x33, 0x41),       // ,
+        0x37 => (0x34, 0x49),       // .
+        0x38 => (0x35, 0x4a),       // /
+        0x39 => (0x3a, 0x58),       // Caps Lock
+        0x3a => (0x3b, 0x05),       // F1
+        0x3b => (0x3c, 0x06),       // F2
+        0x3c => (0x3d, 0x04),       // F3
+        0x3d => (0x3e, 0x0c),       // F4
+        0x3e => (0x3f, 0x03),       // F5
+        0x3f => (0x40, 0x0b),       // F6
+        0x40 => (0x41, 0x83),       // F7
+        0x41 => (0x42, 0x0a),       // F8
+        0x42 => (0x43, 0x01),       // F9
+        0x43 => (0x44, 0x09),       // F10
+        0x44 => (0x57, 0x78),       // F11
+        0x45 => (0x58, 0x07),       // F12
+        0x47 => (0x46, 0x7e),       // Scroll Lock
+        0x49 => (0xe052, 0xe070),   // Insert
+        0x4a => (0xe047, 0xe06c),   // Home
+        0x4b => (0xe049, 0xe07d),   // Page Up
+        0x4c => (0xe053, 0xe071),   // Delete
+        0x4d => (0xe04f, 0xe069),   // End
+        0x4e => (0xe051, 0xe07a),   // Page Down
+        0x4f => (0xe04d, 0xe074),   // Right
+        0x50 => (0xe04b, 0xe06b),   // Left
+        0x51 => (0xe050, 0xe072),   // Down
+        0x52 => (0xe048, 0xe075),   // Up
+        0x53 => (0x45, 0x77),       // Num Lock
+        0x54 => (0xe035, 0xe04a),   // Keypad /
+        0x55 => (0x37, 0x7c),       // Keypad *
+        0x56 => (0x4a, 0x7b),       // Keypad -
+        0x57 => (0x4e, 0x79),       // Keypad +
+        0x58 => (0xe01c, 0xe05a),   // Keypad Enter
+        0x59 => (0x4f, 0x69),       // Keypad 1
+        0x5a => (0x50, 0x72),       // Keypad 2
+        0x5b => (0x51, 0x7a),       // Keypad 3
+        0x5c => (0x4b, 0x6b),       // Keypad 4
+        0x5d => (0x4c, 0x73),       // Keypad 5
+        0x5e => (0x4d, 0x74),       // Keypad 6
+        0x5f => (0x47, 0x6c),       // Keypad 7
+        0x60 => (0x48, 0x75),       // Keypad 8
+        0x61 => (0x49, 0x7d),       // Keypad 9
+        0x62 => (0x52, 0x70),       // Keypad 0
+        0x63 => (0x53, 0x71),       // Keypad .
+        0x64 => (0x56, 0x61),       // Non-US \
+        0x65 => (0xe05d, 0xe02f),   // Application
+        0x66 => (0xe05e, 0xe037),   // Power
+        0x67 => (0x59, 0x0f),       // Keypad =
+        0x68 => (0x64, 0x08),       // F13
+        0x69 => (0x65, 0x10),       // F14
+        0x6a => (0x66, 0x18),       // F15
+        0x6b => (0x67, 0x20),       // F16
+        0x6c => (0x68, 0x28),       // F17
+        0x6d => (0x69, 0x30),       // F18
+        0x6e => (0x6a, 0x38),       // F19
+        0x6f => (0x6b, 0x40),       // F20
+        0x70 => (0x6c, 0x48),       // F21
+        0x71 => (0x6d, 0x50),       // F22
+        0x72 => (0x6e, 0x57),       // F23
+        0x73 => (0x76, 0x5f),       // F24
+        0x87 => (0x73, 0x51),       // International 1
+        0x88 => (0x70, 0x13),       // International 2
+        0x89 => (0x7d, 0x6a),       // International 3
+        0x8a => (0x79, 0x64),       // International 4
+        0x8b => (0x7b, 0x67),       // International 5
+        0xe0 => (0x1d, 0x14),       // Left Control
+        0xe1 => (0x2a, 0x12),       // Left Shift
+        0xe2 => (0x38, 0x11),       // Left Alt
+        0xe3 => (0xe05b, 0xe01f),   // Left GUI
+        0xe4 => (0xe01d, 0xe014),   // Right Control
+        0xe5 => (0x36, 0x59),       // Right Shift
+        0xe6 => (0xe038, 0xe011),   // Right Alt
+        0xe7 => (0xe05c, 0xe027),   // Right GUI
+        _ => return None,
+    };
+
+    Some(codes)
+}
+
+/// Returns true if the key sends a make sequence only, without break or typematic repeat
+pub fn is_make_only(key: Key) -> bool {
+    key.0 == PAUSE
+}
+
+/// Translate a key event to its scancode sequence, returns `None` for keys without a scancode
+pub fn translate(set: ScancodeSet, event: KeyEvent) -> Option<Sequence> {
+    let (key, make) = match event {
+        KeyEvent::Make(key) => (key, true),
+        KeyEvent::Break(key) => (key, false),
+    };
+
+    let bytes: &[u8] = match (key.0, set, make) {
+        (PRINT_SCREEN, ScancodeSet::Set1, true) => &[0xe0, 0x2a, 0xe0, 0x37],
+        (PRINT_SCREEN, ScancodeSet::Set1, false) => &[0xe0, 0xb7, 0xe0, 0xaa],
+        (PRINT_SCREEN, ScancodeSet::Set2, true) => &[0xe0, 0x12, 0xe0, 0x7c],
+        (PRINT_SCREEN, ScancodeSet::Set2, false) => &[0xe0, 0xf0, 0x7c, 0xe0, 0xf0, 0x12],
+        (PAUSE, ScancodeSet::Set1, true) => &[0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5],
+        (PAUSE, ScancodeSet::Set2, true) => &[0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77],
+        (PAUSE, _, false) => &[],
+        (usage, set, make) => {
+            let (set1, set2) = lookup(usage)?;
+            let code = match set {
+                ScancodeSet::Set1 => set1,
+                ScancodeSet::Set2 => set2,
+            };
+
+            let mut sequence = Sequence::new();
+            if code & EXTENDED_FLAG != 0 {
+                let _ = sequence.push(EXTENDED);
+            }
+            match (set, make) {
+                (_, true) => {
+                    let _ = sequence.push(code as u8);
+                }
+                (ScancodeSet::Set1, false) => {
+                    let _ = sequence.push(code as u8 | SET1_BREAK);
+                }
+                (ScancodeSet::Set2, false) => {
+                    let _ = sequence.extend_from_slice(&[SET2_BREAK, code as u8]);
+                }
+            }
+            return Some(sequence);
+        }
+    };
+
+    Sequence::from_slice(bytes).ok()
+}
+
+/// Typematic repeat timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Typematic {
+    /// Time a key must be held before it repeats
+    pub delay: Duration,
+    /// Time between repeats
+    pub interval: Duration,
+}
+
+impl Typematic {
+    /// Decode the parameter of the 8042 set typematic rate command (0xf3)
+    pub fn from_command(value: u8) -> Self {
+        let delay = 250 * (((value >> 5) & 0x3) as u64 + 1);
+        // period is (8 + A) * 2^B * 4.17 ms, in microseconds to keep the fraction
+        let a = (value & 0x7) as u64;
+        let b = ((value >> 3) & 0x3) as u32;
+        let interval = (8 + a) * (1 << b) * 4170;
+
+        Self {
+            delay: Duration::from_millis(delay),
+            interval: Duration::from_micros(interval),
+        }
+    }
+}
+
+/// 8042 power-on default of 500 ms delay and 10.9 characters per second
+impl Default for Typematic {
+    fn default() -> Self {
+        Self::from_command(0x2b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        const A: Key = Key(0x04);
+        const RIGHT_CTRL: Key = Key(0xe4);
+
+        assert_eq!(
+            translate(ScancodeSet::Set1, KeyEvent::Make(A)).as_deref(),
+            Some(&[0x1e][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set1, KeyEvent::Break(A)).as_deref(),
+            Some(&[0x9e][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set2, KeyEvent::Make(A)).as_deref(),
+            Some(&[0x1c][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set2, KeyEvent::Break(A)).as_deref(),
+            Some(&[0xf0, 0x1c][..])
+        );
+
+        // extended keys
+        assert_eq!(
+            translate(ScancodeSet::Set1, KeyEvent::Break(RIGHT_CTRL)).as_deref(),
+            Some(&[0xe0, 0x9d][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set2, KeyEvent::Break(RIGHT_CTRL)).as_deref(),
+            Some(&[0xe0, 0xf0, 0x14][..])
+        );
+
+        // special sequences, Pause has no break
+        assert_eq!(
+            translate(ScancodeSet::Set2, KeyEvent::Make(Key(PRINT_SCREEN))).as_deref(),
+            Some(&[0xe0, 0x12, 0xe0, 0x7c][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set1, KeyEvent::Make(Key(PAUSE))).as_deref(),
+            Some(&[0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5][..])
+        );
+        assert_eq!(
+            translate(ScancodeSet::Set2, KeyEvent::Break(Key(PAUSE))).as_deref(),
+            Some(&[][..])
+        );
+        assert!(is_make_only(Key(PAUSE)));
+
+        // reserved usages have no scancode
+        assert_eq!(translate(ScancodeSet::Set1, KeyEvent::Make(Key(0x01))).as_deref(), None);
+    }
+
+    #[test]
+    fn test_typematic() {
+        let default = Typematic::default();
+        assert_eq!(default.delay, Duration::from_millis(500));
+        assert_eq!(default.interval, Duration::from_micros(91_740));
+
+        // fastest rate with the shortest delay
+        let fastest = Typematic::from_command(0x00);
+        assert_eq!(fastest.delay, Duration::from_millis(250));
+        assert_eq!(fastest.interval, Duration::from_micros(33_360));
+    }
+}
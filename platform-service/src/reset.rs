@@ -4,12 +4,13 @@ use core::future::Future;
 
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, lazy_lock::LazyLock, signal::Signal};
 
-use embedded_services::{intrusive_list, IntrusiveList, Node, NodeContainer};
+use embedded_services::{intrusive_list, Node, NodeContainer, TypedIntrusiveList};
 
-static BLOCKERS: LazyLock<IntrusiveList> = LazyLock::new(IntrusiveList::new);
+static BLOCKERS: LazyLock<TypedIntrusiveList<Blocker>> = LazyLock::new(TypedIntrusiveList::new);
 
 pub struct Blocker {
     node: Node,
+    priority: u8,
     reset_pending: Signal<CriticalSectionRawMutex, ()>,
     unblocked: Signal<CriticalSectionRawMutex, ()>,
 }
@@ -23,8 +24,14 @@ impl NodeContainer for Blocker {
 impl Blocker {
     /// allocate a Blocker, such that it could be used in a static
     pub const fn uninit() -> Self {
+        Self::with_priority(0)
+    }
+
+    /// allocate a Blocker with a priority. Blockers are signaled and waited on in descending priority order, blockers with equal priority in registration order
+    pub const fn with_priority(priority: u8) -> Self {
         Self {
             node: Node::uninit(),
+            priority,
             reset_pending: Signal::new(),
             unblocked: Signal::new(),
         }
@@ -32,7 +39,14 @@ impl Blocker {
 
     /// call once on startup to be registered as a Reset handling blocker, forwards any error states (such as double registration) from intrusive_list
     pub async fn register(&'static self) -> intrusive_list::Result<()> {
-        BLOCKERS.get().push(self)
+        BLOCKERS
+            .get()
+            .insert_sorted_by_key(self, |blocker| core::cmp::Reverse(blocker.priority))
+    }
+
+    /// remove this Blocker from Reset handling, a reset will no longer wait on it. Forwards any error states (such as not being registered) from intrusive_list
+    pub async fn deregister(&'static self) -> intrusive_list::Result<()> {
+        BLOCKERS.get().remove(self)
     }
 
     /// waitable reset indicator, for handling resets
@@ -53,12 +67,12 @@ pub async fn system_reset() -> ! {
     let blockers = BLOCKERS.get();
 
     // 1. signal all events
-    for blocker in blockers.iter_only::<Blocker>() {
+    for blocker in blockers {
         blocker.reset_pending.signal(());
     }
 
     // 2. wait for all events
-    for blocker in blockers.iter_only::<Blocker>() {
+    for blocker in blockers {
         blocker.unblocked.wait().await;
     }
 
@@ -1,4 +1,3 @@
-use embedded_services::power::policy::charger::Device as ChargerDevice;
 use embedded_services::power::policy::charger::PolicyEvent;
 
 use super::*;
@@ -27,12 +26,12 @@ impl Ord for State {
 
 impl PowerPolicy {
     /// Iterate over all devices to determine what is now the highest-powered consumer
+    ///
+    /// Devices are iterated in ID order, so among consumers with equal capability the lowest device ID wins
     async fn find_highest_power_consumer(&self) -> Result<Option<State>, Error> {
         let mut best_consumer = None;
 
-        for node in self.context.devices().await {
-            let device = node.data::<Device>().ok_or(Error::InvalidDevice)?;
-
+        for device in self.context.devices().await {
             // Update the best available consumer
             best_consumer = match (best_consumer, device.consumer_capability().await) {
                 // Nothing available
@@ -87,8 +86,7 @@ impl PowerPolicy {
                 consumer.disconnect().await?;
             }
 
-            for node in self.context.chargers().await {
-                let device = node.data::<ChargerDevice>().ok_or(Error::InvalidDevice)?;
+            for device in self.context.chargers().await {
                 device
                     .execute_command(PolicyEvent::PolicyConfiguration(PowerCapability {
                         voltage_mv: 0,
@@ -112,8 +110,7 @@ impl PowerPolicy {
             idle.connect_consumer(new_consumer.power_capability).await?;
             state.current_consumer_state = Some(new_consumer);
             embassy_time::Timer::after_millis(800).await;
-            for node in self.context.chargers().await {
-                let device = node.data::<ChargerDevice>().ok_or(Error::InvalidDevice)?;
+            for device in self.context.chargers().await {
                 device
                     .execute_command(PolicyEvent::PolicyConfiguration(new_consumer.power_capability))
                     .await?;
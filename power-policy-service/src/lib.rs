@@ -3,7 +3,6 @@ use core::ops::DerefMut;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
-use embedded_services::power::policy::device::Device;
 use embedded_services::power::policy::{action, policy, *};
 use embedded_services::{comms, error, info};
 
@@ -83,10 +82,7 @@ impl PowerPolicy {
 
     /// Send a notification with the comms service
     async fn comms_notify(&self, message: CommsMessage) {
-        let _ = self
-            .tp
-            .send(comms::EndpointID::Internal(comms::Internal::Battery), &message)
-            .await;
+        let _ = self.tp.publish_with_priority(message.data.priority(), &message).await;
     }
 
     async fn wait_request(&self) -> policy::Request {
@@ -48,7 +48,7 @@ impl PowerPolicy {
         let mut total_power_mw = 0;
 
         // Determine total requested power draw
-        for device in self.context.devices().await.iter_only::<device::Device>() {
+        for device in self.context.devices().await {
             let target_provider_cap = if device.id() == requester_id {
                 // Use the requester's requested power capability
                 // this handles both new connections and upgrade requests
@@ -89,7 +89,7 @@ impl Service {
                 debug!("Port{}: Debug accessory disconnected", port_id.0);
             }
 
-            if self.tp.send(EndpointID::Internal(Internal::Usbc), &msg).await.is_err() {
+            if self.tp.publish(&msg).await.is_err() {
                 error!("Failed to send debug accessory message");
             }
         }